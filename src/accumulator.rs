@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+
+// results are separated by a blank line.
+const SEPARATOR: &str = "\r\n\r\n";
+
+/// The results copied so far in accumulate mode, oldest first. Once they take more than
+/// `limit` bytes, the oldest ones are dropped.
+#[derive(Debug, Default)]
+pub struct Accumulator {
+    results: VecDeque<String>,
+    limit: usize,
+    // whether the last push added a result, for `replace_last`.
+    pushed: bool,
+}
+
+impl Accumulator {
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            results: VecDeque::new(),
+            limit,
+            pushed: false,
+        }
+    }
+
+    /// Adds a result and returns everything accumulated, to be put on the clipboard.
+    pub fn push(&mut self, text: &str) -> String {
+        let text = text.trim_end_matches(['\r', '\n']);
+        self.pushed = !text.is_empty();
+        if self.pushed {
+            self.results.push_back(text.to_owned());
+        }
+        while self.len() > self.limit && self.results.len() > 1 {
+            self.results.pop_front();
+        }
+        // a single result over the limit loses its beginning.
+        let over = self.len().saturating_sub(self.limit);
+        if let (Some(only), true) = (self.results.front_mut(), over > 0) {
+            let mut cut = over.min(only.len());
+            while !only.is_char_boundary(cut) {
+                cut += 1;
+            }
+            only.drain(..cut);
+        }
+        self.text()
+    }
+
+    /// Like `push`, replacing the result pushed last, e.g. by one for the same image.
+    pub fn replace_last(&mut self, text: &str) -> String {
+        if self.pushed {
+            self.results.pop_back();
+        }
+        self.push(text)
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.pushed = false;
+    }
+
+    fn text(&self) -> String {
+        if self.results.is_empty() {
+            return String::new();
+        }
+        let mut text = Vec::from(self.results.clone()).join(SEPARATOR);
+        text.push_str("\r\n");
+        text
+    }
+
+    // the length of `text` in bytes.
+    fn len(&self) -> usize {
+        match self.results.len() {
+            0 => 0,
+            n => {
+                self.results.iter().map(String::len).sum::<usize>() + (n - 1) * SEPARATOR.len() + 2
+            }
+        }
+    }
+}
+
+#[test]
+fn push_test() {
+    let mut acc = Accumulator::new(1024);
+    assert_eq!(acc.push("first\r\n"), "first\r\n");
+    assert_eq!(
+        acc.push("second\r\nline\r\n"),
+        "first\r\n\r\nsecond\r\nline\r\n"
+    );
+    // empty results add nothing.
+    assert_eq!(acc.push("\r\n"), "first\r\n\r\nsecond\r\nline\r\n");
+
+    acc.clear();
+    assert_eq!(acc.push("third"), "third\r\n");
+}
+
+#[test]
+fn replace_last_test() {
+    let mut acc = Accumulator::new(1024);
+    acc.push("first");
+    acc.push("wrong");
+    assert_eq!(acc.replace_last("right"), "first\r\n\r\nright\r\n");
+    assert_eq!(acc.replace_last("again"), "first\r\n\r\nagain\r\n");
+
+    // an empty result replaced nothing, the one before it stays.
+    acc.push("");
+    assert_eq!(
+        acc.replace_last("more"),
+        "first\r\n\r\nagain\r\n\r\nmore\r\n"
+    );
+    assert_eq!(acc.replace_last(""), "first\r\n\r\nagain\r\n");
+
+    acc.clear();
+    assert_eq!(acc.replace_last("new"), "new\r\n");
+}
+
+#[test]
+fn limit_test() {
+    // "aaaa\r\n\r\nbbbb\r\n" is 14 bytes.
+    let mut acc = Accumulator::new(14);
+    acc.push("aaaa");
+    assert_eq!(acc.push("bbbb"), "aaaa\r\n\r\nbbbb\r\n");
+    assert_eq!(acc.push("cc"), "bbbb\r\n\r\ncc\r\n");
+    assert_eq!(acc.push("dddddddddd"), "dddddddddd\r\n");
+    assert_eq!(acc.text().len(), acc.len());
+
+    // too long on its own, cut at a char boundary.
+    assert_eq!(acc.push("0123456789abcdef"), "456789abcdef\r\n");
+    let mut acc = Accumulator::new(13);
+    assert_eq!(acc.push("日本語の文章"), "の文章\r\n");
+}
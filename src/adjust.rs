@@ -0,0 +1,184 @@
+use super::{loword, settings, HWND_MAIN_WINDOW};
+use anyhow::Result;
+use windows::{
+    core::{w, HSTRING, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+        Graphics::Gdi::{GetSysColorBrush, COLOR_MENUBAR},
+        UI::{
+            Controls::{
+                TBM_SETPOS, TBM_SETRANGEMAX, TBM_SETRANGEMIN, TBS_HORZ, TBS_NOTICKS, TB_ENDTRACK,
+                TRACKBAR_CLASSW, WC_STATICW,
+            },
+            WindowsAndMessaging::{
+                CreateWindowExW, DefWindowProcW, FindWindowW, GetDlgItem, RegisterClassW,
+                SendMessageW, SetForegroundWindow, SetWindowTextW, CW_USEDEFAULT, HMENU,
+                WINDOW_EX_STYLE, WINDOW_STYLE, WM_CREATE, WM_HSCROLL, WM_USER, WNDCLASSW,
+                WS_CAPTION, WS_CHILD, WS_EX_TOOLWINDOW, WS_OVERLAPPED, WS_SYSMENU, WS_VISIBLE,
+            },
+        },
+    },
+};
+
+const CLASS_NAME: PCWSTR = w!("ocr_adjust_class_name");
+
+const ID_GAMMA: i32 = 100;
+const ID_BRIGHTNESS: i32 = 101;
+const ID_GAMMA_LABEL: i32 = 110;
+const ID_BRIGHTNESS_LABEL: i32 = 111;
+
+// not exported by the windows crate.
+const TBM_GETPOS: u32 = WM_USER;
+
+// the gamma trackbar works in hundredths.
+const GAMMA_RANGE: (i32, i32) = (20, 300);
+const BRIGHTNESS_RANGE: (i32, i32) = (-100, 100);
+
+/// Opens the gamma/brightness window, or brings it to the front if it is already open.
+pub fn open(owner: HWND) -> Result<()> {
+    if let Ok(hwnd) = unsafe { FindWindowW(CLASS_NAME, None) } {
+        unsafe { _ = SetForegroundWindow(hwnd) };
+        return Ok(());
+    }
+
+    let wc = WNDCLASSW {
+        lpfnWndProc: Some(wnd_proc),
+        lpszClassName: CLASS_NAME,
+        hbrBackground: unsafe { GetSysColorBrush(COLOR_MENUBAR) },
+        ..Default::default()
+    };
+    // fails harmlessly when the class is already registered.
+    unsafe { RegisterClassW(&wc) };
+
+    unsafe {
+        CreateWindowExW(
+            WS_EX_TOOLWINDOW,
+            CLASS_NAME,
+            w!("Adjust image"),
+            WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            340,
+            120,
+            owner,
+            None,
+            None,
+            None,
+        )?
+    };
+    Ok(())
+}
+
+unsafe extern "system" fn wnd_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_CREATE => {
+            create(hwnd).ok();
+        }
+        WM_HSCROLL => {
+            let (gamma, brightness) = positions(hwnd);
+            update_labels(hwnd, gamma, brightness);
+            // re-run once the thumb is released rather than on every step.
+            if loword(wparam.0 as u32) as u32 == TB_ENDTRACK {
+                settings::update(|s| {
+                    s.gamma = gamma;
+                    s.brightness = brightness;
+                });
+                if let Some(main) = HWND_MAIN_WINDOW.get() {
+                    if let Err(e) = super::rescan(main.handle()) {
+                        super::report(&e);
+                    }
+                }
+            }
+        }
+        _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+    LRESULT::default()
+}
+
+fn create(hwnd: HWND) -> Result<()> {
+    let current = settings::get();
+    let gamma = (current.gamma * 100.0).round() as i32;
+    create_row(hwnd, 10, ID_GAMMA_LABEL, ID_GAMMA, GAMMA_RANGE, gamma)?;
+    create_row(
+        hwnd,
+        45,
+        ID_BRIGHTNESS_LABEL,
+        ID_BRIGHTNESS,
+        BRIGHTNESS_RANGE,
+        current.brightness,
+    )?;
+    update_labels(hwnd, current.gamma, current.brightness);
+    Ok(())
+}
+
+fn create_row(
+    hwnd: HWND,
+    y: i32,
+    label_id: i32,
+    id: i32,
+    (min, max): (i32, i32),
+    pos: i32,
+) -> Result<()> {
+    unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            WC_STATICW,
+            None,
+            WS_CHILD | WS_VISIBLE,
+            10,
+            y + 4,
+            110,
+            20,
+            hwnd,
+            HMENU(label_id as _),
+            None,
+            None,
+        )?
+    };
+    let trackbar = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            TRACKBAR_CLASSW,
+            None,
+            WINDOW_STYLE(TBS_HORZ | TBS_NOTICKS) | WS_CHILD | WS_VISIBLE,
+            120,
+            y,
+            200,
+            28,
+            hwnd,
+            HMENU(id as _),
+            None,
+            None,
+        )?
+    };
+    unsafe {
+        SendMessageW(trackbar, TBM_SETRANGEMIN, WPARAM(0), LPARAM(min as _));
+        SendMessageW(trackbar, TBM_SETRANGEMAX, WPARAM(1), LPARAM(max as _));
+        SendMessageW(trackbar, TBM_SETPOS, WPARAM(1), LPARAM(pos as _));
+    }
+    Ok(())
+}
+
+fn positions(hwnd: HWND) -> (f32, i32) {
+    let pos = |id| unsafe {
+        GetDlgItem(hwnd, id)
+            .map(|h| SendMessageW(h, TBM_GETPOS, None, None).0 as i32)
+            .unwrap_or_default()
+    };
+    (pos(ID_GAMMA) as f32 / 100.0, pos(ID_BRIGHTNESS))
+}
+
+fn update_labels(hwnd: HWND, gamma: f32, brightness: i32) {
+    let set = |id, text: String| unsafe {
+        if let Ok(label) = GetDlgItem(hwnd, id) {
+            _ = SetWindowTextW(label, &HSTRING::from(text));
+        }
+    };
+    set(ID_GAMMA_LABEL, format!("Gamma: {gamma:.2}"));
+    set(ID_BRIGHTNESS_LABEL, format!("Brightness: {brightness:+}"));
+}
@@ -0,0 +1,87 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// Executable names, e.g. `KeePass.exe, draw.io.exe`, stored comma-separated in the
+/// settings file. Names are compared ignoring ASCII case.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppList(Vec<String>);
+
+impl AppList {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.iter().any(|app| app.eq_ignore_ascii_case(name))
+    }
+}
+
+impl FromStr for AppList {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let apps = s
+            .split([',', ';'])
+            .map(str::trim)
+            .filter(|app| !app.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Ok(Self(apps))
+    }
+}
+
+impl fmt::Display for AppList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.join(","))
+    }
+}
+
+/// Whether an update from the app `owner` may be scanned. A non-empty allow list admits
+/// only the apps on it, and the deny list always keeps its apps out. An update without
+/// an owner can't be placed and is allowed.
+pub fn is_allowed(owner: Option<&str>, allow: &AppList, deny: &AppList) -> bool {
+    let Some(owner) = owner else {
+        return true;
+    };
+    (allow.is_empty() || allow.contains(owner)) && !deny.contains(owner)
+}
+
+#[test]
+fn app_list_test() {
+    let list: AppList = " KeePass.exe, draw.io.exe;;".parse().unwrap();
+    assert!(list.contains("keepass.EXE"));
+    assert!(list.contains("draw.io.exe"));
+    assert!(!list.contains("draw.io"));
+    assert_eq!(list.to_string(), "KeePass.exe,draw.io.exe");
+    assert_eq!(list.to_string().parse::<AppList>().unwrap(), list);
+
+    assert!("".parse::<AppList>().unwrap().is_empty());
+    assert!(" , ".parse::<AppList>().unwrap().is_empty());
+}
+
+#[test]
+fn is_allowed_test() {
+    let none = AppList::new();
+    let apps: AppList = "KeePass.exe".parse().unwrap();
+
+    // empty lists allow everything.
+    assert!(is_allowed(Some("mspaint.exe"), &none, &none));
+
+    assert!(!is_allowed(Some("keepass.exe"), &none, &apps));
+    assert!(is_allowed(Some("mspaint.exe"), &none, &apps));
+
+    assert!(is_allowed(Some("KEEPASS.EXE"), &apps, &none));
+    assert!(!is_allowed(Some("mspaint.exe"), &apps, &none));
+
+    // denying wins over allowing.
+    assert!(!is_allowed(Some("keepass.exe"), &apps, &apps));
+
+    // no owner to check against.
+    assert!(is_allowed(None, &apps, &none));
+    assert!(is_allowed(None, &none, &apps));
+}
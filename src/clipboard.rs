@@ -1,17 +1,46 @@
-use anyhow::{anyhow, ensure, Result};
+use super::dib::{self, check_dimensions, stride, Dib, INFO_HEADER_SIZE};
+#[cfg(test)]
+use super::dib::{dib_blob, png_dib, TINY_PNG};
+use super::{image::Image, virtual_file, wic, Hwnd, HWND_MAIN_WINDOW};
+use anyhow::{anyhow, ensure, Context, Result};
+use std::ffi::c_void;
+use std::mem;
 use std::ptr;
 use std::slice;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+use windows::core::{w, HSTRING, PCSTR, PCWSTR, PWSTR};
+use windows::Media::Ocr::OcrEngine;
 use windows::Win32::{
-    Foundation::{GlobalFree, HANDLE, HGLOBAL},
-    Graphics::Gdi::BITMAPINFO,
+    Foundation::{
+        CloseHandle, GlobalFree, SetLastError, ERROR_ACCESS_DENIED, HANDLE, HGLOBAL, HWND, RECT,
+        RECTL, WIN32_ERROR,
+    },
+    Globalization::{LocaleNameToLCID, WideCharToMultiByte, CP_ACP, LOCALE_ALLOW_NEUTRAL_NAMES},
+    Graphics::Gdi::{
+        CopyEnhMetaFileW, CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteEnhMetaFile,
+        DeleteObject, GdiFlush, GetDC, GetDIBits, GetEnhMetaFileHeader, GetObjectW,
+        PlayEnhMetaFile, ReleaseDC, SelectObject, BITMAP, BITMAPINFO, BITMAPINFOHEADER, BI_RGB,
+        DIB_RGB_COLORS, ENHMETAHEADER, HBITMAP, HDC, HENHMETAFILE, HGDIOBJ,
+    },
     System::{
         DataExchange::{
-            CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable,
-            OpenClipboard, SetClipboardData,
+            CloseClipboard, EmptyClipboard, EnumClipboardFormats, GetClipboardData,
+            GetClipboardFormatNameW, GetClipboardOwner, IsClipboardFormatAvailable, OpenClipboard,
+            RegisterClipboardFormatW, SetClipboardData,
+        },
+        Memory::{GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE},
+        Ole::{
+            CF_BITMAP, CF_DIB, CF_DIBV5, CF_ENHMETAFILE, CF_HDROP, CF_LOCALE, CF_TEXT, CF_TIFF,
+            CF_UNICODETEXT, CLIPBOARD_FORMAT,
+        },
+        Threading::{
+            OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+            PROCESS_QUERY_LIMITED_INFORMATION,
         },
-        Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
-        Ole::{CF_DIB, CF_UNICODETEXT},
     },
+    UI::WindowsAndMessaging::GetWindowThreadProcessId,
 };
 
 struct Clipboard;
@@ -21,211 +50,976 @@ impl Drop for Clipboard {
     }
 }
 
+// attempts to open the clipboard while another app keeps it open, and the pause between
+// them.
+const OPEN_ATTEMPTS: u32 = 10;
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+// formats of the text written by `set_wide`, rendered on demand.
+const TEXT_FORMATS: [u32; 2] = [CF_UNICODETEXT.0 as u32, CF_TEXT.0 as u32];
+
+// null-terminated text promised by the last `set_wide`, until it's rendered or discarded.
+static PENDING: Mutex<Option<Vec<u16>>> = Mutex::new(None);
+
+// Global memory ownership: a block from GetClipboardData belongs to the clipboard, and
+// one from STGMEDIUM to the medium; readers lock it (`GlobalLockGuard`) and never free
+// it. A block we allocate is ours (`GlobalMemory`) until SetClipboardData succeeds, after
+// which the system owns it and frees it when the clipboard is next emptied.
+
+/// A lock on a global memory block we don't own. Dropping it only unlocks.
 #[derive(Debug)]
-struct Handle(HGLOBAL);
-impl Drop for Handle {
+struct GlobalLockGuard {
+    handle: HGLOBAL,
+    ptr: *mut c_void,
+}
+
+impl GlobalLockGuard {
+    fn new(handle: HGLOBAL) -> Result<Self> {
+        let ptr = unsafe { GlobalLock(handle) };
+        ensure!(!ptr.is_null(), "failed to global lock.");
+        Ok(Self { handle, ptr })
+    }
+
+    /// The contents of the block.
+    fn bytes(&self) -> &[u8] {
+        let size = unsafe { GlobalSize(self.handle) };
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, size) }
+    }
+}
+
+impl Drop for GlobalLockGuard {
     fn drop(&mut self) {
-        unsafe { GlobalUnlock(self.0).ok() };
+        unsafe { GlobalUnlock(self.handle).ok() };
     }
 }
 
+/// A global memory block we own, freed on drop unless handed over to the clipboard.
 #[derive(Debug)]
-struct MemoryHandle(HGLOBAL);
-impl Drop for MemoryHandle {
+struct GlobalMemory(HGLOBAL);
+
+impl GlobalMemory {
+    /// Allocates a block holding a copy of `bytes`.
+    fn new(bytes: &[u8]) -> Result<Self> {
+        let memory = Self(unsafe { GlobalAlloc(GMEM_MOVEABLE, bytes.len())? });
+        let lock = GlobalLockGuard::new(memory.0)?;
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), lock.ptr as *mut u8, bytes.len()) };
+        Ok(memory)
+    }
+
+    /// Puts the block up as `format`, which passes it to the system. The clipboard must be
+    /// open.
+    fn set_clipboard_data(self, format: u32) -> Result<()> {
+        self.hand_over(|handle| unsafe { SetClipboardData(format, HANDLE(handle.0)) })?;
+        Ok(())
+    }
+
+    /// Passes the block to `take`, which owns it from then on if it succeeds. On failure
+    /// the block is still ours, and freed.
+    fn hand_over<T>(self, take: impl FnOnce(HGLOBAL) -> windows::core::Result<T>) -> Result<T> {
+        let taken = take(self.0)?;
+        self.into_raw();
+        Ok(taken)
+    }
+
+    /// Gives up ownership of the block without freeing it.
+    fn into_raw(self) -> HGLOBAL {
+        let handle = self.0;
+        mem::forget(self);
+        handle
+    }
+}
+
+impl Drop for GlobalMemory {
     fn drop(&mut self) {
         unsafe { GlobalFree(self.0).ok() };
     }
 }
 
-struct BitIterator<'a> {
-    slice: &'a [u8],
-    index: usize,
+struct DeviceContext(HDC);
+impl Drop for DeviceContext {
+    fn drop(&mut self) {
+        unsafe { ReleaseDC(None, self.0) };
+    }
+}
+
+struct MemoryDeviceContext(HDC);
+impl Drop for MemoryDeviceContext {
+    fn drop(&mut self) {
+        let _ = unsafe { DeleteDC(self.0) };
+    }
 }
 
-impl<'a> BitIterator<'a> {
-    fn new(slice: &'a [u8]) -> Self {
-        Self { slice, index: 0 }
+struct GdiObject(HGDIOBJ);
+impl Drop for GdiObject {
+    fn drop(&mut self) {
+        let _ = unsafe { DeleteObject(self.0) };
+    }
+}
+
+struct Metafile(HENHMETAFILE);
+impl Drop for Metafile {
+    fn drop(&mut self) {
+        let _ = unsafe { DeleteEnhMetaFile(self.0) };
     }
 }
 
-impl Iterator for BitIterator<'_> {
-    type Item = u8;
+struct Process(HANDLE);
+impl Drop for Process {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0).ok() };
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let byte_index = self.index / 8;
-        let bit_index = self.index % 8;
+// resolution metafiles are played at.
+const METAFILE_DPI: f32 = 150.0;
 
-        let bits = self.slice.get(byte_index)?;
-        let bit = (bits << bit_index) >> 7;
+/// A clipboard format and its contents, such as CF_HTML put up next to the text.
+#[derive(Debug, Clone)]
+pub struct Flavor {
+    pub format: u32,
+    pub data: Vec<u8>,
+}
 
-        self.index += 1;
+/// An image read from the clipboard.
+#[derive(Debug)]
+pub struct ClipboardImage {
+    pub image: Image,
+    /// The format it was read from, FileContents for virtual files.
+    pub format: u32,
+    /// The bytes of that format, to put back along with the text.
+    pub original: Option<Flavor>,
+}
 
-        Some(bit)
+/// Reads the image on the clipboard, checking and reading its formats in a single session
+/// so they can't change in between. With `keep`, also returns the bytes of the format it
+/// was read from.
+pub fn get(keep: bool) -> Result<ClipboardImage> {
+    let clip = open(None)?;
+    let formats = image_formats(&enum_formats(), png_format());
+    for (i, &format) in formats.iter().enumerate() {
+        match read_image_format(format, keep) {
+            Ok((image, original)) => {
+                return Ok(ClipboardImage {
+                    image,
+                    format,
+                    original,
+                })
+            }
+            Err(e) if i + 1 < formats.len() => {
+                log!(
+                    "failed to read {}, falling back: {e:#}",
+                    format_name(format)
+                );
+            }
+            Err(e) => return Err(e),
+        }
     }
+    drop(clip);
+
+    // Outlook puts copied attachments up as virtual files only, read through OLE.
+    ensure!(virtual_file::is_available(), "not bitmap data");
+    let file = virtual_file::read()?.context("no image among the copied files.")?;
+    let image = wic::decode(&file).context("failed to decode the copied file.")?;
+    Ok(ClipboardImage {
+        image,
+        format: virtual_file::contents_format(),
+        original: None,
+    })
+}
+
+/// Picks the image formats worth reading out of the `available` ones, best first.
+fn image_formats(available: &[u32], png: u32) -> Vec<u32> {
+    let text = available.contains(&(CF_UNICODETEXT.0 as u32));
+    [
+        // browsers put the original file up as "PNG", with correct alpha.
+        png,
+        // the V5 header carries the color masks and alpha of modern producers.
+        CF_DIBV5.0 as u32,
+        CF_DIB.0 as u32,
+        CF_TIFF.0 as u32,
+        CF_BITMAP.0 as u32,
+        CF_ENHMETAFILE.0 as u32,
+    ]
+    .into_iter()
+    .filter(|format| available.contains(format))
+    // word processors attach a metafile rendering to copied text.
+    .filter(|&format| format != CF_ENHMETAFILE.0 as u32 || !text)
+    .collect()
 }
 
-#[derive(Debug, Default)]
-struct Dib {
-    width: i32,
-    height: i32,
-    bits_per_pixel: u16,
-    data: Vec<u8>,
+/// The line break of text put on the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    CrLf,
+    Lf,
 }
 
-impl Dib {
-    fn width(&self) -> i32 {
-        self.width
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::CrLf => "\r\n",
+            Self::Lf => "\n",
+        }
     }
+}
+
+/// Puts `text` on the clipboard as CF_UNICODETEXT, along with CF_TEXT for receivers that
+/// only read ANSI text, a CF_LOCALE for `lang`, the tag of the recognition language, and
+/// the extra `flavors`. Line breaks of any kind are written as `ending`.
+///
+/// Anything after an embedded null is dropped: receivers stop reading CF_UNICODETEXT at the
+/// first null, so truncating here makes what we store match what gets pasted.
+pub fn set_text(text: &str, lang: &str, ending: LineEnding, flavors: &[Flavor]) -> Result<()> {
+    let wide: Vec<u16> = normalize_line_endings(text, ending)
+        .encode_utf16()
+        .collect();
+    set_wide(&wide, lang, flavors)
+}
 
-    fn height(&self) -> i32 {
-        self.height
+/// Rewrites every CRLF, lone CR and lone LF in `text` as `ending`.
+pub fn normalize_line_endings(text: &str, ending: LineEnding) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                normalized.push_str(ending.as_str());
+            }
+            '\n' => normalized.push_str(ending.as_str()),
+            c => normalized.push(c),
+        }
     }
+    normalized
+}
 
-    fn scan_line_bytes_count_with_padding(&self) -> usize {
-        (self.width as usize * self.bits_per_pixel as usize + 31) / 32 * 4
+/// Like `set_text` for text that is already UTF-16. `src` needs no null terminator, and
+/// unpaired surrogates are rejected.
+pub fn set_wide(src: &[u16], lang: &str, flavors: &[Flavor]) -> Result<()> {
+    let src = to_clipboard_text(src)?;
+    byte_len(src.len())?;
+    // an empty name would be the invariant locale.
+    let locale = if lang.is_empty() {
+        0
+    } else {
+        unsafe { LocaleNameToLCID(&HSTRING::from(lang), LOCALE_ALLOW_NEUTRAL_NAMES) }
+    };
+    // delayed rendering needs a window to ask for the data.
+    let owner = HWND_MAIN_WINDOW.get().map(Hwnd::handle);
+
+    let _clip = open(owner)?;
+
+    // this discards the text we promised last time, if any.
+    unsafe { EmptyClipboard()? };
+
+    if owner.is_some() {
+        *pending() = Some(src.clone());
+    }
+    for format in TEXT_FORMATS {
+        if owner.is_some() && promise(format) {
+            continue;
+        }
+        match render_format(format, &src) {
+            Err(e) if format == CF_TEXT.0 as u32 => log!("CF_TEXT left out: {e:#}"),
+            result => result?,
+        }
+    }
+    if locale != 0 {
+        set_data(CF_LOCALE.0 as u32, &locale.to_le_bytes())?;
     }
+    for flavor in flavors {
+        set_data(flavor.format, &flavor.data)?;
+    }
+    Ok(())
+}
 
-    fn to_bgra(&self) -> Result<Vec<u8>> {
-        let iter = self
-            .data
-            .chunks(self.scan_line_bytes_count_with_padding())
-            .rev();
-        let result = match self.bits_per_pixel {
-            32 => iter.flatten().cloned().collect(),
-            24 => iter
-                .flat_map(|s| {
-                    s[0..self.width as usize * 3]
-                        .chunks(3)
-                        .flat_map(|p| [p[0], p[1], p[2], 255])
-                })
-                .collect(),
-            1 => iter
-                .flat_map(|s| {
-                    BitIterator::new(s).take(self.width as usize).flat_map(|n| {
-                        if n > 0 {
-                            [255; 4]
-                        } else {
-                            [0; 4]
-                        }
-                    })
-                })
-                .collect(),
-            _ => {
-                return Err(anyhow!(
-                    "{} bits per pixel image is not yet supported.",
-                    self.bits_per_pixel
-                ));
+/// Opens the clipboard, retrying while another app keeps it open, as the app that has
+/// just notified a change often still does. Other errors fail at once.
+fn open(owner: Option<HWND>) -> Result<Clipboard> {
+    retry(OPEN_ATTEMPTS, OPEN_RETRY_INTERVAL, || unsafe {
+        OpenClipboard(owner.unwrap_or_default())
+    })
+    .context("failed to open the clipboard.")?;
+    Ok(Clipboard)
+}
+
+/// Calls `f` up to `attempts` times, `interval` apart, as long as it fails with access
+/// denied.
+fn retry<T>(
+    attempts: u32,
+    interval: Duration,
+    mut f: impl FnMut() -> windows::core::Result<T>,
+) -> windows::core::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(e) if e.code() == ERROR_ACCESS_DENIED.to_hresult() && attempt < attempts => {
+                attempt += 1;
+                thread::sleep(interval);
             }
-        };
-        Ok(result)
+            result => return result,
+        }
     }
 }
 
-pub fn get() -> Result<(i32, i32, Vec<u8>)> {
-    ensure!(is_bitmap_on_clipboard(), "not bitmap data");
-    let dib = read_bitmap_from_clipboard()?;
-    Ok((dib.width(), dib.height(), dib.to_bgra()?))
+fn pending() -> MutexGuard<'static, Option<Vec<u16>>> {
+    PENDING.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-pub fn set(src: &[u16]) -> Result<()> {
-    unsafe { OpenClipboard(None)? };
-    let _clip = Clipboard;
+/// Puts up `format` without data, to be rendered on WM_RENDERFORMAT. The clipboard must
+/// be open.
+fn promise(format: u32) -> bool {
+    unsafe { SetLastError(WIN32_ERROR(0)) };
+    // a NULL handle is returned on success too, told apart by the last error.
+    match unsafe { SetClipboardData(format, HANDLE::default()) } {
+        Ok(_) => true,
+        Err(e) => e.code().is_ok(),
+    }
+}
 
-    unsafe { EmptyClipboard()? };
+/// Writes null-terminated `text` as one of the `TEXT_FORMATS`. The clipboard must be open.
+fn render_format(format: u32, text: &[u16]) -> Result<()> {
+    if format == CF_TEXT.0 as u32 {
+        return set_data(format, &to_ansi(text)?);
+    }
+    let bytes = unsafe { slice::from_raw_parts(text.as_ptr() as *const u8, byte_len(text.len())?) };
+    set_data(format, bytes)
+}
 
-    let h_mem = unsafe { GlobalAlloc(GMEM_MOVEABLE, src.len() * 2)? };
-    ensure!(!h_mem.is_invalid(), "failed to global alloc.");
-    let h_mem = MemoryHandle(h_mem);
+/// Supplies a promised format on WM_RENDERFORMAT. The requester has the clipboard open.
+pub fn render(format: u32) -> Result<()> {
+    let text = pending().clone().context("no text to render.")?;
+    ensure!(
+        TEXT_FORMATS.contains(&format),
+        "format {format} was never promised."
+    );
+    render_format(format, &text)
+}
 
-    let dst = unsafe { GlobalLock(h_mem.0) } as *mut u8;
-    ensure!(!dst.is_null(), "failed to global lock.");
+/// Supplies every promised format while the clipboard still holds them, on
+/// WM_RENDERALLFORMATS and before exiting, so no promise outlives the window.
+pub fn render_all(owner: HWND) -> Result<()> {
+    let Some(text) = pending().take() else {
+        return Ok(());
+    };
+    let _clip = open(Some(owner))?;
 
-    unsafe {
-        ptr::copy_nonoverlapping(src.as_ptr() as *const u8, dst, src.len() * 2);
-        let _ = GlobalUnlock(h_mem.0);
-        SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(h_mem.0 .0 as _))?;
+    // another app may have emptied the clipboard since.
+    if unsafe { GetClipboardOwner() }.ok() != Some(owner) {
+        return Ok(());
+    }
+    for format in TEXT_FORMATS {
+        render_format(format, &text)?;
     }
     Ok(())
 }
 
-fn is_bitmap_on_clipboard() -> bool {
-    unsafe { IsClipboardFormatAvailable(CF_DIB.0 as u32).is_ok() }
+/// Forgets the promised text once the clipboard has been emptied, on WM_DESTROYCLIPBOARD.
+pub fn discard_pending() {
+    pending().take();
+}
+
+/// Copies `bytes` into a global memory block put up as `format`. The clipboard must be
+/// open.
+fn set_data(format: u32, bytes: &[u8]) -> Result<()> {
+    GlobalMemory::new(bytes)?.set_clipboard_data(format)
+}
+
+/// Converts null-terminated UTF-16 to the active ANSI code page. Characters without a
+/// best-fit mapping become the code page's default character, '?'.
+fn to_ansi(src: &[u16]) -> Result<Vec<u8>> {
+    let len = unsafe { WideCharToMultiByte(CP_ACP, 0, src, None, PCSTR::null(), None) };
+    ensure!(len > 0, "failed to convert the text to the ANSI code page.");
+    let mut ansi = vec![0; len as usize];
+    let len = unsafe { WideCharToMultiByte(CP_ACP, 0, src, Some(&mut ansi), PCSTR::null(), None) };
+    ensure!(len > 0, "failed to convert the text to the ANSI code page.");
+    ansi.truncate(len as usize);
+    Ok(ansi)
+}
+
+/// Truncates `src` at its first null, validates it and appends the null terminator.
+fn to_clipboard_text(src: &[u16]) -> Result<Vec<u16>> {
+    let src = src.split(|&c| c == 0).next().unwrap_or_default();
+    if let Some(Err(e)) = char::decode_utf16(src.iter().copied()).find(Result::is_err) {
+        return Err(anyhow!("invalid UTF-16 text: {e}"));
+    }
+    Ok(src.iter().copied().chain(Some(0)).collect())
+}
+
+/// Size in bytes of `len` UTF-16 code units.
+fn byte_len(len: usize) -> Result<usize> {
+    len.checked_mul(2)
+        .filter(|&size| size <= isize::MAX as usize)
+        .context("text is too large for the clipboard.")
+}
+
+/// Reads CF_UNICODETEXT, if the clipboard has any.
+pub fn get_text() -> Result<Option<String>> {
+    let _clip = open(None)?;
+
+    if unsafe { IsClipboardFormatAvailable(CF_UNICODETEXT.0 as u32) }.is_err() {
+        return Ok(None);
+    }
+    let handle = unsafe { GetClipboardData(CF_UNICODETEXT.0 as u32)? };
+    let lock = GlobalLockGuard::new(HGLOBAL(handle.0 as _))?;
+    let bytes = lock.bytes();
+
+    let text = unsafe { slice::from_raw_parts(lock.ptr as *const u16, bytes.len() / 2) };
+    let len = text.iter().position(|&c| c == 0).unwrap_or(text.len());
+    Ok(Some(String::from_utf16_lossy(&text[..len])))
 }
 
-fn read_bitmap_from_clipboard() -> Result<Dib> {
-    unsafe { OpenClipboard(None)? };
-    let _clip = Clipboard;
+/// Returns the executable name of the process owning the clipboard, if any.
+pub fn owner_name() -> Option<String> {
+    let hwnd = unsafe { GetClipboardOwner() }.ok()?;
+    let mut pid = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
 
-    let handle = unsafe { GetClipboardData(CF_DIB.0 as u32)? };
-    let handle = HGLOBAL(handle.0 as _);
-    let bitmap = unsafe { GlobalLock(handle) };
-    ensure!(!bitmap.is_null(), "failed to global lock.");
-    let _handle = Handle(handle);
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+    let process = Process(process);
 
-    let bitmap = unsafe { &mut *(bitmap as *mut BITMAPINFO) };
-    //dbg!(&bitmap);
+    let mut buf = [0u16; 260];
+    let mut len = buf.len() as u32;
+    unsafe {
+        QueryFullProcessImageNameW(
+            process.0,
+            PROCESS_NAME_WIN32,
+            PWSTR(buf.as_mut_ptr()),
+            &mut len,
+        )
+        .ok()?
+    };
+    let path = String::from_utf16_lossy(&buf[..len as usize]);
+    path.rsplit('\\').next().map(str::to_owned)
+}
 
-    let width = bitmap.bmiHeader.biWidth;
-    let height = bitmap.bmiHeader.biHeight;
-    let size = if bitmap.bmiHeader.biSizeImage == 0 {
-        (width * height * bitmap.bmiHeader.biBitCount as i32 / 8) as _
+/// Lists the formats currently on the clipboard for diagnostics, e.g. `8, 17, PNG`.
+pub fn format_names() -> String {
+    let Ok(_clip) = open(None) else {
+        return "(clipboard is busy)".to_owned();
+    };
+    let names: Vec<String> = enum_formats().into_iter().map(format_name).collect();
+    names.join(", ")
+}
+
+/// The formats on the clipboard, in the order they were put up. The clipboard must be
+/// open.
+fn enum_formats() -> Vec<u32> {
+    let mut formats = Vec::new();
+    let mut format = 0;
+    loop {
+        format = unsafe { EnumClipboardFormats(format) };
+        if format == 0 {
+            break;
+        }
+        formats.push(format);
+    }
+    formats
+}
+
+/// The registered name of `format`, or its number for the standard ones.
+pub fn format_name(format: u32) -> String {
+    let mut buf = [0u16; 128];
+    let len = unsafe { GetClipboardFormatNameW(format, &mut buf) };
+    if len > 0 {
+        String::from_utf16_lossy(&buf[..len as usize])
     } else {
-        bitmap.bmiHeader.biSizeImage as _
+        format.to_string()
+    }
+}
+
+/// The registered "PNG" format holding the encoded file bytes.
+fn png_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(w!("PNG")) }
+}
+
+/// The registered "HTML Format", CF_HTML.
+pub fn html_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(w!("HTML Format")) }
+}
+
+/// The registered "Rich Text Format".
+pub fn rtf_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(w!("Rich Text Format")) }
+}
+
+/// Flavors keeping the entry out of the clipboard history (Win+V), its cloud sync and
+/// clipboard monitors that honor the convention.
+pub fn history_exclusion() -> [Flavor; 2] {
+    let flavor = |name| Flavor {
+        format: unsafe { RegisterClipboardFormatW(name) },
+        // a DWORD 0; only the presence of the second one counts.
+        data: 0u32.to_le_bytes().to_vec(),
     };
-    //ensure!(size > 0, "no data.");
+    [
+        flavor(w!("CanIncludeInClipboardHistory")),
+        flavor(w!("ExcludeClipboardContentFromMonitorProcessing")),
+    ]
+}
 
-    let bits_per_pixel = bitmap.bmiHeader.biBitCount;
-    ensure!(bitmap.bmiHeader.biHeight > 0, "not yet supported!");
+/// CF_HDROP, the list of copied files.
+pub fn hdrop_format() -> u32 {
+    CF_HDROP.0 as u32
+}
 
-    let data = unsafe { slice::from_raw_parts(bitmap.bmiColors.as_ptr() as *mut u8, size) };
+pub fn is_bitmap_on_clipboard() -> bool {
+    unsafe { IsClipboardFormatAvailable(png_format()) }.is_ok()
+        || has_bitmap_format()
+        || virtual_file::is_available()
+}
 
-    Ok(Dib {
-        width,
-        height,
-        bits_per_pixel,
-        data: data.to_owned(),
-    })
+/// True if one of the formats `read_image_format` reads is on the clipboard.
+fn has_bitmap_format() -> bool {
+    unsafe {
+        IsClipboardFormatAvailable(CF_DIB.0 as u32).is_ok()
+            || IsClipboardFormatAvailable(CF_DIBV5.0 as u32).is_ok()
+            || IsClipboardFormatAvailable(CF_TIFF.0 as u32).is_ok()
+            || IsClipboardFormatAvailable(CF_BITMAP.0 as u32).is_ok()
+            // word processors attach a metafile rendering to copied text.
+            || (IsClipboardFormatAvailable(CF_ENHMETAFILE.0 as u32).is_ok()
+                && IsClipboardFormatAvailable(CF_UNICODETEXT.0 as u32).is_err())
+    }
 }
 
-#[test]
-fn scan_line_bytes_count_with_padding_test() {
-    let dib = Dib {
-        width: 9,
-        bits_per_pixel: 1,
+/// Copies the contents of a global memory block.
+pub fn copy_global(handle: HGLOBAL) -> Result<Vec<u8>> {
+    let lock = GlobalLockGuard::new(handle)?;
+    let bytes = lock.bytes();
+    ensure!(!bytes.is_empty(), "empty image data.");
+    Ok(bytes.to_owned())
+}
+
+/// Reads one of the `image_formats`, along with its bytes if `keep` is set and the format
+/// has any. The clipboard must be open. Images are decoded straight from the locked
+/// memory, which is only copied for `keep`.
+fn read_image_format(format: u32, keep: bool) -> Result<(Image, Option<Flavor>)> {
+    let decode: fn(&[u8]) -> Result<Image> = match CLIPBOARD_FORMAT(format as u16) {
+        CF_DIBV5 | CF_DIB => decode_dib,
+        // strips and compression are left to the WIC TIFF codec.
+        CF_TIFF => |bytes| wic::decode(bytes).context("unsupported TIFF image."),
+        // GDI handles can't be put back as bytes.
+        CF_BITMAP => return Ok((read_device_dependent_bitmap()?.to_image()?, None)),
+        CF_ENHMETAFILE => return Ok((read_enhanced_metafile()?.to_image()?, None)),
+        // the registered "PNG".
+        _ => |bytes| wic::decode(bytes).context("failed to decode PNG."),
+    };
+    let handle = unsafe { GetClipboardData(format)? };
+    let lock = GlobalLockGuard::new(HGLOBAL(handle.0 as _))?;
+    let bytes = lock.bytes();
+    ensure!(!bytes.is_empty(), "empty image data.");
+    let original = keep.then(|| Flavor {
+        format,
+        data: bytes.to_vec(),
+    });
+    Ok((decode(bytes)?, original))
+}
+
+/// Converts a packed DIB, leaving the flavors `Dib::parse` and `Dib::to_bgra` don't know
+/// to the WIC BMP decoder.
+fn decode_dib(bytes: &[u8]) -> Result<Image> {
+    if let Some(payload) = dib::encoded_payload(bytes)? {
+        // the size comes from the decoded frame, the header may disagree with it.
+        return wic::decode(payload).context("failed to decode the JPEG or PNG inside the DIB.");
+    }
+    match Dib::parse(bytes).and_then(|dib| dib.to_image()) {
+        Ok(image) => Ok(image),
+        Err(e) => {
+            log!("falling back to BitmapDecoder: {e:#}");
+            wic::decode(&dib::to_bmp_file(bytes)?).context("BitmapDecoder failed to read the DIB.")
+        }
+    }
+}
+
+/// Converts the CF_BITMAP of producers that offer no DIB into a top-down 32 bpp one.
+/// The clipboard must be open.
+fn read_device_dependent_bitmap() -> Result<Dib<'static>> {
+    let handle = unsafe { GetClipboardData(CF_BITMAP.0 as u32)? };
+    let hbitmap = HBITMAP(handle.0);
+
+    let mut bitmap = BITMAP::default();
+    let len = unsafe {
+        GetObjectW(
+            hbitmap,
+            size_of::<BITMAP>() as i32,
+            Some(&mut bitmap as *mut _ as _),
+        )
+    };
+    ensure!(len != 0, "failed to get the bitmap.");
+    let (width, height) = (bitmap.bmWidth, bitmap.bmHeight);
+    check_dimensions(width, height)?;
+
+    let hdc = unsafe { GetDC(None) };
+    ensure!(!hdc.is_invalid(), "failed to get the screen dc.");
+    let hdc = DeviceContext(hdc);
+
+    let mut info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: INFO_HEADER_SIZE as u32,
+            biWidth: width,
+            biHeight: -height,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
         ..Default::default()
     };
-    assert_eq!(dib.scan_line_bytes_count_with_padding(), 4);
+    let mut data = vec![0u8; stride(width, 32) * height as usize];
+    let lines = unsafe {
+        GetDIBits(
+            hdc.0,
+            hbitmap,
+            0,
+            height as u32,
+            Some(data.as_mut_ptr() as _),
+            &mut info,
+            DIB_RGB_COLORS,
+        )
+    };
+    ensure!(lines == height, "failed to get the bitmap bits.");
+
+    // the fourth byte of device-dependent pixels is undefined.
+    data.chunks_mut(4).for_each(|p| p[3] = 255);
+
+    Ok(Dib::bgra(width, height, data))
+}
+
+/// Plays the CF_ENHMETAFILE of producers that offer no bitmap onto white, at
+/// `METAFILE_DPI`. The clipboard must be open.
+fn read_enhanced_metafile() -> Result<Dib<'static>> {
+    let handle = unsafe { GetClipboardData(CF_ENHMETAFILE.0 as u32)? };
+    // a private copy, the clipboard keeps owning its handle.
+    let metafile = unsafe { CopyEnhMetaFileW(HENHMETAFILE(handle.0), PCWSTR::null()) };
+    ensure!(!metafile.is_invalid(), "failed to copy the metafile.");
+    let metafile = Metafile(metafile);
+
+    let mut header = ENHMETAHEADER::default();
+    let len = unsafe {
+        GetEnhMetaFileHeader(
+            metafile.0,
+            size_of::<ENHMETAHEADER>() as u32,
+            Some(&mut header),
+        )
+    };
+    ensure!(len != 0, "failed to read the metafile header.");
+    let max = OcrEngine::MaxImageDimension()?;
+    let (width, height) = metafile_size(&header.rclFrame, max);
+    ensure!(width > 0 && height > 0, "empty metafile.");
 
-    let dib = Dib {
-        width: 53,
-        bits_per_pixel: 24,
+    let info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: INFO_HEADER_SIZE as u32,
+            biWidth: width,
+            biHeight: -height,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
         ..Default::default()
     };
-    assert_eq!(dib.scan_line_bytes_count_with_padding(), 160);
+    let mut bits = ptr::null_mut();
+    let section = unsafe { CreateDIBSection(None, &info, DIB_RGB_COLORS, &mut bits, None, 0)? };
+    let section = GdiObject(section.into());
+    // dropped before the section, which can't be deleted while selected.
+    let hdc = unsafe { CreateCompatibleDC(None) };
+    ensure!(!hdc.is_invalid(), "failed to create a memory dc.");
+    let hdc = MemoryDeviceContext(hdc);
+
+    let len = width as usize * height as usize * 4;
+    let pixels = unsafe { slice::from_raw_parts_mut(bits as *mut u8, len) };
+    // metafiles are often transparent.
+    pixels.fill(255);
+    unsafe {
+        SelectObject(hdc.0, section.0);
+        let rect = RECT {
+            right: width,
+            bottom: height,
+            ..Default::default()
+        };
+        PlayEnhMetaFile(hdc.0, metafile.0, &rect).ok()?;
+        GdiFlush().ok()?;
+    }
+    // GDI leaves the fourth byte alone or zeroes it.
+    let mut data = pixels.to_owned();
+    data.chunks_mut(4).for_each(|p| p[3] = 255);
+
+    Ok(Dib::bgra(width, height, data))
+}
 
-    let dib = Dib {
-        width: 53,
-        bits_per_pixel: 32,
+/// Pixel size of a metafile frame given in .01 mm, scaled down to fit `max` on both sides.
+fn metafile_size(frame: &RECTL, max: u32) -> (i32, i32) {
+    let px = |hundredths_mm: i32| hundredths_mm.max(0) as f32 / 2540.0 * METAFILE_DPI;
+    let (width, height) = (px(frame.right - frame.left), px(frame.bottom - frame.top));
+    let scale = (max as f32 / width.max(height)).min(1.0);
+    (
+        (width * scale).round() as i32,
+        (height * scale).round() as i32,
+    )
+}
+
+#[test]
+fn image_formats_test() {
+    const PNG: u32 = 0xc0de;
+    const HTML: u32 = 0xc0df;
+    let (dib, dibv5, bitmap, emf) = (8, 17, 2, 14);
+    let text = CF_UNICODETEXT.0 as u32;
+
+    // a screenshot tool: the bitmap and what Windows synthesizes from it.
+    assert_eq!(
+        image_formats(&[bitmap, dib, dibv5], PNG),
+        [dibv5, dib, bitmap]
+    );
+    // a browser.
+    assert_eq!(
+        image_formats(&[HTML, PNG, dib, text, bitmap, dibv5], PNG),
+        [PNG, dibv5, dib, bitmap]
+    );
+    // a word processor's rendering of copied text is not an image.
+    assert_eq!(image_formats(&[text, emf], PNG), []);
+    assert_eq!(image_formats(&[emf], PNG), [emf]);
+    assert_eq!(image_formats(&[text, HTML], PNG), []);
+}
+
+#[test]
+fn global_memory_test() {
+    // the low byte of the flags, GMEM_LOCKCOUNT.
+    let lock_count = |handle| unsafe { windows::Win32::System::Memory::GlobalFlags(handle) } & 0xff;
+
+    let memory = GlobalMemory::new(b"abc").unwrap();
+    assert_eq!(lock_count(memory.0), 0);
+    {
+        let lock = GlobalLockGuard::new(memory.0).unwrap();
+        assert_eq!(lock_count(memory.0), 1);
+        assert_eq!(&lock.bytes()[..3], b"abc");
+    }
+    // the guard unlocks, and leaves the block alone.
+    assert_eq!(lock_count(memory.0), 0);
+    assert!(unsafe { GlobalSize(memory.0) } >= 3);
+
+    // taken over: the block outlives the hand-over, for the new owner to free.
+    let handle = memory.hand_over(Ok).unwrap();
+    assert!(unsafe { GlobalSize(handle) } >= 3);
+    unsafe { GlobalFree(handle).ok() };
+
+    // refused: the error comes back, and the block is freed with the `GlobalMemory`.
+    let memory = GlobalMemory::new(b"abc").unwrap();
+    let result = memory.hand_over(|_| -> windows::core::Result<()> {
+        Err(windows::core::Error::from_hresult(
+            windows::Win32::Foundation::E_FAIL,
+        ))
+    });
+    assert!(result.is_err());
+}
+
+#[test]
+fn parse_dib_short_block_test() {
+    // a 4x4 24 bpp header in a block with room for one row.
+    let header = BITMAPINFOHEADER {
+        biSize: INFO_HEADER_SIZE as u32,
+        biWidth: 4,
+        biHeight: 4,
+        biPlanes: 1,
+        biBitCount: 24,
         ..Default::default()
     };
-    assert_eq!(dib.scan_line_bytes_count_with_padding(), 212);
+    let memory = GlobalMemory::new(&dib_blob(header, &[], &[0; 12])).unwrap();
+    let bytes = copy_global(memory.0).unwrap();
+    assert_eq!(
+        Dib::parse(&bytes).unwrap_err().to_string(),
+        "48 bytes of pixels at 40 exceed the 52 byte bitmap."
+    );
+
+    // biSizeImage owning up to the short block doesn't make the rows fit.
+    let header = BITMAPINFOHEADER {
+        biSizeImage: 12,
+        ..header
+    };
+    let memory = GlobalMemory::new(&dib_blob(header, &[], &[0; 12])).unwrap();
+    let bytes = copy_global(memory.0).unwrap();
+    assert_eq!(
+        Dib::parse(&bytes).unwrap_err().to_string(),
+        "4x4 pixels at 24 bpp take 48 bytes, the bitmap has 12."
+    );
+}
+
+#[test]
+fn retry_test() {
+    let denied = || windows::core::Error::from_hresult(ERROR_ACCESS_DENIED.to_hresult());
+    let mut calls = 0;
+    let result = retry(3, Duration::ZERO, || {
+        calls += 1;
+        if calls < 3 {
+            Err(denied())
+        } else {
+            Ok(calls)
+        }
+    });
+    assert_eq!(result.unwrap(), 3);
+
+    // gives up once the attempts run out.
+    let mut calls = 0;
+    let result: windows::core::Result<()> = retry(3, Duration::ZERO, || {
+        calls += 1;
+        Err(denied())
+    });
+    assert_eq!(result.unwrap_err().code(), ERROR_ACCESS_DENIED.to_hresult());
+    assert_eq!(calls, 3);
+
+    // other errors fail at once.
+    let mut calls = 0;
+    let result: windows::core::Result<()> = retry(3, Duration::ZERO, || {
+        calls += 1;
+        Err(windows::core::Error::from_hresult(
+            windows::Win32::Foundation::E_FAIL,
+        ))
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn metafile_size_test() {
+    let frame = |right, bottom| RECTL {
+        left: 0,
+        top: 0,
+        right,
+        bottom,
+    };
+    // one by half an inch.
+    assert_eq!(metafile_size(&frame(2540, 1270), 10000), (150, 75));
+    assert_eq!(metafile_size(&frame(2540, 1270), 100), (100, 50));
+    assert_eq!(metafile_size(&frame(2540 * 100, 2540), 10000), (10000, 100));
+    assert_eq!(metafile_size(&frame(-5, 1270), 10000), (0, 75));
+}
+
+/// The decoded frame decides the size, whatever the header claims.
+#[test]
+#[ignore = "needs the Windows imaging codecs"]
+fn decode_dib_png_test() {
+    let image = decode_dib(&png_dib(7, 9, 0, TINY_PNG.len())).unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(image.bgra, [0, 0, 255, 255, 255, 255, 255, 255]);
+
+    let mut broken = png_dib(2, 1, 0, TINY_PNG.len());
+    let len = broken.len();
+    broken[len - 20..].fill(0);
+    assert!(decode_dib(&broken).is_err());
+}
+
+/// Feeds a 2 bpp DIB, which only the WIC decoder understands, through recognition.
+#[test]
+#[ignore = "needs the Windows OCR engine and the en-US language pack"]
+fn decode_dib_fallback_test() {
+    use super::ocr::{self, Recognizer};
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/clean.bmp");
+    let clean = wic::decode(&std::fs::read(path).unwrap()).unwrap();
+    let (width, height) = (clean.width, clean.height);
+
+    // black text on white, four gray levels, rows bottom-up.
+    let stride = stride(width, 2);
+    let mut pixels = vec![0u8; stride * height as usize];
+    for (y, row) in clean.bgra.chunks(width as usize * 4).rev().enumerate() {
+        for (x, p) in row.chunks(4).enumerate() {
+            let level = p[1] >> 6;
+            pixels[y * stride + x / 4] |= level << (6 - x % 4 * 2);
+        }
+    }
+    let dib = dib_blob(
+        BITMAPINFOHEADER {
+            biSize: INFO_HEADER_SIZE as u32,
+            biWidth: width,
+            biHeight: height,
+            biPlanes: 1,
+            biBitCount: 2,
+            ..Default::default()
+        },
+        &[
+            [0, 0, 0, 0],
+            [85, 85, 85, 0],
+            [170, 170, 170, 0],
+            [255, 255, 255, 0],
+        ]
+        .concat(),
+        &pixels,
+    );
+    assert!(Dib::parse(&dib).unwrap().to_bgra().is_err());
+
+    let image = decode_dib(&dib).unwrap();
+    let engine = ocr::WinRt { timeout: None };
+    let outcome = engine
+        .recognize(
+            image.width,
+            image.height,
+            &mut |dst| dst.copy_from_slice(&image.bgra),
+            "en-US",
+        )
+        .unwrap();
+    let recognition = ocr::Recognition::new(outcome, image.width, image.height);
+    assert!(recognition
+        .to_text(&Default::default())
+        .contains("quick brown fox"));
+}
+
+#[test]
+fn normalize_line_endings_test() {
+    use LineEnding::{CrLf, Lf};
+
+    assert_eq!(normalize_line_endings("a\r\nb\r\n", Lf), "a\nb\n");
+    assert_eq!(normalize_line_endings("a\r\nb\r\n", CrLf), "a\r\nb\r\n");
+    // RichEdit keeps bare CRs, pasted text may bring bare LFs.
+    assert_eq!(normalize_line_endings("a\rb\nc\r\n", Lf), "a\nb\nc\n");
+    assert_eq!(
+        normalize_line_endings("a\rb\nc\r\n", CrLf),
+        "a\r\nb\r\nc\r\n"
+    );
+    assert_eq!(normalize_line_endings("\n\r\r\n\n", Lf), "\n\n\n\n");
+    assert_eq!(normalize_line_endings("", Lf), "");
+    assert_eq!(normalize_line_endings("日本\r\n", Lf), "日本\n");
+}
+
+#[test]
+fn to_clipboard_text_test() {
+    let wide = |s: &str| s.encode_utf16().collect::<Vec<_>>();
+
+    assert_eq!(to_clipboard_text(&[]).unwrap(), [0]);
+    assert_eq!(
+        to_clipboard_text(&wide("abc")).unwrap(),
+        [0x61, 0x62, 0x63, 0]
+    );
+    assert_eq!(
+        to_clipboard_text(&wide("abc\0def")).unwrap(),
+        [0x61, 0x62, 0x63, 0]
+    );
+    assert_eq!(to_clipboard_text(&wide("\0abc")).unwrap(), [0]);
+
+    let cjk = "日本語のテキスト";
+    assert_eq!(
+        to_clipboard_text(&wide(cjk)).unwrap(),
+        wide(&format!("{cjk}\0"))
+    );
+
+    // surrogate pairs survive, lone surrogates are rejected.
+    let emoji = "😀 ok";
+    assert_eq!(
+        to_clipboard_text(&wide(emoji)).unwrap(),
+        wide(&format!("{emoji}\0"))
+    );
+    assert!(to_clipboard_text(&[0xd83d, 0x61]).is_err());
+    assert!(to_clipboard_text(&[0x61, 0xde00]).is_err());
+
+    let large = "あ".repeat(1 << 20);
+    assert_eq!(
+        to_clipboard_text(&wide(&large)).unwrap().len(),
+        (1 << 20) + 1
+    );
 }
 
 #[test]
-fn bit_iterator_test() {
-    let s: [u8; 4] = [0b1001_1110, 0b1100_1100, 0, 0];
-    let mut iter = BitIterator::new(&s).take(10);
-    assert_eq!(iter.next(), Some(1));
-    assert_eq!(iter.next(), Some(0));
-    assert_eq!(iter.next(), Some(0));
-    assert_eq!(iter.next(), Some(1));
-
-    assert_eq!(iter.next(), Some(1));
-    assert_eq!(iter.next(), Some(1));
-    assert_eq!(iter.next(), Some(1));
-    assert_eq!(iter.next(), Some(0));
-
-    assert_eq!(iter.next(), Some(1));
-    assert_eq!(iter.next(), Some(1));
-    assert_eq!(iter.next(), None);
+fn byte_len_test() {
+    assert_eq!(byte_len(0).unwrap(), 0);
+    assert_eq!(byte_len(4096).unwrap(), 8192);
+    assert!(byte_len(usize::MAX / 2 + 1).is_err());
+    assert!(byte_len(isize::MAX as usize).is_err());
 }
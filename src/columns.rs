@@ -0,0 +1,159 @@
+//! Reading order for multi-column layouts. The engine returns lines top to bottom across
+//! the whole image, interleaving the columns of e.g. a two-column PDF. A column is told
+//! by a gutter, a strip of the image that runs between lines all the way down; lines
+//! across it, like a heading, split the columns into sections read one after another.
+
+use super::paragraph::{self, LineBox};
+
+// a gutter is at least this many line heights wide.
+const GUTTER: f32 = 1.0;
+// lines this many times wider than the usual one, like a heading, may cross gutters.
+const WIDE: f32 = 1.5;
+// of the other lines, at most one in this many may cross a gutter.
+const CROSSING_SHARE: usize = 10;
+// the fewest lines to make a column on either side of a gutter.
+const MIN_COLUMN_LINES: usize = 2;
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// The gutters between the columns of `lines`, left to right, as `(left, right)`.
+fn gutters(lines: &[LineBox]) -> Vec<(f32, f32)> {
+    let (Some(height), Some(width)) = (
+        median(lines.iter().map(|b| b.bottom - b.top).collect()),
+        median(lines.iter().map(|b| b.right - b.left).collect()),
+    ) else {
+        return Vec::new();
+    };
+    let narrow: Vec<&LineBox> = lines
+        .iter()
+        .filter(|b| b.right - b.left <= width * WIDE)
+        .collect();
+    let crossing = narrow.len() / CROSSING_SHARE;
+
+    let spans: Vec<(f32, f32)> = narrow.iter().map(|b| (b.left, b.right)).collect();
+    let mut runs = paragraph::open_runs(&spans, crossing);
+    runs.retain(|&(left, right)| {
+        let before = narrow.iter().filter(|b| b.right <= left).count();
+        let after = narrow.iter().filter(|b| b.left >= right).count();
+        right - left >= height * GUTTER && before >= MIN_COLUMN_LINES && after >= MIN_COLUMN_LINES
+    });
+    runs
+}
+
+/// The indices of `lines` in reading order, in blocks: the lines of a column within a
+/// section, or lines across the columns. Without a clear gutter, a single block of
+/// `lines` in the order given.
+pub fn blocks(lines: &[LineBox]) -> Vec<Vec<usize>> {
+    let gutters = gutters(lines);
+    if gutters.is_empty() {
+        return vec![(0..lines.len()).collect()];
+    }
+    let mut blocks = Vec::new();
+    let mut columns = vec![Vec::new(); gutters.len() + 1];
+    let mut across = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let crosses = gutters.iter().any(|&(left, right)| {
+            let middle = (left + right) / 2.0;
+            line.left < middle && middle < line.right
+        });
+        if crosses {
+            blocks.extend(columns.iter_mut().map(std::mem::take));
+            across.push(i);
+        } else {
+            if !across.is_empty() {
+                blocks.push(std::mem::take(&mut across));
+            }
+            let center = (line.left + line.right) / 2.0;
+            let column = gutters
+                .iter()
+                .filter(|&&(left, right)| (left + right) / 2.0 <= center)
+                .count();
+            columns[column].push(i);
+        }
+    }
+    blocks.extend(columns);
+    blocks.push(across);
+    blocks.retain(|block| !block.is_empty());
+    blocks
+}
+
+#[cfg(test)]
+fn boxes(lines: &[(f32, f32, f32)]) -> Vec<LineBox> {
+    // lines 10 high, by left, top and right.
+    lines
+        .iter()
+        .map(|&(left, top, right)| LineBox {
+            left,
+            top,
+            right,
+            bottom: top + 10.0,
+        })
+        .collect()
+}
+
+#[test]
+fn single_column_test() {
+    // ragged lines, an indented one and a long one are no gutter.
+    let lines = boxes(&[
+        (0.0, 0.0, 200.0),
+        (20.0, 14.0, 180.0),
+        (0.0, 28.0, 120.0),
+        (0.0, 42.0, 60.0),
+        (0.0, 56.0, 60.0),
+    ]);
+    assert_eq!(blocks(&lines), [[0, 1, 2, 3, 4]]);
+    assert_eq!(blocks(&[]), [[]; 1]);
+
+    // too narrow a gap for a gutter.
+    let lines = boxes(&[
+        (0.0, 0.0, 100.0),
+        (105.0, 0.0, 200.0),
+        (0.0, 14.0, 100.0),
+        (105.0, 14.0, 200.0),
+    ]);
+    assert_eq!(blocks(&lines), [[0, 1, 2, 3]]);
+}
+
+#[test]
+fn two_columns_test() {
+    // interleaved as the engine returns them, under a heading across both.
+    let lines = boxes(&[
+        (0.0, 0.0, 300.0),
+        (0.0, 20.0, 140.0),
+        (160.0, 20.0, 300.0),
+        (0.0, 34.0, 135.0),
+        (160.0, 34.0, 290.0),
+        (0.0, 48.0, 80.0),
+        (160.0, 48.0, 300.0),
+        (160.0, 62.0, 220.0),
+    ]);
+    assert_eq!(blocks(&lines), [vec![0], vec![1, 3, 5], vec![2, 4, 6, 7]]);
+    assert_eq!(gutters(&lines), [(140.0, 160.0)]);
+
+    // a footer across both ends the section.
+    let mut lines = lines;
+    lines.extend(boxes(&[(0.0, 90.0, 300.0)]));
+    assert_eq!(
+        blocks(&lines),
+        [vec![0], vec![1, 3, 5], vec![2, 4, 6, 7], vec![8]]
+    );
+}
+
+#[test]
+fn three_columns_test() {
+    let lines = boxes(&[
+        (0.0, 0.0, 90.0),
+        (110.0, 0.0, 190.0),
+        (210.0, 0.0, 300.0),
+        (0.0, 14.0, 85.0),
+        (110.0, 14.0, 200.0),
+        (210.0, 14.0, 290.0),
+        (0.0, 28.0, 90.0),
+        (210.0, 28.0, 250.0),
+    ]);
+    assert_eq!(gutters(&lines).len(), 2);
+    assert_eq!(blocks(&lines), [vec![0, 3, 6], vec![1, 4], vec![2, 5, 7]]);
+}
@@ -0,0 +1,240 @@
+//! The Crop then OCR window: shows the clipboard image, lets a rectangle be dragged over
+//! it and recognizes just that part. Escape recognizes the whole image instead. If the
+//! image was just recognized, the title previews the words under the rectangle.
+
+use super::image::{Image, Region};
+use super::ocr::Recognition;
+use super::{hiword, loword, report, HWND_MAIN_WINDOW};
+use anyhow::Result;
+use std::mem::size_of;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use windows::{
+    core::{w, HSTRING, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Graphics::Gdi::{
+            BeginPaint, DrawFocusRect, EndPaint, InvalidateRect, SetStretchBltMode, StretchDIBits,
+            BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HALFTONE, PAINTSTRUCT, SRCCOPY,
+        },
+        UI::{
+            Input::KeyboardAndMouse::{GetCapture, ReleaseCapture, SetCapture, VK_ESCAPE},
+            WindowsAndMessaging::{
+                AdjustWindowRectEx, CreateWindowExW, DefWindowProcW, DestroyWindow, FindWindowW,
+                LoadCursorW, RegisterClassW, SetWindowTextW, CW_USEDEFAULT, IDC_CROSS, WM_DESTROY,
+                WM_KEYDOWN, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WM_PAINT, WNDCLASSW,
+                WS_CAPTION, WS_EX_TOOLWINDOW, WS_OVERLAPPED, WS_SYSMENU, WS_VISIBLE,
+            },
+        },
+    },
+};
+
+const CLASS_NAME: PCWSTR = w!("ocr_crop_class_name");
+const TITLE: PCWSTR = w!("Drag over the text to recognize, Esc for all of it");
+
+// larger images are shown scaled down to this many pixels on their longest side.
+const MAX_VIEW: i32 = 1000;
+
+/// The image being cropped, the scale it is shown at, and the rectangle dragged so far
+/// in window coordinates. `recognition` is that of the image, if it was recognized last.
+struct Crop {
+    image: Image,
+    scale: f32,
+    drag: Option<((i32, i32), (i32, i32))>,
+    recognition: Option<Recognition>,
+}
+
+impl Crop {
+    /// The dragged rectangle in image pixels, None if it covers none.
+    fn region(&self) -> Option<Region> {
+        let (start, end) = self.drag?;
+        let unscale = |(x, y): (i32, i32)| {
+            let unscale = |v: i32| (v as f32 / self.scale).round() as i32;
+            (unscale(x), unscale(y))
+        };
+        Region::between(unscale(start), unscale(end)).clamp(self.image.width, self.image.height)
+    }
+
+    /// The recognized words under the dragged rectangle.
+    fn preview(&self) -> Option<String> {
+        let recognition = self.recognition.as_ref()?;
+        let region = self.region()?;
+        let words: Vec<_> = recognition
+            .words_in(region)
+            .map(|word| word.text.as_str())
+            .collect();
+        (!words.is_empty()).then(|| words.join(" "))
+    }
+}
+
+static CROP: Mutex<Option<Crop>> = Mutex::new(None);
+
+/// Opens the crop window on `image`, replacing one already open. `recognition` is the
+/// last one of the image, if any.
+pub fn open(owner: HWND, image: Image, recognition: Option<Recognition>) -> Result<()> {
+    if let Ok(hwnd) = unsafe { FindWindowW(CLASS_NAME, None) } {
+        unsafe { _ = DestroyWindow(hwnd) };
+    }
+
+    let scale = (MAX_VIEW as f32 / image.width.max(image.height) as f32).min(1.0);
+    let mut rect = RECT {
+        right: view_size(image.width, scale),
+        bottom: view_size(image.height, scale),
+        ..Default::default()
+    };
+    *crop() = Some(Crop {
+        image,
+        scale,
+        drag: None,
+        recognition,
+    });
+
+    let wc = WNDCLASSW {
+        lpfnWndProc: Some(wnd_proc),
+        lpszClassName: CLASS_NAME,
+        hCursor: unsafe { LoadCursorW(None, IDC_CROSS)? },
+        ..Default::default()
+    };
+    // fails harmlessly when the class is already registered.
+    unsafe { RegisterClassW(&wc) };
+
+    let style = WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_VISIBLE;
+    unsafe { AdjustWindowRectEx(&mut rect, style, false, WS_EX_TOOLWINDOW)? };
+    unsafe {
+        CreateWindowExW(
+            WS_EX_TOOLWINDOW,
+            CLASS_NAME,
+            TITLE,
+            style,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            rect.right - rect.left,
+            rect.bottom - rect.top,
+            owner,
+            None,
+            None,
+            None,
+        )?
+    };
+    Ok(())
+}
+
+fn crop() -> MutexGuard<'static, Option<Crop>> {
+    CROP.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn view_size(side: i32, scale: f32) -> i32 {
+    ((side as f32 * scale).round() as i32).max(1)
+}
+
+unsafe extern "system" fn wnd_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    // client coordinates, negative past the left or top edge while dragging.
+    let point = || {
+        let dword = lparam.0 as u32;
+        (loword(dword) as i16 as i32, hiword(dword) as i16 as i32)
+    };
+    match msg {
+        WM_PAINT => paint(hwnd),
+        WM_LBUTTONDOWN => {
+            SetCapture(hwnd);
+            set_drag(hwnd, Some((point(), point())));
+        }
+        WM_MOUSEMOVE if GetCapture() == hwnd => {
+            let start = crop().as_ref().and_then(|c| c.drag).map(|(start, _)| start);
+            set_drag(hwnd, start.map(|start| (start, point())));
+            let preview = crop().as_ref().and_then(Crop::preview);
+            _ = match preview {
+                Some(words) => SetWindowTextW(hwnd, &HSTRING::from(words)),
+                None => SetWindowTextW(hwnd, TITLE),
+            };
+        }
+        WM_LBUTTONUP if GetCapture() == hwnd => {
+            _ = ReleaseCapture();
+            let region = crop().as_ref().and_then(Crop::region);
+            match region {
+                Some(region) => finish(hwnd, Some(region)),
+                // a click without a drag, start over.
+                None => set_drag(hwnd, None),
+            }
+        }
+        WM_KEYDOWN if wparam.0 == VK_ESCAPE.0 as usize => finish(hwnd, None),
+        WM_DESTROY => *crop() = None,
+        _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+    LRESULT::default()
+}
+
+fn set_drag(hwnd: HWND, drag: Option<((i32, i32), (i32, i32))>) {
+    if let Some(crop) = crop().as_mut() {
+        crop.drag = drag;
+    }
+    unsafe { _ = InvalidateRect(hwnd, None, false) };
+}
+
+/// Closes the window and recognizes `region` of the image, all of it if None.
+fn finish(hwnd: HWND, region: Option<Region>) {
+    let Some(crop) = crop().take() else {
+        return;
+    };
+    unsafe { _ = DestroyWindow(hwnd) };
+    let Some(main) = HWND_MAIN_WINDOW.get() else {
+        return;
+    };
+    if let Err(e) = super::scan_cropped(main.handle(), crop.image, region) {
+        report(&e);
+    }
+}
+
+/// Draws the image scaled to the window, and the rectangle being dragged over it.
+fn paint(hwnd: HWND) {
+    let mut ps = PAINTSTRUCT::default();
+    let hdc = unsafe { BeginPaint(hwnd, &mut ps) };
+    if let Some(crop) = crop().as_ref() {
+        let Image { width, height, .. } = crop.image;
+        let info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width,
+                // top-down rows.
+                biHeight: -height,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        unsafe {
+            SetStretchBltMode(hdc, HALFTONE);
+            StretchDIBits(
+                hdc,
+                0,
+                0,
+                view_size(width, crop.scale),
+                view_size(height, crop.scale),
+                0,
+                0,
+                width,
+                height,
+                Some(crop.image.bgra.as_ptr().cast()),
+                &info,
+                DIB_RGB_COLORS,
+                SRCCOPY,
+            );
+        }
+        if let Some((start, end)) = crop.drag {
+            let rect = RECT {
+                left: start.0.min(end.0),
+                top: start.1.min(end.1),
+                right: start.0.max(end.0),
+                bottom: start.1.max(end.1),
+            };
+            unsafe { _ = DrawFocusRect(hdc, &rect) };
+        }
+    }
+    unsafe { _ = EndPaint(hwnd, &ps) };
+}
@@ -0,0 +1,160 @@
+//! Language detection for the `detect_language` setting: the image is recognized with each
+//! candidate language and the results are scored. A score is the number of characters
+//! recognized, weighted by the share of letters written in a script the language uses,
+//! since an engine reading text in a script it doesn't know still produces letters, in
+//! its own script.
+
+// a score this many times every other one decides the language, the remaining candidates
+// aren't tried.
+pub const DECISIVE_RATIO: f64 = 4.0;
+
+/// Writing systems, as far as telling languages apart goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Kana,
+    Han,
+    Hangul,
+    Other,
+}
+
+fn script(c: char) -> Script {
+    match c {
+        'A'..='Z' | 'a'..='z' | '\u{c0}'..='\u{24f}' | '\u{1e00}'..='\u{1eff}' => Script::Latin,
+        // fullwidth, as in Japanese text.
+        '\u{ff21}'..='\u{ff3a}' | '\u{ff41}'..='\u{ff5a}' => Script::Latin,
+        '\u{370}'..='\u{3ff}' | '\u{1f00}'..='\u{1fff}' => Script::Greek,
+        '\u{400}'..='\u{52f}' => Script::Cyrillic,
+        '\u{590}'..='\u{5ff}' => Script::Hebrew,
+        '\u{600}'..='\u{6ff}' | '\u{750}'..='\u{77f}' | '\u{fb50}'..='\u{fdff}' => Script::Arabic,
+        '\u{fe70}'..='\u{feff}' => Script::Arabic,
+        '\u{3040}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}' => Script::Kana,
+        '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' => Script::Han,
+        '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7af}' => {
+            Script::Hangul
+        }
+        _ => Script::Other,
+    }
+}
+
+/// The scripts the language `tag` is written in, e.g. `ja` or `en-US`.
+fn scripts(tag: &str) -> &'static [Script] {
+    let primary = tag.split('-').next().unwrap_or_default();
+    match primary.to_ascii_lowercase().as_str() {
+        "ja" => &[Script::Kana, Script::Han],
+        "zh" => &[Script::Han],
+        "ko" => &[Script::Hangul, Script::Han],
+        "ru" | "uk" | "be" | "bg" | "sr" | "mk" | "kk" => &[Script::Cyrillic],
+        "el" => &[Script::Greek],
+        "he" => &[Script::Hebrew],
+        "ar" | "fa" | "ur" => &[Script::Arabic],
+        _ => &[Script::Latin],
+    }
+}
+
+/// The number of characters in `text`, not counting white space.
+pub fn characters(text: &str) -> usize {
+    text.chars().filter(|c| !c.is_whitespace()).count()
+}
+
+/// How well `text`, recognized with the language `tag`, reads as that language.
+pub fn score(text: &str, tag: &str) -> f64 {
+    let expected = scripts(tag);
+    let chars = characters(text);
+    let (letters, native) = text
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .fold((0, 0), |(letters, native), c| {
+            (letters + 1, native + expected.contains(&script(c)) as usize)
+        });
+    if letters == 0 {
+        return 0.0;
+    }
+    chars as f64 * native as f64 / letters as f64
+}
+
+/// The index of the highest score, the first one on a tie.
+pub fn best(scores: &[f64]) -> Option<usize> {
+    (0..scores.len()).reduce(|best, i| if scores[i] > scores[best] { i } else { best })
+}
+
+/// Whether the best of `scores` leaves the others far enough behind that the remaining
+/// candidates need not be tried.
+pub fn is_decided(scores: &[f64]) -> bool {
+    let Some(best) = best(scores) else {
+        return false;
+    };
+    scores.len() > 1
+        && scores[best] > 0.0
+        && scores
+            .iter()
+            .enumerate()
+            .all(|(i, &score)| i == best || scores[best] >= score * DECISIVE_RATIO)
+}
+
+/// Whether a result of `text` is too little to keep without trying other languages,
+/// fewer than `min_chars` characters.
+pub fn needs_fallback(text: &str, min_chars: usize) -> bool {
+    characters(text) < min_chars
+}
+
+/// The index of the fallback result to use instead of the primary one, the best of
+/// `scores` if it beats `primary`.
+pub fn fallback(primary: f64, scores: &[f64]) -> Option<usize> {
+    best(scores).filter(|&best| scores[best] > primary.max(0.0))
+}
+
+#[test]
+fn score_test() {
+    // a Japanese screenshot, recognized as Japanese and as English.
+    let japanese = score("今日は良い天気ですね。\r\nカタカナ\r\n", "ja");
+    let garbage = score("'J t\r\n", "en-US");
+    assert_eq!(japanese, 15.0);
+    assert!(garbage < japanese / DECISIVE_RATIO);
+
+    // an English screenshot, the Japanese engine reading it as Latin letters.
+    assert_eq!(score("The quick brown fox", "en-US"), 16.0);
+    assert_eq!(score("The quick brown fox", "ja"), 0.0);
+    // Latin letters in Japanese text only weigh the score down.
+    assert_eq!(score("OCRの結果", "ja-JP"), 3.0);
+
+    assert_eq!(score("Привет", "ru"), 6.0);
+    assert_eq!(score("한국어", "ko"), 3.0);
+    // no letters to tell by.
+    assert_eq!(score("12 345", "en"), 0.0);
+    assert_eq!(score("", "ja"), 0.0);
+}
+
+#[test]
+fn decided_test() {
+    assert_eq!(best(&[]), None);
+    assert_eq!(best(&[1.0, 3.0, 3.0]), Some(1));
+
+    assert!(!is_decided(&[]));
+    // nothing to compare to yet.
+    assert!(!is_decided(&[20.0]));
+    assert!(is_decided(&[20.0, 4.0]));
+    assert!(is_decided(&[1.0, 20.0]));
+    assert!(!is_decided(&[20.0, 6.0]));
+    assert!(!is_decided(&[20.0, 1.0, 6.0]));
+    assert!(!is_decided(&[0.0, 0.0]));
+}
+
+#[test]
+fn fallback_test() {
+    // substantial text never falls back.
+    assert!(!needs_fallback("OK Cancel", 3));
+    assert!(needs_fallback(" .\r\n", 3));
+    assert!(needs_fallback("", 3));
+    assert!(!needs_fallback("", 0));
+
+    // the best other language, if it reads better.
+    assert_eq!(fallback(1.0, &[0.0, 12.0, 5.0]), Some(1));
+    assert_eq!(fallback(0.0, &[0.0, 0.0]), None);
+    assert_eq!(fallback(2.0, &[1.0, 2.0]), None);
+    assert_eq!(fallback(0.0, &[]), None);
+}
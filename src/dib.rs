@@ -0,0 +1,1481 @@
+//! Packed DIBs, the header, color table and pixels of CF_DIB and CF_DIBV5, read from
+//! plain bytes and converted to BGRA.
+
+use super::{image::Image, rle};
+use anyhow::{anyhow, ensure, Context, Result};
+use std::borrow::Cow;
+use std::mem::offset_of;
+use std::ptr;
+use windows::Win32::Graphics::Gdi::{
+    BITMAPCOREHEADER, BITMAPINFOHEADER, BITMAPV5HEADER, BI_BITFIELDS, BI_JPEG, BI_PNG, BI_RGB,
+    BI_RLE4, BI_RLE8,
+};
+
+const CORE_HEADER_SIZE: usize = size_of::<BITMAPCOREHEADER>();
+pub const INFO_HEADER_SIZE: usize = size_of::<BITMAPINFOHEADER>();
+
+// red, green and blue masks of BGRA ordered 32 bpp pixels.
+const BGR_MASKS: [u32; 3] = [0x00ff_0000, 0x0000_ff00, 0x0000_00ff];
+
+// red, green, blue and alpha masks of a BI_RGB 16 bpp image.
+const RGB555_MASKS: [u32; 4] = [0x7c00, 0x03e0, 0x001f, 0];
+
+/// Extracts the channel selected by `mask` from `pixel`, scaled to 0..=255.
+fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let value = ((pixel & mask) >> shift) as u64;
+    ((value * 255 + max / 2) / max) as u8
+}
+
+// sanity ceiling of the width and height of a bitmap, far past what the engine takes.
+const MAX_DIMENSION: i32 = 32 * 1024;
+
+/// Rejects empty bitmaps and sizes past `MAX_DIMENSION`, so the size math on them can't
+/// overflow.
+pub fn check_dimensions(width: i32, height: i32) -> Result<()> {
+    ensure!(
+        (1..=MAX_DIMENSION).contains(&width) && (1..=MAX_DIMENSION).contains(&height),
+        "{width}x{height} is not a valid bitmap size, {MAX_DIMENSION} pixels at most per side."
+    );
+    Ok(())
+}
+
+/// Bytes per scan line, rows are padded to 4 bytes. `width` must have passed
+/// `check_dimensions`.
+pub fn stride(width: i32, bits_per_pixel: u16) -> usize {
+    (width as usize * bits_per_pixel as usize).div_ceil(32) * 4
+}
+
+/// A bitmap decoded far enough to convert its pixels. Parsed ones borrow the pixels of
+/// the packed DIB, so they are copied once, straight into the BGRA output.
+#[derive(Debug, Default)]
+pub struct Dib<'a> {
+    width: i32,
+    height: i32,
+    bits_per_pixel: u16,
+    // color table of paletted images as BGRA.
+    palette: Vec<[u8; 4]>,
+    // red, green, blue and alpha masks of 16 and 32 bpp images, all zero for BI_RGB
+    // 32 bpp ones.
+    masks: [u32; 4],
+    // rows are stored top row first (negative biHeight).
+    top_down: bool,
+    // the pixel rows, owned once run-length decoded.
+    data: Cow<'a, [u8]>,
+}
+
+impl<'a> Dib<'a> {
+    /// Parses a packed DIB: a header, optional color masks, a color table and the pixels.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        let field = |offset: usize| -> Result<u32> {
+            let b = bytes.get(offset..offset + 4).context("truncated bitmap.")?;
+            Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        let header_size = field(0)? as usize;
+        ensure!(header_size <= bytes.len(), "truncated bitmap header.");
+        // palette entries are RGBQUADs, or RGBTRIPLEs after an OS/2 core header.
+        let (header, entry_size) = match header_size {
+            CORE_HEADER_SIZE => {
+                let core =
+                    unsafe { ptr::read_unaligned(bytes.as_ptr() as *const BITMAPCOREHEADER) };
+                let header = BITMAPINFOHEADER {
+                    biSize: core.bcSize,
+                    biWidth: core.bcWidth as i32,
+                    biHeight: core.bcHeight as i32,
+                    biPlanes: core.bcPlanes,
+                    biBitCount: core.bcBitCount,
+                    ..Default::default()
+                };
+                (header, 3)
+            }
+            // BITMAPINFOHEADER, V2, V3, V4 and V5 all start with the BITMAPINFOHEADER fields.
+            40 | 52 | 56 | 108 | 124 => {
+                let header =
+                    unsafe { ptr::read_unaligned(bytes.as_ptr() as *const BITMAPINFOHEADER) };
+                (header, 4)
+            }
+            n => return Err(anyhow!("unsupported bitmap header size {n}.")),
+        };
+        check_header(&header)?;
+
+        let width = header.biWidth;
+        let top_down = header.biHeight < 0;
+        let height = header
+            .biHeight
+            .checked_abs()
+            .context("invalid bitmap height.")?;
+        check_dimensions(width, height)?;
+        let size = if header.biSizeImage == 0 {
+            stride(width, header.biBitCount)
+                .checked_mul(height as usize)
+                .context("bitmap is too large.")?
+        } else {
+            header.biSizeImage as _
+        };
+        //ensure!(size > 0, "no data.");
+
+        let bits_per_pixel = header.biBitCount;
+        // 0 is for the JPEG and PNG streams `encoded_payload` reads.
+        ensure!(bits_per_pixel > 0, "bitmap without a bit depth.");
+        let compression = header.biCompression;
+        let compressed = compression == BI_RLE8.0 || compression == BI_RLE4.0;
+        ensure!(
+            !compressed || (header.biSizeImage > 0 && !top_down),
+            "invalid run-length encoded bitmap."
+        );
+
+        // BI_RGB is plain integer channels, anything else would be fixed or floating point.
+        ensure!(
+            bits_per_pixel != 64 || compression == BI_RGB.0,
+            "64 bpp images with compression {compression} (fixed or floating point channels) are not supported."
+        );
+
+        let depths: &[u16] = match compression {
+            c if c == BI_RLE8.0 => &[8],
+            c if c == BI_RLE4.0 => &[4],
+            c if c == BI_BITFIELDS.0 => &[16, 32],
+            _ => &[bits_per_pixel],
+        };
+        ensure!(
+            depths.contains(&bits_per_pixel),
+            "biCompression {compression} doesn't go with a biBitCount of {bits_per_pixel}."
+        );
+
+        // BI_BITFIELDS masks follow a plain BITMAPINFOHEADER, later versions have them
+        // (and the alpha mask) inside the header.
+        let masks_len = if compression == BI_BITFIELDS.0 && header_size == INFO_HEADER_SIZE {
+            12
+        } else {
+            0
+        };
+        let masks = if compression == BI_BITFIELDS.0 {
+            let alpha = if header_size >= INFO_HEADER_SIZE + 16 {
+                field(INFO_HEADER_SIZE + 12)?
+            } else {
+                0
+            };
+            [
+                field(INFO_HEADER_SIZE)?,
+                field(INFO_HEADER_SIZE + 4)?,
+                field(INFO_HEADER_SIZE + 8)?,
+                alpha,
+            ]
+        } else if bits_per_pixel == 16 {
+            RGB555_MASKS
+        } else {
+            [0; 4]
+        };
+
+        // embedded ICC data may sit between the color table and the pixels.
+        let profile = if header_size >= size_of::<BITMAPV5HEADER>() {
+            Some((
+                field(offset_of!(BITMAPV5HEADER, bV5ProfileData))? as usize,
+                field(offset_of!(BITMAPV5HEADER, bV5ProfileSize))? as usize,
+            ))
+        } else {
+            None
+        };
+
+        let colors = palette_len(bits_per_pixel, header.biClrUsed)?;
+        let table = header_size + masks_len;
+        let palette = bytes
+            .get(table..table + colors * entry_size)
+            .with_context(|| {
+                format!(
+                    "a color table of {colors} entries (biClrUsed {}) exceeds the {} byte bitmap.",
+                    header.biClrUsed,
+                    bytes.len()
+                )
+            })?;
+        let offset = pixel_offset(table, palette.len(), profile);
+        let data = offset
+            .checked_add(size)
+            .and_then(|end| bytes.get(offset..end))
+            .with_context(|| {
+                format!(
+                    "{size} bytes of pixels at {offset} exceed the {} byte bitmap.",
+                    bytes.len()
+                )
+            })?;
+        // `to_bgra` reads whole rows, which a low biSizeImage leaves out.
+        if !compressed {
+            let rows = stride(width, bits_per_pixel)
+                .checked_mul(height as usize)
+                .context("bitmap is too large.")?;
+            ensure!(
+                data.len() >= rows,
+                "{width}x{height} pixels at {bits_per_pixel} bpp take {rows} bytes, the bitmap has {}.",
+                data.len()
+            );
+        }
+
+        let mut dib = Self {
+            width,
+            height,
+            bits_per_pixel,
+            // only paletted images use the table, others may carry an optimal palette.
+            palette: palette
+                .chunks(entry_size)
+                .map(|c| [c[0], c[1], c[2], 255])
+                .collect(),
+            masks,
+            top_down,
+            data: Cow::Borrowed(data),
+        };
+        if compressed {
+            dib.data = Cow::Owned(rle::decode(
+                &dib.data,
+                width as usize,
+                height as usize,
+                bits_per_pixel,
+                dib.scan_line_bytes_count_with_padding(),
+            )?);
+        }
+        Ok(dib)
+    }
+
+    /// A top-down 32 bpp bitmap of BGRA pixels, such as the ones GDI renders into.
+    pub fn bgra(width: i32, height: i32, data: Vec<u8>) -> Self {
+        Self {
+            width,
+            height,
+            bits_per_pixel: 32,
+            top_down: true,
+            data: Cow::Owned(data),
+            ..Default::default()
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn to_image(&self) -> Result<Image> {
+        Ok(Image {
+            width: self.width(),
+            height: self.height(),
+            bgra: self.to_bgra()?,
+        })
+    }
+
+    fn scan_line_bytes_count_with_padding(&self) -> usize {
+        stride(self.width, self.bits_per_pixel)
+    }
+
+    /// True if 32 bpp pixels are already laid out as BGRA.
+    fn has_bgra_masks(&self) -> bool {
+        self.masks == [0; 4] || self.masks[..3] == BGR_MASKS
+    }
+
+    /// Converts a pixel to BGRA through the color masks. Without an alpha mask the pixel
+    /// is opaque.
+    fn remap(&self, pixel: u32) -> [u8; 4] {
+        let [r, g, b, a] = self.masks;
+        let alpha = if a == 0 { 255 } else { channel(pixel, a) };
+        [
+            channel(pixel, b),
+            channel(pixel, g),
+            channel(pixel, r),
+            alpha,
+        ]
+    }
+
+    /// Looks up a palette index. Indices past the end of the color table come out black.
+    fn color(&self, index: u8) -> [u8; 4] {
+        self.palette
+            .get(index as usize)
+            .copied()
+            .unwrap_or([0, 0, 0, 255])
+    }
+
+    pub fn to_bgra(&self) -> Result<Vec<u8>> {
+        ensure!(
+            matches!(self.bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32 | 64),
+            "{} bits per pixel image is not yet supported.",
+            self.bits_per_pixel
+        );
+        let (width, height) = (self.width as usize, self.height as usize);
+        let stride = self.scan_line_bytes_count_with_padding();
+        ensure!(
+            self.data.len() >= stride * height,
+            "{} bytes of pixels for {} rows of {stride} bytes.",
+            self.data.len(),
+            height
+        );
+
+        let mut bgra = vec![0; width * height * 4];
+        let rows = self.data.chunks_exact(stride).take(height);
+        for (y, src) in rows.enumerate() {
+            let y = if self.top_down { y } else { height - 1 - y };
+            self.convert_row(src, &mut bgra[y * width * 4..(y + 1) * width * 4]);
+        }
+        Ok(bgra)
+    }
+
+    /// Converts the scan line `src` into `dst`, a row of BGRA pixels.
+    fn convert_row(&self, src: &[u8], dst: &mut [u8]) {
+        let pixels = dst.chunks_exact_mut(4);
+        match self.bits_per_pixel {
+            // 16 bits per channel, keep the high byte of each.
+            64 => {
+                for (d, p) in pixels.zip(src.chunks_exact(8)) {
+                    d.copy_from_slice(&[p[1], p[3], p[5], p[7]]);
+                }
+            }
+            32 if self.has_bgra_masks() => dst.copy_from_slice(&src[..dst.len()]),
+            32 => {
+                for (d, p) in pixels.zip(src.chunks_exact(4)) {
+                    d.copy_from_slice(&self.remap(u32::from_le_bytes([p[0], p[1], p[2], p[3]])));
+                }
+            }
+            24 => {
+                for (d, p) in pixels.zip(src.chunks_exact(3)) {
+                    d[..3].copy_from_slice(p);
+                    d[3] = 255;
+                }
+            }
+            16 => {
+                for (d, p) in pixels.zip(src.chunks_exact(2)) {
+                    d.copy_from_slice(&self.remap(u16::from_le_bytes([p[0], p[1]]) as u32));
+                }
+            }
+            8 => {
+                for (d, &i) in pixels.zip(src) {
+                    d.copy_from_slice(&self.color(i));
+                }
+            }
+            4 => {
+                // two indices per byte, high nibble first.
+                let indices = src.iter().flat_map(|b| [b >> 4, b & 0x0f]);
+                for (d, i) in pixels.zip(indices) {
+                    d.copy_from_slice(&self.color(i));
+                }
+            }
+            1 => {
+                for (d, i) in pixels.zip(BitIterator::new(src)) {
+                    d.copy_from_slice(&self.color(i));
+                }
+            }
+            // rejected by `to_bgra`.
+            _ => (),
+        }
+    }
+}
+
+struct BitIterator<'a> {
+    slice: &'a [u8],
+    index: usize,
+}
+
+impl<'a> BitIterator<'a> {
+    fn new(slice: &'a [u8]) -> Self {
+        Self { slice, index: 0 }
+    }
+}
+
+impl Iterator for BitIterator<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte_index = self.index / 8;
+        let bit_index = self.index % 8;
+
+        let bits = self.slice.get(byte_index)?;
+        let bit = (bits << bit_index) >> 7;
+
+        self.index += 1;
+
+        Some(bit)
+    }
+}
+
+/// The JPEG or PNG stream of a BI_JPEG or BI_PNG DIB, None for other compressions.
+pub fn encoded_payload(bytes: &[u8]) -> Result<Option<&[u8]>> {
+    let field = |offset: usize| -> Result<u32> {
+        let b = bytes.get(offset..offset + 4).context("truncated bitmap.")?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let header_size = field(0)? as usize;
+    if header_size < INFO_HEADER_SIZE {
+        return Ok(None);
+    }
+    let compression = field(offset_of!(BITMAPINFOHEADER, biCompression))?;
+    if compression != BI_JPEG.0 && compression != BI_PNG.0 {
+        return Ok(None);
+    }
+
+    // biSizeImage is the length of the stream, which follows any (unused) color table.
+    let size = field(offset_of!(BITMAPINFOHEADER, biSizeImage))? as usize;
+    ensure!(size > 0, "compressed bitmap without a size.");
+    let colors = field(offset_of!(BITMAPINFOHEADER, biClrUsed))?;
+    ensure!(colors <= 256, "{colors} palette entries.");
+    let profile = if header_size >= size_of::<BITMAPV5HEADER>() {
+        Some((
+            field(offset_of!(BITMAPV5HEADER, bV5ProfileData))? as usize,
+            field(offset_of!(BITMAPV5HEADER, bV5ProfileSize))? as usize,
+        ))
+    } else {
+        None
+    };
+    let offset = pixel_offset(header_size, colors as usize * 4, profile);
+    offset
+        .checked_add(size)
+        .and_then(|end| bytes.get(offset..end))
+        .map(Some)
+        .with_context(|| {
+            format!(
+                "{size} byte image stream at {offset} exceeds the {} byte bitmap.",
+                bytes.len()
+            )
+        })
+}
+
+/// Prepends a BITMAPFILEHEADER to a packed DIB, turning it into a .bmp file.
+pub fn to_bmp_file(dib: &[u8]) -> Result<Vec<u8>> {
+    let u16_at = |offset: usize| -> Result<usize> {
+        let b = dib
+            .get(offset..offset + 2)
+            .context("truncated bitmap header.")?;
+        Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+    };
+    let u32_at = |offset: usize| -> Result<usize> {
+        let b = dib
+            .get(offset..offset + 4)
+            .context("truncated bitmap header.")?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+
+    // only the pixel offset is needed, the decoder validates the rest.
+    let header_size = u32_at(0)?;
+    let table_len = if header_size == CORE_HEADER_SIZE {
+        match u16_at(10)? {
+            bits @ 1..=8 => 3 << bits,
+            _ => 0,
+        }
+    } else {
+        let bits = u16_at(14)?;
+        let masks = if u32_at(16)? == BI_BITFIELDS.0 as usize && header_size == INFO_HEADER_SIZE {
+            12
+        } else {
+            0
+        };
+        let colors = match u32_at(32)? {
+            0 if bits <= 8 => 1 << bits,
+            n => n,
+        };
+        masks + colors * 4
+    };
+
+    const FILE_HEADER_SIZE: usize = 14;
+    let offset = FILE_HEADER_SIZE + header_size + table_len;
+    let size = FILE_HEADER_SIZE + dib.len();
+    ensure!(
+        offset <= size && size <= u32::MAX as usize,
+        "invalid bitmap layout."
+    );
+    Ok([
+        b"BM".as_slice(),
+        &(size as u32).to_le_bytes(),
+        &[0; 4],
+        &(offset as u32).to_le_bytes(),
+        dib,
+    ]
+    .concat())
+}
+
+/// Rejects header fields no valid bitmap has, naming the first one found.
+fn check_header(header: &BITMAPINFOHEADER) -> Result<()> {
+    ensure!(
+        header.biPlanes == 1,
+        "biPlanes is {}, not 1.",
+        header.biPlanes
+    );
+    // 0 is for the JPEG and PNG streams `encoded_payload` reads, 2 is left to WIC by
+    // `to_bgra`.
+    ensure!(
+        matches!(header.biBitCount, 0 | 1 | 2 | 4 | 8 | 16 | 24 | 32 | 64),
+        "biBitCount {} is not supported.",
+        header.biBitCount
+    );
+    let known = [BI_RGB, BI_RLE8, BI_RLE4, BI_BITFIELDS];
+    ensure!(
+        known.iter().any(|c| c.0 == header.biCompression),
+        "biCompression {} is not supported.",
+        header.biCompression
+    );
+    Ok(())
+}
+
+/// Offset of the pixels from the start of the header: they follow the header, its masks
+/// and the color table of `table_len` bytes, and an ICC profile placed right there
+/// (`(offset, size)`).
+fn pixel_offset(header_len: usize, table_len: usize, profile: Option<(usize, usize)>) -> usize {
+    let offset = header_len + table_len;
+    match profile {
+        Some((data, size)) if size > 0 && data == offset => offset + size,
+        _ => offset,
+    }
+}
+
+/// Number of color table entries. A `clr_used` of 0 means the full 2^bpp palette for
+/// paletted images and no table for the others.
+fn palette_len(bits_per_pixel: u16, clr_used: u32) -> Result<usize> {
+    if bits_per_pixel > 8 {
+        ensure!(clr_used <= 256, "{clr_used} palette entries.");
+        return Ok(clr_used as usize);
+    }
+    let max = 1 << bits_per_pixel;
+    let len = if clr_used == 0 {
+        max
+    } else {
+        clr_used as usize
+    };
+    ensure!(
+        len <= max,
+        "{len} palette entries for a {bits_per_pixel} bpp image."
+    );
+    Ok(len)
+}
+
+#[test]
+fn scan_line_bytes_count_with_padding_test() {
+    let dib = Dib {
+        width: 9,
+        bits_per_pixel: 1,
+        ..Default::default()
+    };
+    assert_eq!(dib.scan_line_bytes_count_with_padding(), 4);
+
+    let dib = Dib {
+        width: 53,
+        bits_per_pixel: 24,
+        ..Default::default()
+    };
+    assert_eq!(dib.scan_line_bytes_count_with_padding(), 160);
+
+    let dib = Dib {
+        width: 53,
+        bits_per_pixel: 32,
+        ..Default::default()
+    };
+    assert_eq!(dib.scan_line_bytes_count_with_padding(), 212);
+}
+
+#[test]
+fn palette_len_test() {
+    assert_eq!(palette_len(8, 0).unwrap(), 256);
+    assert_eq!(palette_len(8, 16).unwrap(), 16);
+    assert_eq!(palette_len(1, 0).unwrap(), 2);
+    assert_eq!(palette_len(24, 0).unwrap(), 0);
+    assert_eq!(palette_len(32, 0).unwrap(), 0);
+    // an optimal palette attached to a true color image.
+    assert_eq!(palette_len(24, 16).unwrap(), 16);
+    assert!(palette_len(8, 257).is_err());
+}
+
+#[test]
+fn to_bgra_8bpp_test() {
+    // 3x2, rows padded to 4 bytes and stored bottom-up.
+    let dib = Dib {
+        width: 3,
+        height: 2,
+        bits_per_pixel: 8,
+        palette: vec![[0, 0, 0, 255], [255, 255, 255, 255], [0, 0, 255, 255]],
+        data: vec![
+            2, 1, 0, 0xaa, // bottom row
+            0, 1, 3, 0xaa, // top row, index 3 is past the palette
+        ]
+        .into(),
+        ..Default::default()
+    };
+    assert_eq!(dib.scan_line_bytes_count_with_padding(), 4);
+    assert_eq!(
+        dib.to_bgra().unwrap(),
+        [
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn to_bgra_4bpp_test() {
+    // 5x2, the low nibble of each row's third byte is padding.
+    let dib = Dib {
+        width: 5,
+        height: 2,
+        bits_per_pixel: 4,
+        palette: vec![[0, 0, 0, 255], [255, 255, 255, 255], [255, 0, 0, 255]],
+        data: vec![
+            0x01, 0x21, 0x0f, 0xff, // bottom row
+            0x10, 0x12, 0x2f, 0xff, // top row
+        ]
+        .into(),
+        ..Default::default()
+    };
+    assert_eq!(dib.scan_line_bytes_count_with_padding(), 4);
+
+    let (b, w, k) = ([255, 0, 0, 255], [255, 255, 255, 255], [0, 0, 0, 255]);
+    assert_eq!(
+        dib.to_bgra().unwrap(),
+        [w, k, w, b, b, k, w, b, w, k].concat()
+    );
+}
+
+#[test]
+fn channel_test() {
+    assert_eq!(channel(0x7c00, 0x7c00), 255);
+    assert_eq!(channel(0x0000, 0x7c00), 0);
+    assert_eq!(channel(0x4000, 0x7c00), 132);
+    assert_eq!(channel(0x07e0, 0x07e0), 255);
+    assert_eq!(channel(0x0400, 0x07e0), 130);
+    assert_eq!(channel(0xffff, 0), 0);
+}
+
+#[test]
+fn to_bgra_16bpp_test() {
+    // 3x1, 6 bytes of pixels padded to 8.
+    let (w, k) = ([255, 255, 255, 255], [0, 0, 0, 255]);
+
+    let rgb555 = Dib {
+        width: 3,
+        height: 1,
+        bits_per_pixel: 16,
+        masks: RGB555_MASKS,
+        data: vec![0x1f, 0x00, 0xff, 0x7f, 0x00, 0x7c, 0xaa, 0xaa].into(),
+        ..Default::default()
+    };
+    assert_eq!(rgb555.scan_line_bytes_count_with_padding(), 8);
+    assert_eq!(
+        rgb555.to_bgra().unwrap(),
+        [[255, 0, 0, 255], w, [0, 0, 255, 255]].concat()
+    );
+
+    let rgb565 = Dib {
+        masks: [0xf800, 0x07e0, 0x001f, 0],
+        data: vec![0xe0, 0x07, 0xff, 0xff, 0x00, 0x00, 0xaa, 0xaa].into(),
+        ..rgb555
+    };
+    assert_eq!(rgb565.to_bgra().unwrap(), [[0, 255, 0, 255], w, k].concat());
+
+    // 1-5-5-5 with the alpha bit from a V5 header.
+    let argb1555 = Dib {
+        masks: [0x7c00, 0x03e0, 0x001f, 0x8000],
+        data: vec![0x1f, 0x80, 0xff, 0x7f, 0x00, 0x80, 0xaa, 0xaa].into(),
+        ..rgb565
+    };
+    assert_eq!(
+        argb1555.to_bgra().unwrap(),
+        [[255, 0, 0, 255], [255, 255, 255, 0], [0, 0, 0, 255]].concat()
+    );
+}
+
+#[test]
+fn to_bgra_32bpp_masks_test() {
+    // 2x1 of red and half transparent blue.
+    let bgra = Dib {
+        width: 2,
+        height: 1,
+        bits_per_pixel: 32,
+        masks: [BGR_MASKS[0], BGR_MASKS[1], BGR_MASKS[2], 0xff00_0000],
+        data: vec![0, 0, 255, 255, 255, 0, 0, 128].into(),
+        ..Default::default()
+    };
+    let expected = [0, 0, 255, 255, 255, 0, 0, 128];
+    assert_eq!(bgra.to_bgra().unwrap(), expected);
+
+    let rgba = Dib {
+        masks: [0x0000_00ff, 0x0000_ff00, 0x00ff_0000, 0xff00_0000],
+        data: vec![255, 0, 0, 255, 0, 0, 255, 128].into(),
+        ..bgra
+    };
+    assert_eq!(rgba.to_bgra().unwrap(), expected);
+
+    // BI_RGB keeps whatever the fourth byte holds.
+    let plain = Dib {
+        masks: [0; 4],
+        data: vec![0, 0, 255, 0, 255, 0, 0, 0].into(),
+        ..rgba
+    };
+    assert_eq!(plain.to_bgra().unwrap(), [0, 0, 255, 0, 255, 0, 0, 0]);
+
+    // without an alpha mask, remapped pixels are opaque.
+    let xbgr = Dib {
+        masks: [0xff00_0000, 0x00ff_0000, 0x0000_ff00, 0],
+        data: vec![0, 0, 0, 255, 0, 255, 0, 0].into(),
+        ..plain
+    };
+    assert_eq!(xbgr.to_bgra().unwrap(), [0, 0, 255, 255, 255, 0, 0, 255]);
+}
+
+#[test]
+fn to_bgra_1bpp_test() {
+    // 10x1, bits past the width are padding.
+    let mut dib = Dib {
+        width: 10,
+        height: 1,
+        bits_per_pixel: 1,
+        palette: vec![[0, 0, 0, 255], [255, 255, 255, 255]],
+        data: vec![0b1001_1110, 0b1111_1111, 0, 0].into(),
+        ..Default::default()
+    };
+    let (w, k) = ([255, 255, 255, 255], [0, 0, 0, 255]);
+    assert_eq!(
+        dib.to_bgra().unwrap(),
+        [w, k, k, w, w, w, w, k, w, w].concat()
+    );
+
+    // fax and scanner output often puts white at index 0.
+    dib.palette.reverse();
+    assert_eq!(
+        dib.to_bgra().unwrap(),
+        [k, w, w, k, k, k, k, w, k, k].concat()
+    );
+}
+
+#[test]
+fn to_bgra_64bpp_test() {
+    let pixel = |b: u16, g: u16, r: u16, a: u16| {
+        [b, g, r, a]
+            .into_iter()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>()
+    };
+    // 2x2, bottom-up.
+    let dib = Dib {
+        width: 2,
+        height: 2,
+        bits_per_pixel: 64,
+        data: [
+            pixel(0x0000, 0x00ff, 0x0100, 0xffff),
+            pixel(0xffff, 0x8000, 0x7fff, 0xffff),
+            pixel(0x1234, 0xabcd, 0xff00, 0x8000),
+            pixel(0, 0, 0, 0),
+        ]
+        .concat()
+        .into(),
+        ..Default::default()
+    };
+    assert_eq!(
+        dib.to_bgra().unwrap(),
+        [
+            [0x12, 0xab, 0xff, 0x80],
+            [0, 0, 0, 0],
+            [0x00, 0x00, 0x01, 0xff],
+            [0xff, 0x80, 0x7f, 0xff],
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn to_bgra_top_down_test() {
+    let rows = [[1u8, 2, 3, 4, 5, 6, 0, 0], [7, 8, 9, 10, 11, 12, 0, 0]];
+    let bottom_up = Dib {
+        width: 2,
+        height: 2,
+        bits_per_pixel: 24,
+        data: [rows[1], rows[0]].concat().into(),
+        ..Default::default()
+    };
+    let expected = [1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255, 10, 11, 12, 255];
+    assert_eq!(bottom_up.to_bgra().unwrap(), expected);
+
+    let top_down = Dib {
+        top_down: true,
+        data: rows.concat().into(),
+        ..bottom_up
+    };
+    assert_eq!(top_down.to_bgra().unwrap(), expected);
+}
+
+#[test]
+#[ignore = "benchmark, run with --ignored --nocapture"]
+fn to_bgra_benchmark() {
+    use std::time::Instant;
+
+    let (width, height) = (3840, 2160);
+    for bits_per_pixel in [24, 32] {
+        let stride = stride(width, bits_per_pixel);
+        let dib = Dib {
+            width,
+            height,
+            bits_per_pixel,
+            data: (0..stride * height as usize)
+                .map(|i| (i % 251) as u8)
+                .collect(),
+            ..Default::default()
+        };
+
+        // per pixel iterators, the way rows used to be converted.
+        let start = Instant::now();
+        let bytes = bits_per_pixel as usize / 8;
+        let naive: Vec<u8> = dib
+            .data
+            .chunks(stride)
+            .rev()
+            .flat_map(|s| {
+                s[..width as usize * bytes]
+                    .chunks(bytes)
+                    .flat_map(|p| [p[0], p[1], p[2], if bytes == 4 { p[3] } else { 255 }])
+            })
+            .collect();
+        let naive_time = start.elapsed();
+
+        let start = Instant::now();
+        let bgra = dib.to_bgra().unwrap();
+        let rows_time = start.elapsed();
+
+        println!("4K {bits_per_pixel} bpp to BGRA: iterators {naive_time:?}, rows {rows_time:?}");
+        assert_eq!(naive, bgra);
+    }
+}
+
+#[test]
+#[ignore = "benchmark, run with --ignored --nocapture"]
+fn parse_benchmark() {
+    use std::time::Instant;
+
+    let (width, height) = (3840, 2160);
+    let pixels: Vec<u8> = (0..width * height * 4).map(|i| (i % 251) as u8).collect();
+    let blob = dib_blob(
+        BITMAPINFOHEADER {
+            biSize: INFO_HEADER_SIZE as u32,
+            biWidth: width,
+            biHeight: height,
+            biPlanes: 1,
+            biBitCount: 32,
+            ..Default::default()
+        },
+        &[],
+        &pixels,
+    );
+
+    // the pixels copied out of the block before converting them, as they used to be.
+    let start = Instant::now();
+    let copied = Dib {
+        data: Cow::Owned(Dib::parse(&blob).unwrap().data.into_owned()),
+        ..Dib::parse(&blob).unwrap()
+    };
+    let copied_bgra = copied.to_bgra().unwrap();
+    let copied_time = start.elapsed();
+
+    let start = Instant::now();
+    let bgra = Dib::parse(&blob).unwrap().to_bgra().unwrap();
+    let borrowed_time = start.elapsed();
+
+    println!("4K 32 bpp DIB to BGRA: copied {copied_time:?}, borrowed {borrowed_time:?}");
+    assert_eq!(copied_bgra, bgra);
+}
+
+#[test]
+fn check_dimensions_test() {
+    assert!(check_dimensions(1, 1).is_ok());
+    assert!(check_dimensions(MAX_DIMENSION, MAX_DIMENSION).is_ok());
+    assert!(check_dimensions(0, 1).is_err());
+    assert!(check_dimensions(1, 0).is_err());
+    assert!(check_dimensions(MAX_DIMENSION + 1, 1).is_err());
+    assert!(check_dimensions(1, MAX_DIMENSION + 1).is_err());
+    assert!(check_dimensions(-1, 1).is_err());
+    assert!(check_dimensions(1_000_000_000, 1).is_err());
+}
+
+/// Headers with extreme sizes and bit depths fail with an error, never a panic.
+#[test]
+fn parse_dib_extremes_test() {
+    let extremes = [
+        i32::MIN,
+        -MAX_DIMENSION - 1,
+        -1,
+        0,
+        1,
+        3,
+        MAX_DIMENSION,
+        MAX_DIMENSION + 1,
+        1_000_000_000,
+        i32::MAX,
+    ];
+    for width in extremes {
+        for height in extremes {
+            for bits in [0, 1, 2, 4, 8, 16, 24, 32, 48, 64, u16::MAX] {
+                for (compression, size_image) in [
+                    (BI_RGB.0, 0),
+                    (BI_RGB.0, u32::MAX),
+                    (BI_RLE8.0, 4),
+                    (BI_BITFIELDS.0, 16),
+                ] {
+                    let header = BITMAPINFOHEADER {
+                        biSize: INFO_HEADER_SIZE as u32,
+                        biWidth: width,
+                        biHeight: height,
+                        biPlanes: 1,
+                        biBitCount: bits,
+                        biCompression: compression,
+                        biSizeImage: size_image,
+                        ..Default::default()
+                    };
+                    let blob = dib_blob(header, &[0xff; 12], &[0, 1, 0, 0]);
+                    if let Ok(dib) = Dib::parse(&blob) {
+                        dib.to_bgra().ok();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn pixel_offset_test() {
+    // BITMAPINFOHEADER, 24 bpp.
+    assert_eq!(pixel_offset(40, 0, None), 40);
+    // BITMAPINFOHEADER with BI_BITFIELDS masks.
+    assert_eq!(pixel_offset(52, 0, None), 52);
+    // 8 bpp with a 16 color palette.
+    assert_eq!(pixel_offset(40, 16 * 4, None), 104);
+    // BITMAPV5HEADER with the profile after the pixels, or none at all.
+    assert_eq!(pixel_offset(124, 0, Some((124 + 64, 3144))), 124);
+    assert_eq!(pixel_offset(124, 0, Some((0, 0))), 124);
+    // BITMAPV5HEADER with the profile between the header and the pixels.
+    assert_eq!(pixel_offset(124, 0, Some((124, 3144))), 3268);
+    assert_eq!(pixel_offset(124, 2 * 4, Some((132, 100))), 232);
+}
+
+#[cfg(test)]
+pub fn dib_blob(header: BITMAPINFOHEADER, table: &[u8], pixels: &[u8]) -> Vec<u8> {
+    let header =
+        unsafe { std::slice::from_raw_parts(&header as *const _ as *const u8, INFO_HEADER_SIZE) };
+    [header, table, pixels].concat()
+}
+
+#[test]
+fn parse_dib_test() {
+    let header = BITMAPINFOHEADER {
+        biSize: INFO_HEADER_SIZE as u32,
+        biWidth: 1,
+        biHeight: 1,
+        biPlanes: 1,
+        biBitCount: 24,
+        ..Default::default()
+    };
+    let first_pixel = |blob: &[u8]| Dib::parse(blob).unwrap().to_bgra().unwrap()[..4].to_vec();
+
+    // plain 24 bpp.
+    let blob = dib_blob(header, &[], &[1, 2, 3, 0]);
+    assert_eq!(first_pixel(&blob), [1, 2, 3, 255]);
+
+    // 24 bpp with an optimal palette of two entries.
+    let blob = dib_blob(
+        BITMAPINFOHEADER {
+            biClrUsed: 2,
+            ..header
+        },
+        &[9; 8],
+        &[1, 2, 3, 0],
+    );
+    assert_eq!(first_pixel(&blob), [1, 2, 3, 255]);
+
+    // 8 bpp with a full 256 color palette.
+    let mut table = vec![0; 256 * 4];
+    table[5 * 4..6 * 4].copy_from_slice(&[10, 20, 30, 0]);
+    let blob = dib_blob(
+        BITMAPINFOHEADER {
+            biBitCount: 8,
+            ..header
+        },
+        &table,
+        &[5, 0, 0, 0],
+    );
+    assert_eq!(first_pixel(&blob), [10, 20, 30, 255]);
+
+    // 32 bpp with RGBA masks after the header.
+    let masks = [0xffu32, 0xff00, 0xff0000].map(u32::to_le_bytes).concat();
+    let blob = dib_blob(
+        BITMAPINFOHEADER {
+            biBitCount: 32,
+            biCompression: BI_BITFIELDS.0,
+            ..header
+        },
+        &masks,
+        &[1, 2, 3, 4],
+    );
+    assert_eq!(first_pixel(&blob), [3, 2, 1, 255]);
+
+    // 64 bpp integer channels, but not the fixed point ones.
+    let header64 = BITMAPINFOHEADER {
+        biBitCount: 64,
+        ..header
+    };
+    let pixel = [0, 1, 0, 2, 0, 3, 0, 4];
+    assert_eq!(first_pixel(&dib_blob(header64, &[], &pixel)), [1, 2, 3, 4]);
+    let blob = dib_blob(
+        BITMAPINFOHEADER {
+            biCompression: BI_BITFIELDS.0,
+            ..header64
+        },
+        &masks,
+        &pixel,
+    );
+    assert!(Dib::parse(&blob).is_err());
+
+    // pixels past the end of the block.
+    let blob = dib_blob(
+        BITMAPINFOHEADER {
+            biClrUsed: 2,
+            ..header
+        },
+        &[9; 8],
+        &[1, 2],
+    );
+    assert!(Dib::parse(&blob).is_err());
+    assert!(Dib::parse(&blob[..20]).is_err());
+}
+
+/// Corrupted headers fail with an error naming the field.
+#[test]
+fn parse_dib_corrupted_header_test() {
+    let header = BITMAPINFOHEADER {
+        biSize: INFO_HEADER_SIZE as u32,
+        biWidth: 2,
+        biHeight: 2,
+        biPlanes: 1,
+        biBitCount: 8,
+        biClrUsed: 2,
+        ..Default::default()
+    };
+    let table = [0; 8];
+    let pixels = [0; 8];
+    assert!(Dib::parse(&dib_blob(header, &table, &pixels)).is_ok());
+
+    let error = |header: BITMAPINFOHEADER, table: &[u8], pixels: &[u8]| {
+        Dib::parse(&dib_blob(header, table, pixels))
+            .unwrap_err()
+            .to_string()
+    };
+    // cut right after the header.
+    assert!(error(header, &[], &[]).contains("biClrUsed 2"));
+    assert!(error(
+        BITMAPINFOHEADER {
+            biBitCount: 7,
+            ..header
+        },
+        &table,
+        &pixels
+    )
+    .contains("biBitCount 7"));
+    assert!(error(
+        BITMAPINFOHEADER {
+            biCompression: 99,
+            ..header
+        },
+        &table,
+        &pixels
+    )
+    .contains("biCompression 99"));
+    assert!(error(
+        BITMAPINFOHEADER {
+            biPlanes: 0,
+            ..header
+        },
+        &table,
+        &pixels
+    )
+    .contains("biPlanes"));
+    // more palette entries than the block holds, or than the depth allows.
+    assert!(error(
+        BITMAPINFOHEADER {
+            biClrUsed: 200,
+            ..header
+        },
+        &table,
+        &pixels
+    )
+    .contains("biClrUsed 200"));
+    assert!(error(
+        BITMAPINFOHEADER {
+            biClrUsed: 300,
+            ..header
+        },
+        &table,
+        &pixels
+    )
+    .contains("300"));
+    // run-length encoding of the wrong depth.
+    assert!(error(
+        BITMAPINFOHEADER {
+            biCompression: BI_RLE4.0,
+            biSizeImage: 8,
+            ..header
+        },
+        &table,
+        &pixels
+    )
+    .contains("biCompression 2"));
+    // garbage in biSize.
+    let mut blob = dib_blob(header, &table, &pixels);
+    blob[..4].copy_from_slice(&0xdead_beefu32.to_le_bytes());
+    assert!(Dib::parse(&blob).is_err());
+}
+
+#[test]
+fn parse_dib_zero_size_image_test() {
+    // 53 pixels take 159 bytes, padded to 160 per row.
+    let (width, height) = (53, 10);
+    let pixels: Vec<u8> = (0..height)
+        .flat_map(|y| {
+            (0..160).map(move |x| {
+                if x < 159 {
+                    (y * 10 + x % 3) as u8
+                } else {
+                    0xee
+                }
+            })
+        })
+        .collect();
+    let blob = dib_blob(
+        BITMAPINFOHEADER {
+            biSize: INFO_HEADER_SIZE as u32,
+            biWidth: width,
+            biHeight: height,
+            biPlanes: 1,
+            biBitCount: 24,
+            biSizeImage: 0,
+            ..Default::default()
+        },
+        &[],
+        &pixels,
+    );
+    let bgra = Dib::parse(&blob).unwrap().to_bgra().unwrap();
+    assert_eq!(bgra.len(), 53 * 10 * 4);
+    // bottom-up, so the last stored row comes first and no padding leaks in.
+    assert_eq!(bgra[..8], [90, 91, 92, 255, 90, 91, 92, 255]);
+    assert_eq!(bgra[bgra.len() - 4..], [0, 1, 2, 255]);
+    assert!(!bgra.contains(&0xee));
+}
+
+#[test]
+fn parse_dib_core_header_test() {
+    // 3x1 at 8 bpp, the pixels follow a 256 entry RGBTRIPLE table.
+    let mut blob = [12u32.to_le_bytes(), [3, 0, 1, 0], [1, 0, 8, 0]].concat();
+    let mut table = vec![0; 256 * 3];
+    table[3..6].copy_from_slice(&[10, 20, 30]);
+    table[6..9].copy_from_slice(&[40, 50, 60]);
+    blob.extend(table);
+    blob.extend([2, 1, 0, 0]);
+
+    let dib = Dib::parse(&blob).unwrap();
+    assert_eq!((dib.width(), dib.height()), (3, 1));
+    assert_eq!(
+        dib.to_bgra().unwrap(),
+        [40, 50, 60, 255, 10, 20, 30, 255, 0, 0, 0, 255]
+    );
+
+    // truncated table.
+    assert!(Dib::parse(&blob[..100]).is_err());
+}
+
+#[test]
+fn parse_dib_header_size_test() {
+    let mut blob = vec![0; 200];
+    for size in [0u32, 4, 16, 39, 64, 125, 4096] {
+        blob[..4].copy_from_slice(&size.to_le_bytes());
+        assert!(Dib::parse(&blob).is_err(), "{size}");
+    }
+}
+
+#[test]
+fn to_bmp_file_test() {
+    let offset = |dib: &[u8]| {
+        let file = to_bmp_file(dib).unwrap();
+        assert_eq!(&file[..2], b"BM");
+        assert_eq!(file[2..6], ((dib.len() + 14) as u32).to_le_bytes());
+        assert_eq!(&file[14..], dib);
+        u32::from_le_bytes([file[10], file[11], file[12], file[13]])
+    };
+    let header = |bits: u16, compression: u32, colors: u32| {
+        dib_blob(
+            BITMAPINFOHEADER {
+                biSize: INFO_HEADER_SIZE as u32,
+                biBitCount: bits,
+                biCompression: compression,
+                biClrUsed: colors,
+                ..Default::default()
+            },
+            &[0; 1024],
+            &[],
+        )
+    };
+    assert_eq!(offset(&header(24, 0, 0)), 14 + 40);
+    assert_eq!(offset(&header(8, 0, 0)), 14 + 40 + 1024);
+    assert_eq!(offset(&header(2, 0, 0)), 14 + 40 + 16);
+    assert_eq!(offset(&header(8, 1, 16)), 14 + 40 + 64);
+    assert_eq!(offset(&header(32, 3, 0)), 14 + 40 + 12);
+
+    let mut core = [12u32.to_le_bytes(), [1, 0, 1, 0], [1, 0, 4, 0]].concat();
+    core.extend([0; 48]);
+    assert_eq!(offset(&core), 14 + 12 + 48);
+
+    assert!(to_bmp_file(&[40, 0]).is_err());
+    assert!(to_bmp_file(&header(8, 0, 1000)[..100]).is_err());
+}
+
+// a 2x1 PNG, red then white, wrapped in DIBs by the tests below.
+#[cfg(test)]
+pub const TINY_PNG: [u8; 72] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x7b, 0x40, 0xe8,
+    0xdd, 0x00, 0x00, 0x00, 0x0f, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8, 0xcf, 0xc0, 0xf0,
+    0xff, 0xff, 0x7f, 0x00, 0x0b, 0xfb, 0x03, 0xfd, 0xdd, 0x17, 0x6f, 0x47, 0x00, 0x00, 0x00, 0x00,
+    0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+];
+
+#[cfg(test)]
+pub fn png_dib(width: i32, height: i32, colors: u32, size: usize) -> Vec<u8> {
+    dib_blob(
+        BITMAPINFOHEADER {
+            biSize: INFO_HEADER_SIZE as u32,
+            biWidth: width,
+            biHeight: height,
+            biPlanes: 1,
+            biCompression: BI_PNG.0,
+            biSizeImage: size as u32,
+            biClrUsed: colors,
+            ..Default::default()
+        },
+        &vec![0; colors as usize * 4],
+        &TINY_PNG,
+    )
+}
+
+#[test]
+fn encoded_payload_test() {
+    let dib = png_dib(2, 1, 0, TINY_PNG.len());
+    assert_eq!(encoded_payload(&dib).unwrap(), Some(TINY_PNG.as_slice()));
+    // a color table before the stream is skipped.
+    let dib = png_dib(2, 1, 2, TINY_PNG.len());
+    assert_eq!(encoded_payload(&dib).unwrap(), Some(TINY_PNG.as_slice()));
+
+    // not an encoded DIB.
+    let header = BITMAPINFOHEADER {
+        biSize: INFO_HEADER_SIZE as u32,
+        biBitCount: 24,
+        ..Default::default()
+    };
+    assert_eq!(
+        encoded_payload(&dib_blob(header, &[], &[0; 4])).unwrap(),
+        None
+    );
+    let core = [12u32.to_le_bytes(), [1, 0, 1, 0], [1, 0, 24, 0]].concat();
+    assert_eq!(encoded_payload(&core).unwrap(), None);
+
+    // missing or overlong stream size.
+    assert!(encoded_payload(&png_dib(2, 1, 0, 0)).is_err());
+    assert!(encoded_payload(&png_dib(2, 1, 0, TINY_PNG.len() + 1)).is_err());
+}
+
+#[test]
+fn bit_iterator_test() {
+    let s: [u8; 4] = [0b1001_1110, 0b1100_1100, 0, 0];
+    let mut iter = BitIterator::new(&s).take(10);
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next(), Some(0));
+    assert_eq!(iter.next(), Some(0));
+    assert_eq!(iter.next(), Some(1));
+
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next(), Some(0));
+
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next(), None);
+}
+
+// a small bitmap and the pixels it should convert to.
+#[cfg(test)]
+struct Fixture {
+    name: &'static str,
+    width: i32,
+    // negative for top-down rows, as in biHeight.
+    height: i32,
+    bits_per_pixel: u16,
+    table: &'static [u8],
+    // rows as stored, padded to 4 bytes; 0xee marks padding.
+    pixels: &'static [u8],
+    // top row first.
+    expected: &'static [[u8; 4]],
+}
+
+#[test]
+fn fixtures_test() {
+    const K: [u8; 4] = [0, 0, 0, 255];
+    const W: [u8; 4] = [255, 255, 255, 255];
+    const R: [u8; 4] = [0, 0, 255, 255];
+    const G: [u8; 4] = [0, 255, 0, 255];
+    const B: [u8; 4] = [255, 0, 0, 255];
+    const MONO: &[u8] = &[0, 0, 0, 0, 255, 255, 255, 0];
+    const KWR: &[u8] = &[0, 0, 0, 0, 255, 255, 255, 0, 0, 0, 255, 0];
+
+    let fixtures = [
+        Fixture {
+            name: "1 bpp bottom-up",
+            width: 3,
+            height: 2,
+            bits_per_pixel: 1,
+            table: MONO,
+            pixels: &[0b0010_0000, 0, 0, 0, 0b1010_0000, 0, 0, 0],
+            expected: &[W, K, W, K, K, W],
+        },
+        Fixture {
+            name: "1 bpp top-down",
+            width: 3,
+            height: -2,
+            bits_per_pixel: 1,
+            table: MONO,
+            pixels: &[0b1010_0000, 0, 0, 0, 0b0010_0000, 0, 0, 0],
+            expected: &[W, K, W, K, K, W],
+        },
+        Fixture {
+            name: "1 bpp over a byte",
+            width: 9,
+            height: 1,
+            bits_per_pixel: 1,
+            table: MONO,
+            pixels: &[0b1000_0001, 0b1111_1111, 0xee, 0xee],
+            expected: &[W, K, K, K, K, K, K, W, W],
+        },
+        Fixture {
+            name: "4 bpp bottom-up, odd width",
+            width: 3,
+            height: 2,
+            bits_per_pixel: 4,
+            table: KWR,
+            pixels: &[0x00, 0x2f, 0xee, 0xee, 0x21, 0x0f, 0xee, 0xee],
+            expected: &[R, W, K, K, K, R],
+        },
+        Fixture {
+            name: "4 bpp top-down",
+            width: 2,
+            height: -2,
+            bits_per_pixel: 4,
+            table: KWR,
+            pixels: &[0x12, 0xee, 0xee, 0xee, 0x20, 0xee, 0xee, 0xee],
+            expected: &[W, R, R, K],
+        },
+        Fixture {
+            name: "8 bpp top-down, padded",
+            width: 5,
+            height: -2,
+            bits_per_pixel: 8,
+            table: KWR,
+            pixels: &[
+                0, 1, 2, 1, 0, 0xee, 0xee, 0xee, //
+                2, 2, 2, 2, 2, 0xee, 0xee, 0xee,
+            ],
+            expected: &[K, W, R, W, K, R, R, R, R, R],
+        },
+        Fixture {
+            name: "8 bpp bottom-up",
+            width: 4,
+            height: 2,
+            bits_per_pixel: 8,
+            table: KWR,
+            pixels: &[0, 0, 1, 1, 2, 1, 2, 1],
+            expected: &[R, W, R, W, K, K, W, W],
+        },
+        Fixture {
+            name: "24 bpp bottom-up, padded",
+            width: 2,
+            height: 2,
+            bits_per_pixel: 24,
+            table: &[],
+            pixels: &[
+                255, 0, 0, 255, 255, 255, 0xee, 0xee, //
+                0, 0, 255, 0, 255, 0, 0xee, 0xee,
+            ],
+            expected: &[R, G, B, W],
+        },
+        Fixture {
+            name: "24 bpp top-down, padded",
+            width: 3,
+            height: -1,
+            bits_per_pixel: 24,
+            table: &[],
+            pixels: &[0, 0, 255, 0, 255, 0, 255, 0, 0, 0xee, 0xee, 0xee],
+            expected: &[R, G, B],
+        },
+        Fixture {
+            name: "24 bpp, no padding",
+            width: 4,
+            height: 1,
+            bits_per_pixel: 24,
+            table: &[],
+            pixels: &[0, 0, 0, 255, 255, 255, 0, 0, 255, 0, 0, 0],
+            expected: &[K, W, R, K],
+        },
+        Fixture {
+            name: "32 bpp bottom-up",
+            width: 2,
+            height: 2,
+            bits_per_pixel: 32,
+            table: &[],
+            pixels: &[
+                255, 0, 0, 255, 255, 255, 255, 255, //
+                0, 0, 255, 255, 0, 255, 0, 128,
+            ],
+            expected: &[R, [0, 255, 0, 128], B, W],
+        },
+        Fixture {
+            name: "32 bpp top-down",
+            width: 1,
+            height: -2,
+            bits_per_pixel: 32,
+            table: &[],
+            pixels: &[0, 0, 255, 255, 255, 0, 0, 255],
+            expected: &[R, B],
+        },
+    ];
+
+    for fixture in fixtures {
+        let name = fixture.name;
+        let rows = fixture.height.unsigned_abs() as usize;
+        assert_eq!(
+            fixture.pixels.len(),
+            stride(fixture.width, fixture.bits_per_pixel) * rows,
+            "{name}"
+        );
+        let blob = dib_blob(
+            BITMAPINFOHEADER {
+                biSize: INFO_HEADER_SIZE as u32,
+                biWidth: fixture.width,
+                biHeight: fixture.height,
+                biPlanes: 1,
+                biBitCount: fixture.bits_per_pixel,
+                biClrUsed: (fixture.table.len() / 4) as u32,
+                ..Default::default()
+            },
+            fixture.table,
+            fixture.pixels,
+        );
+        let dib = Dib::parse(&blob).unwrap_or_else(|e| panic!("{name}: {e}"));
+        assert_eq!(
+            (dib.width(), dib.height()),
+            (fixture.width, rows as i32),
+            "{name}"
+        );
+        assert_eq!(dib.to_bgra().unwrap(), fixture.expected.concat(), "{name}");
+    }
+}
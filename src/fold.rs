@@ -0,0 +1,180 @@
+//! Folding the width and compatibility forms of characters in recognized text, mostly
+//! Japanese, which mixes `ＡＢＣ１２３` in with `ABC123`. Width folding is what Unicode
+//! NFKC does to the full-width Latin letters, digits and punctuation and to half-width
+//! katakana, and nothing else. Compatibility folding also takes apart what NFKC takes
+//! apart most often in recognized text, some of it in ways that may surprise: `㌔` to
+//! `キロ`, `①` to `1`, `²` to `2`, `½` to `1⁄2`, `™` to `TM`. It covers those, not all of
+//! NFKC.
+
+// full-width ASCII, `！` to `～`, is this far above ASCII.
+const FULL_WIDTH_OFFSET: u32 = 0xfee0;
+
+/// The full-width form of half-width katakana and punctuation, `｡` to `ﾟ` from U+FF61.
+const HALF_WIDTH_KANA: &[char] = &[
+    '。', '「', '」', '、', '・', 'ヲ', 'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ャ', 'ュ', 'ョ', 'ッ', 'ー',
+    'ア', 'イ', 'ウ', 'エ', 'オ', 'カ', 'キ', 'ク', 'ケ', 'コ', 'サ', 'シ', 'ス', 'セ', 'ソ', 'タ',
+    'チ', 'ツ', 'テ', 'ト', 'ナ', 'ニ', 'ヌ', 'ネ', 'ノ', 'ハ', 'ヒ', 'フ', 'ヘ', 'ホ', 'マ', 'ミ',
+    'ム', 'メ', 'モ', 'ヤ', 'ユ', 'ヨ', 'ラ', 'リ', 'ル', 'レ', 'ロ', 'ワ', 'ン', '\u{3099}',
+    '\u{309a}',
+];
+
+/// Compatibility characters and what they fold to, beyond the width forms.
+const COMPATIBILITY: &[(char, &str)] = &[
+    ('\u{a0}', " "),
+    ('…', "..."),
+    ('™', "TM"),
+    ('℃', "°C"),
+    ('№', "No"),
+    ('¼', "1⁄4"),
+    ('½', "1⁄2"),
+    ('¾', "3⁄4"),
+    ('ﬀ', "ff"),
+    ('ﬁ', "fi"),
+    ('ﬂ', "fl"),
+    ('ﬃ', "ffi"),
+    ('ﬄ', "ffl"),
+    ('ﬅ', "st"),
+    ('ﬆ', "st"),
+    ('㈱', "(株)"),
+    ('㈲', "(有)"),
+    ('㍻', "平成"),
+    ('㍼', "昭和"),
+    ('㍽', "大正"),
+    ('㍾', "明治"),
+    ('㋿', "令和"),
+    ('㌔', "キロ"),
+    ('㌘', "グラム"),
+    ('㌢', "センチ"),
+    ('㌧', "トン"),
+    ('㌫', "パーセント"),
+    ('㍉', "ミリ"),
+    ('㍍', "メートル"),
+    ('㍑', "リットル"),
+    ('㎎', "mg"),
+    ('㎏', "kg"),
+    ('㎜', "mm"),
+    ('㎝', "cm"),
+    ('㎞', "km"),
+    ('㎡', "m2"),
+    ('㏄', "cc"),
+];
+const ROMAN: &[&str] = &[
+    "I", "II", "III", "IV", "V", "VI", "VII", "VIII", "IX", "X", "XI", "XII",
+];
+const SUPERSCRIPTS: &[char] = &['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// The half-width form of `c`, None if it has none.
+fn half_width(c: char) -> Option<char> {
+    match c {
+        '！'..='～' => char::from_u32(c as u32 - FULL_WIDTH_OFFSET),
+        '\u{3000}' => Some(' '),
+        '￠' => Some('¢'),
+        '￡' => Some('£'),
+        '￢' => Some('¬'),
+        '￣' => Some('¯'),
+        '￤' => Some('¦'),
+        '￥' => Some('¥'),
+        '￦' => Some('₩'),
+        _ => None,
+    }
+}
+
+/// The full-width form of the half-width katakana `c`, None if it isn't one.
+fn full_width_kana(c: char) -> Option<char> {
+    HALF_WIDTH_KANA
+        .get((c as u32).checked_sub(0xff61)? as usize)
+        .copied()
+}
+
+/// `kana` with the voiced sound mark `mark`, U+3099, or semi-voiced one, U+309A, e.g.
+/// `ガ` for `カ`. None if it doesn't take it.
+fn voiced(kana: char, mark: char) -> Option<char> {
+    let c = kana as u32;
+    let next = match mark {
+        // カ to ド, ハ to ホ and ウ take the voiced mark, the voiced form next after them.
+        '\u{3099}' => match kana {
+            'ウ' => return Some('ヴ'),
+            'カ'..='ヂ' if !c.is_multiple_of(2) => c + 1,
+            'ツ'..='ド' if c.is_multiple_of(2) => c + 1,
+            'ハ'..='ホ' if (c - 'ハ' as u32).is_multiple_of(3) => c + 1,
+            'ワ' => return Some('ヷ'),
+            'ヲ' => return Some('ヺ'),
+            _ => return None,
+        },
+        '\u{309a}' => match kana {
+            'ハ'..='ホ' if (c - 'ハ' as u32).is_multiple_of(3) => c + 2,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    char::from_u32(next)
+}
+
+/// What the compatibility character `c` folds to, None if it isn't one.
+fn compatibility_form(c: char) -> Option<String> {
+    if let Some(&(_, folded)) = COMPATIBILITY.iter().find(|&&(from, _)| from == c) {
+        return Some(folded.to_owned());
+    }
+    if let Some(digit) = SUPERSCRIPTS.iter().position(|&s| s == c) {
+        return Some(digit.to_string());
+    }
+    let number = |first: char| c as u32 - first as u32 + 1;
+    Some(match c {
+        '₀'..='₉' => (number('₀') - 1).to_string(),
+        '①'..='⑳' => number('①').to_string(),
+        '⑴'..='⒇' => format!("({})", number('⑴')),
+        '⒈'..='⒛' => format!("{}.", number('⒈')),
+        'Ⅰ'..='Ⅻ' => ROMAN[number('Ⅰ') as usize - 1].to_owned(),
+        'ⅰ'..='ⅻ' => ROMAN[number('ⅰ') as usize - 1].to_lowercase(),
+        _ => return None,
+    })
+}
+
+/// `text` with full-width ASCII and the ideographic space made half-width, and
+/// half-width katakana full-width, their sound marks put together with them as NFKC
+/// does. With `compatibility`, the compatibility characters are folded too.
+pub fn fold(text: &str, compatibility: bool) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let Some(c) = half_width(c) {
+            folded.push(c);
+        } else if let Some(kana) = full_width_kana(c) {
+            // a sound mark makes one character with the kana before it, if it takes it.
+            let last = folded.chars().next_back();
+            match last.and_then(|last| voiced(last, kana)) {
+                Some(voiced) => {
+                    folded.pop();
+                    folded.push(voiced);
+                }
+                None => folded.push(kana),
+            }
+        } else if let Some(expanded) = compatibility.then(|| compatibility_form(c)).flatten() {
+            folded.push_str(&expanded);
+        } else {
+            folded.push(c);
+        }
+    }
+    folded
+}
+
+#[test]
+fn fold_test() {
+    let text = "ＡＢＣ１２３　（株）ｶﾞｲﾄﾞ､ﾊﾟｰﾄ２　￥５００";
+    assert_eq!(fold(text, false), "ABC123 (株)ガイド、パート2 ¥500");
+    assert_eq!(fold(text, true), fold(text, false));
+    // already folded text, and what only compatibility folds, stay.
+    let text = "ABC 日本語、ガイド ① ㌔ ﬁle x² ½";
+    assert_eq!(fold(text, false), text);
+    // sound marks on kana that don't take them stay marks.
+    assert_eq!(fold("ｱﾞﾝﾟ", false), "ア\u{3099}ン\u{309a}");
+    assert_eq!(fold("ｳﾞｧｲｵﾘﾝ ﾜﾞ", false), "ヴァイオリン ヷ");
+}
+
+#[test]
+fn compatibility_test() {
+    let text = "①②⑳ ⑴ ⒈ Ⅳ ⅻ x²+y₁ ½ ㌔㍍ ㈱ ﬁle 30℃ No™ … ＡＢ";
+    assert_eq!(
+        fold(text, true),
+        "1220 (1) 1. IV xii x2+y1 1⁄2 キロメートル (株) file 30°C NoTM ... AB"
+    );
+}
@@ -0,0 +1,93 @@
+//! Furigana, the small kana reading written over the kanji of Japanese text, comes out of
+//! the engine as lines of its own between the lines of text. Such a line is told by being
+//! well below the usual height, all kana, and sitting right on top of a line of the usual
+//! height over the same stretch. Small text with nothing under it, like a footnote, stays.
+
+use super::paragraph::LineBox;
+
+// lines at most this share of the usual height may be furigana.
+const RUBY_HEIGHT: f32 = 0.7;
+// lines at least this share of the usual height may carry furigana.
+const BASE_HEIGHT: f32 = 0.8;
+// furigana is at most this many of the usual line heights above its line.
+const RUBY_GAP: f32 = 0.5;
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}')
+}
+
+/// Whether each of `lines`, its box and text, is furigana.
+pub fn furigana(lines: &[(LineBox, &str)]) -> Vec<bool> {
+    let mut heights: Vec<f32> = lines.iter().map(|(b, _)| b.bottom - b.top).collect();
+    heights.sort_by(f32::total_cmp);
+    let Some(&height) = heights.get(heights.len() / 2) else {
+        return Vec::new();
+    };
+    lines
+        .iter()
+        .map(|(ruby, text)| {
+            let kana =
+                text.chars().any(is_kana) && text.chars().all(|c| is_kana(c) || c.is_whitespace());
+            kana && ruby.bottom - ruby.top <= height * RUBY_HEIGHT
+                && lines.iter().any(|(base, _)| {
+                    let gap = base.top - ruby.bottom;
+                    base.bottom - base.top >= height * BASE_HEIGHT
+                        && (-height * RUBY_GAP..=height * RUBY_GAP).contains(&gap)
+                        && base.left < ruby.right
+                        && ruby.left < base.right
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn lines(lines: &[(f32, f32, f32, f32, &'static str)]) -> Vec<(LineBox, &'static str)> {
+    // by left, top, right, height and text.
+    lines
+        .iter()
+        .map(|&(left, top, right, height, text)| {
+            let line = LineBox {
+                left,
+                top,
+                right,
+                bottom: top + height,
+            };
+            (line, text)
+        })
+        .collect()
+}
+
+#[test]
+fn furigana_test() {
+    // readings over the kanji of two lines, as the engine orders them.
+    let page = lines(&[
+        (20.0, 0.0, 60.0, 10.0, "かんじ"),
+        (0.0, 12.0, 300.0, 20.0, "漢字の読み方を学ぶ"),
+        (100.0, 36.0, 140.0, 10.0, "にほんご"),
+        (0.0, 48.0, 300.0, 20.0, "日本語は難しい"),
+        (0.0, 72.0, 300.0, 20.0, "けれども楽しい"),
+    ]);
+    assert_eq!(furigana(&page), [true, false, true, false, false]);
+    assert!(furigana(&[]).is_empty());
+}
+
+#[test]
+fn small_text_test() {
+    let page = lines(&[
+        (0.0, 0.0, 300.0, 20.0, "本文の一行目です"),
+        (0.0, 24.0, 300.0, 20.0, "本文の二行目です"),
+        (0.0, 48.0, 300.0, 20.0, "本文の三行目です"),
+        // a kana footnote with nothing under it.
+        (0.0, 100.0, 200.0, 10.0, "ちゅうしゃく"),
+        // small kanji over a line, like a caption.
+        (0.0, 120.0, 60.0, 10.0, "図一"),
+        (0.0, 132.0, 300.0, 20.0, "図の説明です"),
+        // small kana off to the side of the line under it.
+        (400.0, 160.0, 440.0, 10.0, "よみ"),
+        (0.0, 172.0, 300.0, 20.0, "最後の行です"),
+        // kana well above the line under it.
+        (0.0, 200.0, 60.0, 10.0, "はなれた"),
+        (0.0, 230.0, 300.0, 20.0, "終わり"),
+    ]);
+    assert!(furigana(&page).iter().all(|&ruby| !ruby));
+}
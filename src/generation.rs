@@ -0,0 +1,48 @@
+/// Numbers each recognition started so that only the newest one's result is applied.
+/// Starting one supersedes all earlier ones, whether they are still queued, running,
+/// cancelled or done. Owned by the UI thread, which alone decides what gets applied: a
+/// result finishing just as it's cancelled is told apart here, not by the engine.
+#[derive(Debug, Default)]
+pub struct Generations {
+    current: u64,
+}
+
+impl Generations {
+    pub const fn new() -> Self {
+        Self { current: 0 }
+    }
+
+    /// Starts a new generation, superseding the earlier ones.
+    pub fn start(&mut self) -> u64 {
+        self.current += 1;
+        self.current
+    }
+
+    /// Whether the result of `generation` should be applied.
+    pub fn is_current(&self, generation: u64) -> bool {
+        generation == self.current
+    }
+}
+
+#[test]
+fn generations_test() {
+    let mut generations = Generations::new();
+    // nothing started yet.
+    assert!(!generations.is_current(1));
+
+    let first = generations.start();
+    assert!(generations.is_current(first));
+
+    // a newer image arrives before the first one is done.
+    let second = generations.start();
+    assert!(!generations.is_current(first));
+    assert!(generations.is_current(second));
+
+    // results coming back in any order, only the newest is applied.
+    let third = generations.start();
+    let applied: Vec<_> = [third, first, second]
+        .into_iter()
+        .filter(|&generation| generations.is_current(generation))
+        .collect();
+    assert_eq!(applied, [third]);
+}
@@ -0,0 +1,89 @@
+//! "Copy as file": the text is written to a .txt file in %TEMP% and put on the clipboard
+//! as CF_HDROP, for apps that only accept dropped or pasted files. The file is reused for
+//! the whole session and removed on exit.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use windows::Win32::UI::Shell::DROPFILES;
+
+const BOM: &str = "\u{feff}";
+
+/// The file the text is written to, one per running instance.
+pub fn temp_path() -> PathBuf {
+    env::temp_dir().join(format!("ocr-{}.txt", process::id()))
+}
+
+/// Writes `text` as UTF-8 with a BOM to the temp file and returns its path.
+pub fn write_temp(text: &str) -> Result<PathBuf> {
+    let path = temp_path();
+    fs::write(&path, format!("{BOM}{text}"))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Removes the temp file if it was ever written.
+pub fn remove_temp() {
+    fs::remove_file(temp_path()).ok();
+}
+
+/// Builds the CF_HDROP block for `paths`: a DROPFILES header followed by the wide paths,
+/// each null-terminated, and one more null ending the list.
+pub fn dropfiles(paths: &[&Path]) -> Vec<u8> {
+    let header = size_of::<DROPFILES>();
+    let mut bytes = Vec::with_capacity(header);
+    // pFiles, the offset of the list.
+    bytes.extend((header as u32).to_le_bytes());
+    // pt and fNC, no drop point.
+    bytes.extend([0; 12]);
+    // fWide
+    bytes.extend(1u32.to_le_bytes());
+
+    for path in paths {
+        for unit in path.as_os_str().to_string_lossy().encode_utf16() {
+            bytes.extend(unit.to_le_bytes());
+        }
+        bytes.extend([0; 2]);
+    }
+    bytes.extend([0; 2]);
+    bytes
+}
+
+#[test]
+fn dropfiles_test() {
+    let bytes = dropfiles(&[Path::new(r"C:\t\a.txt")]);
+    assert_eq!(size_of::<DROPFILES>(), 20);
+    assert_eq!(&bytes[..4], [20, 0, 0, 0]);
+    assert_eq!(&bytes[4..16], [0; 12]);
+    assert_eq!(&bytes[16..20], [1, 0, 0, 0]);
+
+    let list: Vec<u16> = bytes[20..]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let expected: Vec<u16> = "C:\\t\\a.txt\0\0".encode_utf16().collect();
+    assert_eq!(list, expected);
+
+    // several paths, non-ASCII ones included.
+    let bytes = dropfiles(&[Path::new(r"C:\a.txt"), Path::new(r"C:\結果.txt")]);
+    let list: Vec<u16> = bytes[20..]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let expected: Vec<u16> = "C:\\a.txt\0C:\\結果.txt\0\0".encode_utf16().collect();
+    assert_eq!(list, expected);
+
+    // an empty list is the header and the terminating null.
+    assert_eq!(dropfiles(&[]).len(), 22);
+}
+
+#[test]
+fn write_temp_test() {
+    let path = write_temp("日本\r\n").unwrap();
+    assert_eq!(path, temp_path());
+    assert_eq!(fs::read(&path).unwrap(), "\u{feff}日本\r\n".as_bytes());
+    remove_temp();
+    assert!(!path.exists());
+}
@@ -0,0 +1,120 @@
+use std::fmt::Write;
+
+const PREFIX: &str = "<html><body>\r\n<!--StartFragment-->";
+const SUFFIX: &str = "<!--EndFragment-->\r\n</body></html>";
+
+/// Frames `text` as a CF_HTML ("HTML Format") payload. Lines are separated by `<br>`, and
+/// blank lines start a new paragraph.
+pub fn cf_html(text: &str) -> String {
+    // offsets are fixed width, so the header length doesn't depend on them.
+    let header = |start_html: usize, end_html: usize, start_fragment: usize, end_fragment| {
+        format!(
+            "Version:0.9\r\n\
+             StartHTML:{start_html:010}\r\n\
+             EndHTML:{end_html:010}\r\n\
+             StartFragment:{start_fragment:010}\r\n\
+             EndFragment:{end_fragment:010}\r\n"
+        )
+    };
+    let fragment = fragment(text);
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + PREFIX.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + SUFFIX.len();
+    format!(
+        "{}{PREFIX}{fragment}{SUFFIX}",
+        header(start_html, end_html, start_fragment, end_fragment)
+    )
+}
+
+fn fragment(text: &str) -> String {
+    let mut html = String::new();
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    for paragraph in lines.split(|line| line.is_empty()) {
+        if paragraph.is_empty() {
+            continue;
+        }
+        html.push_str("<p>");
+        for (i, line) in paragraph.iter().enumerate() {
+            if i > 0 {
+                html.push_str("<br>");
+            }
+            escape(line, &mut html);
+        }
+        html.push_str("</p>");
+    }
+    html
+}
+
+fn escape(text: &str, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            c if c.is_control() => write!(html, "&#{};", c as u32).unwrap_or_default(),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+fn offset(payload: &str, key: &str) -> usize {
+    let line = payload
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .unwrap();
+    assert_eq!(line.len(), 10);
+    line.parse().unwrap()
+}
+
+#[test]
+fn cf_html_test() {
+    for text in [
+        "",
+        "Hello world\r\n",
+        "日本語の\r\nテキスト\r\n\r\n二段落目\r\n",
+    ] {
+        let payload = cf_html(text);
+        let start_html = offset(&payload, "StartHTML");
+        let end_html = offset(&payload, "EndHTML");
+        let start_fragment = offset(&payload, "StartFragment");
+        let end_fragment = offset(&payload, "EndFragment");
+
+        // offsets count UTF-8 bytes.
+        assert!(payload[..start_html].ends_with("\r\n"));
+        assert!(payload[start_html..].starts_with("<html>"));
+        assert_eq!(end_html, payload.len());
+        assert!(payload[..start_fragment].ends_with("<!--StartFragment-->"));
+        assert!(payload[end_fragment..].starts_with("<!--EndFragment-->"));
+        assert_eq!(&payload[start_fragment..end_fragment], fragment(text));
+    }
+}
+
+#[test]
+fn cf_html_header_test() {
+    assert_eq!(
+        cf_html("Hello"),
+        "Version:0.9\r\n\
+         StartHTML:0000000105\r\n\
+         EndHTML:0000000185\r\n\
+         StartFragment:0000000139\r\n\
+         EndFragment:0000000151\r\n\
+         <html><body>\r\n<!--StartFragment--><p>Hello</p><!--EndFragment-->\r\n</body></html>"
+    );
+}
+
+#[test]
+fn fragment_test() {
+    assert_eq!(fragment(""), "");
+    assert_eq!(fragment("a\r\nb\r\n"), "<p>a<br>b</p>");
+    assert_eq!(
+        fragment("a\r\n\r\n\r\nb\r\nc\r\n[lang:en-US]"),
+        "<p>a</p><p>b<br>c<br>[lang:en-US]</p>"
+    );
+    assert_eq!(
+        fragment("<b> & \"x\"\tend"),
+        "<p>&lt;b&gt; &amp; &quot;x&quot;&#9;end</p>"
+    );
+}
@@ -0,0 +1,167 @@
+//! Joins words hyphenated across line breaks, as in text scanned from paper:
+//! `recogni-` ending one line and `tion` starting the next become `recognition`.
+
+use anyhow::{bail, Error};
+use std::fmt;
+use std::str::FromStr;
+
+// the first parts of compounds that keep their hyphen, e.g. `well-known`.
+const COMPOUND_PREFIXES: &[&str] = &[
+    "all", "cross", "ex", "full", "half", "high", "long", "low", "non", "quasi", "self", "semi",
+    "short", "so", "well",
+];
+// and the last ones, e.g. `cloud-based`.
+const COMPOUND_SUFFIXES: &[&str] = &[
+    "based", "driven", "free", "friendly", "level", "like", "related", "specific", "wide",
+];
+
+/// What to do with a hyphen ending a line before a word continuing in lowercase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dehyphenate {
+    /// Leave the lines as recognized.
+    #[default]
+    Never,
+    /// Join the word, dropping the hyphen.
+    Always,
+    /// Join the word, keeping the hyphen of what looks like a compound.
+    Smart,
+}
+
+impl FromStr for Dehyphenate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "always" => Ok(Self::Always),
+            "smart" => Ok(Self::Smart),
+            _ => bail!("unknown dehyphenation mode {s}."),
+        }
+    }
+}
+
+impl fmt::Display for Dehyphenate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Never => "never",
+            Self::Always => "always",
+            Self::Smart => "smart",
+        })
+    }
+}
+
+/// Whether `head-tail`, split at a line end, is a compound rather than a single word.
+fn is_compound(head: &str, tail: &str) -> bool {
+    let word = head.rsplit('-').next().unwrap_or(head);
+    // `e-mail`, `state-of-the-art`, `ACME-based`.
+    word.chars().count() == 1
+        || word != head
+        || !word.chars().any(char::is_lowercase)
+        || COMPOUND_PREFIXES.contains(&word.to_lowercase().as_str())
+        || COMPOUND_SUFFIXES.contains(&tail.trim_end_matches(|c: char| !c.is_alphabetic()))
+}
+
+/// If `line` ends with a word broken off with a hyphen and `next` starts with the rest
+/// of it, whether `mode` keeps the hyphen when joining them. None if it doesn't, e.g. for
+/// a range like `10-` or a dash.
+pub fn keeps_hyphen(line: &str, next: &str, mode: Dehyphenate) -> Option<bool> {
+    let start = line.strip_suffix(['-', '\u{2010}', '\u{ad}'])?;
+    let head = start.rsplit(char::is_whitespace).next().unwrap_or_default();
+    let tail = next.split(char::is_whitespace).next().unwrap_or_default();
+    let broken = head.chars().last().is_some_and(char::is_alphabetic)
+        && tail.chars().next().is_some_and(char::is_lowercase);
+    broken.then(|| match mode {
+        Dehyphenate::Never => true,
+        Dehyphenate::Always => false,
+        Dehyphenate::Smart => is_compound(head, tail),
+    })
+}
+
+/// Joins the words of CRLF-terminated `text` that are hyphenated across a line break, as
+/// `mode` says. The rest of the following line stays on its own line.
+pub fn dehyphenate(text: &str, mode: Dehyphenate) -> String {
+    if mode == Dehyphenate::Never {
+        return text.to_owned();
+    }
+    let mut lines: Vec<String> = text.split("\r\n").map(str::to_owned).collect();
+    // the lines whose only word moved up, to drop.
+    let mut emptied = vec![false; lines.len()];
+    for i in 1..lines.len() {
+        let Some(keep) = keeps_hyphen(&lines[i - 1], &lines[i], mode) else {
+            continue;
+        };
+        let next = &lines[i];
+        let end = next.find(char::is_whitespace).unwrap_or(next.len());
+        let mut joined = lines[i - 1].clone();
+        if !keep {
+            joined.pop();
+        }
+        joined.push_str(&next[..end]);
+        let rest = next[end..].trim_start().to_owned();
+        emptied[i] = rest.is_empty();
+        lines[i - 1] = joined;
+        lines[i] = rest;
+    }
+    lines
+        .into_iter()
+        .zip(emptied)
+        .filter(|(_, emptied)| !emptied)
+        .map(|(line, _)| line)
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+#[test]
+fn dehyphenate_test() {
+    let smart = |text| dehyphenate(text, Dehyphenate::Smart);
+    let always = |text| dehyphenate(text, Dehyphenate::Always);
+
+    assert_eq!(
+        smart("the recogni-\r\ntion of text\r\n"),
+        "the recognition\r\nof text\r\n"
+    );
+    // the whole next line moves up, and then its line is gone.
+    assert_eq!(
+        smart("recogni-\r\ntion\r\ndone\r\n"),
+        "recognition\r\ndone\r\n"
+    );
+    assert_eq!(
+        smart("a hyphen\u{2010}\r\nated word"),
+        "a hyphenated\r\nword"
+    );
+
+    // numeric ranges and dashes are left alone.
+    assert_eq!(always("pages 10-\r\n20\r\n"), "pages 10-\r\n20\r\n");
+    assert_eq!(always("pages 10-\r\nthe end"), "pages 10-\r\nthe end");
+    assert_eq!(always("wait –\r\nno"), "wait –\r\nno");
+    assert_eq!(always("wait -\r\nno"), "wait -\r\nno");
+    assert_eq!(always("see Figure-\r\n3"), "see Figure-\r\n3");
+    // a capital next is a new word, e.g. a name.
+    assert_eq!(always("Anglo-\r\nSaxon"), "Anglo-\r\nSaxon");
+
+    // compounds keep their hyphen, unless always joining.
+    assert_eq!(smart("send an e-\r\nmail"), "send an e-mail");
+    assert_eq!(always("send an e-\r\nmail"), "send an email");
+    assert_eq!(smart("well-\r\nknown"), "well-known");
+    assert_eq!(
+        smart("state-of-the-\r\nart design"),
+        "state-of-the-art\r\ndesign"
+    );
+    assert_eq!(smart("cloud-\r\nbased."), "cloud-based.");
+    assert_eq!(smart("ACME-\r\nbased"), "ACME-based");
+
+    // blank lines stay, the last one too.
+    assert_eq!(
+        smart("a\r\n\r\nbro-\r\nken\r\n\r\n"),
+        "a\r\n\r\nbroken\r\n\r\n"
+    );
+    assert_eq!(
+        dehyphenate("recogni-\r\ntion", Dehyphenate::Never),
+        "recogni-\r\ntion"
+    );
+
+    for mode in [Dehyphenate::Never, Dehyphenate::Always, Dehyphenate::Smart] {
+        assert_eq!(mode.to_string().parse::<Dehyphenate>().unwrap(), mode);
+    }
+    assert!("sometimes".parse::<Dehyphenate>().is_err());
+}
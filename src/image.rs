@@ -0,0 +1,713 @@
+use anyhow::{bail, ensure, Context, Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::str::FromStr;
+
+/// A decoded image as handed to the engine: 8-bit BGRA, top-down rows.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub bgra: Vec<u8>,
+}
+
+impl Image {
+    /// A hash of the size and pixels, telling another copy of an image from a new one.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.width, self.height, &self.bgra).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Scales the image down to `width` x `height`, each pixel the average of the ones it
+    /// covers.
+    pub fn downscale(&self, width: i32, height: i32) -> Image {
+        let (src_width, src_height) = (self.width as usize, self.height as usize);
+        let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+        // the source range covering destination index `i` of `n`, never empty.
+        let span = |i: usize, n: usize, len: usize| {
+            let start = i * len / n;
+            start..((i + 1) * len / n).max(start + 1)
+        };
+
+        let mut bgra = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let rows = span(y, height, src_height);
+            for x in 0..width {
+                let columns = span(x, width, src_width);
+                let mut sum = [0u32; 4];
+                for row in rows.clone() {
+                    let start = (row * src_width + columns.start) * 4;
+                    let end = (row * src_width + columns.end) * 4;
+                    for p in self.bgra[start..end].chunks_exact(4) {
+                        sum.iter_mut().zip(p).for_each(|(s, &v)| *s += v as u32);
+                    }
+                }
+                let count = (rows.len() * columns.len()) as u32;
+                bgra.extend(sum.map(|s| ((s + count / 2) / count) as u8));
+            }
+        }
+        Image {
+            width: width as i32,
+            height: height as i32,
+            bgra,
+        }
+    }
+}
+
+/// A rectangle of an image in pixels, stored as `x,y,width,height` in the settings file.
+/// Empty when it covers nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Region {
+    /// The region with corners `a` and `b`, in either order.
+    pub fn between(a: (i32, i32), b: (i32, i32)) -> Self {
+        Self {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: a.0.abs_diff(b.0) as i32,
+            height: a.1.abs_diff(b.1) as i32,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    /// The part of the region within a `width` x `height` image, None if nothing is.
+    pub fn clamp(&self, width: i32, height: i32) -> Option<Self> {
+        let left = self.x.clamp(0, width);
+        let top = self.y.clamp(0, height);
+        let right = self.x.saturating_add(self.width).clamp(0, width);
+        let bottom = self.y.saturating_add(self.height).clamp(0, height);
+        let region = Self::between((left, top), (right, bottom));
+        (!self.is_empty() && !region.is_empty()).then_some(region)
+    }
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("invalid region {s}."))?;
+        let [x, y, width, height] = values[..] else {
+            bail!("a region needs 4 values, not {s}.");
+        };
+        Ok(Self {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return Ok(());
+        }
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// The size of the canvas holding a `width` x `height` image rotated by `degrees`.
+pub fn rotated_size(width: i32, height: i32, degrees: f64) -> (i32, i32) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = (width as f64, height as f64);
+    // a hair under a whole pixel doesn't need another one.
+    let side = |a: f64, b: f64| ((a + b - 1e-6).ceil() as i32).max(1);
+    (
+        side(width * cos.abs(), height * sin.abs()),
+        side(width * sin.abs(), height * cos.abs()),
+    )
+}
+
+/// Rotates a `width` x `height` BGRA image clockwise by `degrees` about its center, onto
+/// a canvas large enough to hold all of it. The corners uncovered are white.
+pub fn rotate(width: i32, height: i32, bgra: &[u8], degrees: f64) -> Image {
+    let (rotated_width, rotated_height) = rotated_size(width, height, degrees);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (src_width, src_height) = (width as usize, height as usize);
+    let center = (width as f64 / 2.0, height as f64 / 2.0);
+    let rotated_center = (rotated_width as f64 / 2.0, rotated_height as f64 / 2.0);
+    // the source pixel at (x, y), white past the edges.
+    let pixel = |x: isize, y: isize| -> [f64; 3] {
+        if x < 0 || y < 0 || x as usize >= src_width || y as usize >= src_height {
+            return [255.0; 3];
+        }
+        let i = (y as usize * src_width + x as usize) * 4;
+        [bgra[i] as f64, bgra[i + 1] as f64, bgra[i + 2] as f64]
+    };
+
+    let mut rotated = Vec::with_capacity(rotated_width as usize * rotated_height as usize * 4);
+    for y in 0..rotated_height {
+        for x in 0..rotated_width {
+            // back from the center of the destination pixel to the source, pixel centers
+            // being at whole coordinates there.
+            let dx = x as f64 + 0.5 - rotated_center.0;
+            let dy = y as f64 + 0.5 - rotated_center.1;
+            let sx = dx * cos + dy * sin + center.0 - 0.5;
+            let sy = -dx * sin + dy * cos + center.1 - 0.5;
+            let (x0, y0) = (sx.floor(), sy.floor());
+            let (fx, fy) = (sx - x0, sy - y0);
+            let (x0, y0) = (x0 as isize, y0 as isize);
+            let mut bgr = [0.0; 3];
+            for (px, py, weight) in [
+                (x0, y0, (1.0 - fx) * (1.0 - fy)),
+                (x0 + 1, y0, fx * (1.0 - fy)),
+                (x0, y0 + 1, (1.0 - fx) * fy),
+                (x0 + 1, y0 + 1, fx * fy),
+            ] {
+                if weight > 0.0 {
+                    let p = pixel(px, py);
+                    bgr.iter_mut().zip(p).for_each(|(c, v)| *c += v * weight);
+                }
+            }
+            rotated.extend(bgr.map(|c| c.round().clamp(0.0, 255.0) as u8));
+            rotated.push(255);
+        }
+    }
+    Image {
+        width: rotated_width,
+        height: rotated_height,
+        bgra: rotated,
+    }
+}
+
+/// Rotates a `width` x `height` BGRA image clockwise by `turns` quarter turns, exactly.
+pub fn rotate_quarters(width: i32, height: i32, bgra: &[u8], turns: u32) -> Image {
+    let (src_width, src_height) = (width as usize, height as usize);
+    let turns = turns % 4;
+    let (rotated_width, rotated_height) = if turns % 2 == 1 {
+        (src_height, src_width)
+    } else {
+        (src_width, src_height)
+    };
+    let mut rotated = vec![0; src_width * src_height * 4];
+    for (i, p) in bgra
+        .chunks_exact(4)
+        .take(src_width * src_height)
+        .enumerate()
+    {
+        let (x, y) = (i % src_width, i / src_width);
+        let (x, y) = match turns {
+            0 => (x, y),
+            1 => (src_height - 1 - y, x),
+            2 => (src_width - 1 - x, src_height - 1 - y),
+            _ => (y, src_width - 1 - x),
+        };
+        let j = (y * rotated_width + x) * 4;
+        rotated[j..j + 4].copy_from_slice(p);
+    }
+    Image {
+        width: rotated_width as i32,
+        height: rotated_height as i32,
+        bgra: rotated,
+    }
+}
+
+/// The most common color along the edges of a `width` x `height` BGRA image, taken for
+/// its background. Colors are told apart by their upper bits, so noise doesn't split
+/// one, and the pixels of the winner averaged.
+pub fn edge_color(width: i32, height: i32, bgra: &[u8]) -> [u8; 4] {
+    let (width, height) = (width.max(0) as usize, height.max(0) as usize);
+    let row_len = width * 4;
+    let pixel = |x: usize, y: usize| {
+        let i = y * row_len + x * 4;
+        bgra.get(i..i + 4)
+    };
+    let edges = (0..width)
+        .flat_map(|x| [pixel(x, 0), pixel(x, height.saturating_sub(1))])
+        .chain((1..height.saturating_sub(1)).flat_map(|y| [pixel(0, y), pixel(width - 1, y)]))
+        .flatten();
+
+    let mut colors: HashMap<[u8; 3], ([u32; 3], u32)> = HashMap::new();
+    for p in edges {
+        let (sum, count) = colors.entry([p[0] >> 4, p[1] >> 4, p[2] >> 4]).or_default();
+        sum.iter_mut().zip(p).for_each(|(s, &v)| *s += v as u32);
+        *count += 1;
+    }
+    let Some((sum, count)) = colors.into_values().max_by_key(|&(_, count)| count) else {
+        return [255; 4];
+    };
+    let [b, g, r] = sum.map(|s| ((s + count / 2) / count) as u8);
+    [b, g, r, 255]
+}
+
+/// Puts a `width` x `height` BGRA image in the middle of a canvas `margin` pixels larger
+/// on every side, filled with `color`.
+pub fn pad(width: i32, height: i32, bgra: &[u8], margin: i32, color: [u8; 4]) -> Image {
+    let (width, height, margin) = (width as usize, height as usize, margin.max(0) as usize);
+    let padded_width = width + 2 * margin;
+    let padded_height = height + 2 * margin;
+    let mut padded = color.repeat(padded_width * padded_height);
+    for (y, row) in bgra.chunks_exact(width * 4).take(height).enumerate() {
+        let start = ((y + margin) * padded_width + margin) * 4;
+        padded[start..start + row.len()].copy_from_slice(row);
+    }
+    Image {
+        width: padded_width as i32,
+        height: padded_height as i32,
+        bgra: padded,
+    }
+}
+
+impl Image {
+    /// The part of the image within `region`, None if there is none.
+    pub fn crop(&self, region: Region) -> Option<Image> {
+        let region = region.clamp(self.width, self.height)?;
+        let row_len = self.width as usize * 4;
+        let (left, len) = (region.x as usize * 4, region.width as usize * 4);
+        let bgra = self
+            .bgra
+            .chunks_exact(row_len)
+            .skip(region.y as usize)
+            .take(region.height as usize)
+            .flat_map(|row| &row[left..left + len])
+            .copied()
+            .collect();
+        Some(Image {
+            width: region.width,
+            height: region.height,
+            bgra,
+        })
+    }
+}
+
+/// Remembers the fingerprint of the last image recognized, so copying it again doesn't
+/// repeat the result.
+#[derive(Debug, Default)]
+pub struct Repeats {
+    last: Option<u64>,
+}
+
+impl Repeats {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    pub fn is_repeat(&self, fingerprint: u64) -> bool {
+        self.last == Some(fingerprint)
+    }
+
+    /// Records the image that has just been recognized.
+    pub fn record(&mut self, fingerprint: u64) {
+        self.last = Some(fingerprint);
+    }
+
+    /// Lets the next image through even if it is the last one again.
+    pub fn forget(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Images narrower or shorter than this are not worth a recognition round-trip.
+pub const MIN_DIMENSION: i32 = 4;
+
+/// Returns true if the image has nothing the engine could recognize:
+/// it is (almost) zero-area, or every pixel has the same color.
+pub fn is_blank(width: i32, height: i32, bgra: &[u8]) -> bool {
+    is_too_small(width, height) || is_uniform(bgra)
+}
+
+/// Checks an image before anything else looks at its pixels: it must have some, as many
+/// as its size says, and be large enough to recognize.
+pub fn check_size(width: i32, height: i32, len: usize) -> Result<()> {
+    ensure!(
+        width > 0 && height > 0,
+        "the image has no pixels ({width}x{height})."
+    );
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .context("the image is too large.")?;
+    ensure!(
+        len == expected,
+        "{len} bytes of pixels for a {width}x{height} image."
+    );
+    ensure!(
+        !is_too_small(width, height),
+        "the image is too small to recognize ({width}x{height})."
+    );
+    Ok(())
+}
+
+pub fn is_too_small(width: i32, height: i32) -> bool {
+    width.abs() < MIN_DIMENSION || height.abs() < MIN_DIMENSION
+}
+
+/// Returns true if all pixels share one color. Every pixel is compared, stopping at the
+/// first that differs: a small word on a large image is easily missed by sampling, and
+/// the pass is cheap next to a recognition.
+pub fn is_uniform(bgra: &[u8]) -> bool {
+    let Some(first) = bgra.get(..4) else {
+        return true;
+    };
+    bgra.chunks_exact(4).all(|pixel| pixel == first)
+}
+
+/// Composites pixels with straight alpha over white, so transparent areas don't reach the
+/// engine as black. An alpha channel that is zero throughout is taken to be unused and
+/// the image is made opaque instead.
+pub fn flatten_alpha(bgra: &mut [u8]) {
+    let alpha = || bgra.iter().skip(3).step_by(4);
+    if alpha().all(|&a| a == 255) {
+        return;
+    }
+    if alpha().all(|&a| a == 0) {
+        bgra.iter_mut().skip(3).step_by(4).for_each(|a| *a = 255);
+        return;
+    }
+    bgra.chunks_mut(4).for_each(|p| {
+        let a = p[3] as u32;
+        for c in &mut p[..3] {
+            *c = ((*c as u32 * a + 255 * (255 - a) + 127) / 255) as u8;
+        }
+        p[3] = 255;
+    });
+}
+
+#[test]
+fn fingerprint_test() {
+    let image = |width, height, bgra: &[u8]| Image {
+        width,
+        height,
+        bgra: bgra.to_vec(),
+    };
+    let a = image(2, 1, &[1, 2, 3, 255, 4, 5, 6, 255]);
+    assert_eq!(a.fingerprint(), a.clone().fingerprint());
+    assert_ne!(
+        a.fingerprint(),
+        image(2, 1, &[1, 2, 3, 255, 4, 5, 7, 255]).fingerprint()
+    );
+    // same pixels, other shape.
+    assert_ne!(a.fingerprint(), image(1, 2, &a.bgra).fingerprint());
+}
+
+#[test]
+fn downscale_test() {
+    let (k, w) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+    // 4x2 of black and white columns.
+    let image = Image {
+        width: 4,
+        height: 2,
+        bgra: [k, w, k, w, k, w, k, w].concat(),
+    };
+    let half = image.downscale(2, 1);
+    assert_eq!((half.width, half.height), (2, 1));
+    assert_eq!(half.bgra, [128, 128, 128, 255, 128, 128, 128, 255]);
+
+    let same = image.downscale(4, 2);
+    assert_eq!(same.bgra, image.bgra);
+
+    // uneven ratios still cover every source pixel.
+    let image = Image {
+        width: 3,
+        height: 1,
+        bgra: [k, k, w].concat(),
+    };
+    assert_eq!(
+        image.downscale(2, 1).bgra,
+        [k, [128, 128, 128, 255]].concat()
+    );
+    assert_eq!(image.downscale(1, 1).bgra, [85, 85, 85, 255]);
+}
+
+#[test]
+fn rotate_test() {
+    assert_eq!(rotated_size(40, 20, 0.0), (40, 20));
+    assert_eq!(rotated_size(40, 20, 90.0), (20, 40));
+    assert_eq!(rotated_size(40, 20, -90.0), (20, 40));
+    assert_eq!(rotated_size(100, 100, 45.0), (142, 142));
+    // nothing is cropped at a slight angle either way.
+    assert_eq!(rotated_size(1000, 100, 3.0), (1004, 153));
+    assert_eq!(rotated_size(1000, 100, -3.0), (1004, 153));
+
+    let (a, b, c, d) = (
+        [1, 2, 3, 255],
+        [4, 5, 6, 255],
+        [7, 8, 9, 255],
+        [10, 11, 12, 255],
+    );
+    // a b
+    // c d
+    let bgra = [a, b, c, d].concat();
+    let image = rotate(2, 2, &bgra, 0.0);
+    assert_eq!(image.bgra, bgra);
+    let image = rotate(2, 2, &bgra, 90.0);
+    assert_eq!((image.width, image.height), (2, 2));
+    assert_eq!(image.bgra, [c, a, d, b].concat());
+
+    // a wide image turns tall.
+    let image = rotate(3, 1, &[a, b, c].concat(), -90.0);
+    assert_eq!((image.width, image.height), (1, 3));
+    assert_eq!(image.bgra, [c, b, a].concat());
+
+    // the uncovered corners are white and opaque.
+    let image = rotate(10, 10, &[[0, 0, 0, 255]; 100].concat(), 30.0);
+    assert_eq!((image.width, image.height), (14, 14));
+    assert_eq!(image.bgra[..4], [255; 4]);
+    let center = (7 * 14 + 7) * 4;
+    assert_eq!(image.bgra[center..center + 4], [0, 0, 0, 255]);
+}
+
+#[test]
+fn rotate_quarters_test() {
+    let pixel = |v: u8| [v, v + 10, v + 20, 255];
+    // 1 2 3
+    // 4 5 6
+    let bgra = [1, 2, 3, 4, 5, 6].map(pixel).concat();
+    let turned = |turns| {
+        let image = rotate_quarters(3, 2, &bgra, turns);
+        (image.width, image.height, image.bgra)
+    };
+    assert_eq!(turned(0), (3, 2, bgra.clone()));
+    assert_eq!(turned(4), (3, 2, bgra.clone()));
+    assert_eq!(turned(1), (2, 3, [4, 1, 5, 2, 6, 3].map(pixel).concat()));
+    assert_eq!(turned(2), (3, 2, [6, 5, 4, 3, 2, 1].map(pixel).concat()));
+    assert_eq!(turned(3), (2, 3, [3, 6, 2, 5, 1, 4].map(pixel).concat()));
+
+    // the same as the general rotation, tall images too.
+    let tall = rotate_quarters(3, 2, &bgra, 1);
+    for turns in 1..4 {
+        let exact = rotate_quarters(2, 3, &tall.bgra, turns);
+        let general = rotate(2, 3, &tall.bgra, turns as f64 * 90.0);
+        assert_eq!(
+            (exact.width, exact.height, exact.bgra),
+            (general.width, general.height, general.bgra)
+        );
+    }
+}
+
+#[test]
+fn edge_color_test() {
+    let (paper, ink) = ([250, 250, 250, 255], [20, 20, 20, 255]);
+    // ink touching the right edge, on paper.
+    let bgra = [
+        [paper, paper, paper, paper],
+        [paper, ink, ink, ink],
+        [paper, paper, paper, paper],
+    ]
+    .concat()
+    .concat();
+    assert_eq!(edge_color(4, 3, &bgra), paper);
+
+    // a dark theme, its background a little noisy.
+    let (dark, darker, light) = ([40, 30, 30, 255], [38, 30, 30, 255], [220, 220, 220, 255]);
+    let bgra = [[dark, darker, light], [darker, light, dark]]
+        .concat()
+        .concat();
+    assert_eq!(edge_color(3, 2, &bgra), [39, 30, 30, 255]);
+
+    assert_eq!(edge_color(1, 1, &ink), ink);
+    assert_eq!(edge_color(0, 0, &[]), [255; 4]);
+}
+
+#[test]
+fn pad_test() {
+    let (a, b, c, d, e, f) = (
+        [1, 1, 1, 255],
+        [2, 2, 2, 255],
+        [3, 3, 3, 255],
+        [4, 4, 4, 255],
+        [5, 5, 5, 255],
+        [6, 6, 6, 255],
+    );
+    let o = [9, 8, 7, 255];
+    let image = pad(3, 2, &[a, b, c, d, e, f].concat(), 1, o);
+    assert_eq!((image.width, image.height), (5, 4));
+    assert_eq!(
+        image.bgra,
+        [
+            [o, o, o, o, o],
+            [o, a, b, c, o],
+            [o, d, e, f, o],
+            [o, o, o, o, o],
+        ]
+        .concat()
+        .concat()
+    );
+
+    let image = pad(2, 1, &[a, b].concat(), 16, o);
+    assert_eq!((image.width, image.height), (34, 33));
+    let start = (16 * 34 + 16) * 4;
+    assert_eq!(image.bgra[start..start + 8], [a, b].concat());
+    assert_eq!(image.bgra.len(), 34 * 33 * 4);
+
+    let image = pad(2, 1, &[a, b].concat(), 0, o);
+    assert_eq!(image.bgra, [a, b].concat());
+}
+
+#[test]
+fn region_test() {
+    let region = Region::between((30, 5), (10, 25));
+    assert_eq!(
+        region,
+        Region {
+            x: 10,
+            y: 5,
+            width: 20,
+            height: 20
+        }
+    );
+    assert_eq!(region.to_string(), "10,5,20,20");
+    assert_eq!("10, 5,20,20".parse::<Region>().unwrap(), region);
+    assert_eq!("".parse::<Region>().unwrap(), Region::default());
+    assert_eq!(Region::default().to_string(), "");
+    assert!("1,2,3".parse::<Region>().is_err());
+    assert!("1,2,3,x".parse::<Region>().is_err());
+
+    assert_eq!(region.clamp(100, 100), Some(region));
+    assert_eq!(
+        region.clamp(15, 12),
+        Some(Region::between((10, 5), (15, 12)))
+    );
+    // past the image, or of no size.
+    assert_eq!(region.clamp(10, 100), None);
+    assert_eq!(
+        Region::between((4, 4), (-10, -10)).clamp(100, 100),
+        Some(Region::between((0, 0), (4, 4)))
+    );
+    assert_eq!(Region::between((5, 5), (5, 9)).clamp(100, 100), None);
+    assert_eq!(Region::default().clamp(100, 100), None);
+}
+
+#[test]
+fn crop_test() {
+    let pixel = |v: u8| [v, v, v, 255];
+    // 4x3, rows 10-13, 20-23, 30-33.
+    let image = Image {
+        width: 4,
+        height: 3,
+        bgra: [10, 11, 12, 13, 20, 21, 22, 23, 30, 31, 32, 33]
+            .map(pixel)
+            .concat(),
+    };
+    let cropped = image.crop(Region::between((1, 1), (3, 3))).unwrap();
+    assert_eq!((cropped.width, cropped.height), (2, 2));
+    assert_eq!(cropped.bgra, [21, 22, 31, 32].map(pixel).concat());
+
+    // a column, a row, and a region hanging over the edge.
+    let column = image.crop(Region::between((3, 0), (4, 3))).unwrap();
+    assert_eq!(column.bgra, [13, 23, 33].map(pixel).concat());
+    let row = image.crop(Region::between((0, 2), (4, 3))).unwrap();
+    assert_eq!(row.bgra, [30, 31, 32, 33].map(pixel).concat());
+    let over = image.crop(Region::between((2, -5), (9, 1))).unwrap();
+    assert_eq!((over.width, over.height), (2, 1));
+    assert_eq!(over.bgra, [12, 13].map(pixel).concat());
+
+    assert!(image.crop(Region::between((4, 0), (6, 2))).is_none());
+    assert_eq!(
+        image.crop(Region::between((0, 0), (4, 3))).unwrap().bgra,
+        image.bgra
+    );
+}
+
+#[test]
+fn repeats_test() {
+    let mut repeats = Repeats::new();
+    assert!(!repeats.is_repeat(1));
+    repeats.record(1);
+    assert!(repeats.is_repeat(1));
+    assert!(repeats.is_repeat(1));
+    assert!(!repeats.is_repeat(2));
+    repeats.record(2);
+    assert!(!repeats.is_repeat(1));
+}
+
+#[test]
+fn is_too_small_test() {
+    assert!(is_too_small(0, 100));
+    assert!(is_too_small(100, 0));
+    assert!(is_too_small(1, 1));
+    assert!(is_too_small(3, 100));
+    assert!(!is_too_small(4, 4));
+    assert!(!is_too_small(4, -4));
+}
+
+#[test]
+fn check_size_test() {
+    assert!(check_size(0, 10, 0).is_err());
+    assert!(check_size(10, 0, 0).is_err());
+    assert!(check_size(-10, 10, 400).is_err());
+    assert_eq!(
+        check_size(1, 1, 4).unwrap_err().to_string(),
+        "the image is too small to recognize (1x1)."
+    );
+    assert!(check_size(3, 100, 1200).is_err());
+    assert!(check_size(4, 4, 64).is_ok());
+    assert!(check_size(4, 4, 60).is_err());
+    assert!(check_size(i32::MAX, i32::MAX, 0).is_err());
+}
+
+#[test]
+fn is_uniform_test() {
+    assert!(is_uniform(&[]));
+    assert!(is_uniform(&[255; 16 * 16 * 4]));
+
+    let mut bgra = vec![255; 16 * 16 * 4];
+    bgra[100 * 4] = 0;
+    assert!(!is_uniform(&bgra));
+
+    // a large white image with a dark line of "text" in the middle.
+    let (width, height) = (1920, 1080);
+    let mut bgra = vec![255; width * height * 4];
+    let row = height / 2;
+    bgra[row * width * 4..(row + 1) * width * 4].fill(0);
+    assert!(!is_uniform(&bgra));
+
+    // a vertical line must be found even when the stride is a multiple of the width.
+    let mut bgra = vec![255; width * height * 4];
+    (0..height).for_each(|y| bgra[(y * width + 7) * 4] = 0);
+    assert!(!is_uniform(&bgra));
+
+    // a single dark word anywhere in a large white screenshot.
+    let (word_width, word_height) = (160, 14);
+    for (x, y) in [(0, 0), (37, 501), (881, 333), (1760, 1066), (1203, 7)] {
+        let mut bgra = vec![255; width * height * 4];
+        for row in y..y + word_height {
+            bgra[(row * width + x) * 4..(row * width + x + word_width) * 4].fill(20);
+        }
+        assert!(!is_uniform(&bgra), "{x},{y}");
+    }
+
+    let mut bgra = vec![255; width * height * 4];
+    bgra.iter_mut().step_by(4).for_each(|b| *b = 10);
+    assert!(is_uniform(&bgra));
+}
+
+#[test]
+fn flatten_alpha_test() {
+    // opaque images are left alone.
+    let mut bgra = vec![10, 20, 30, 255, 40, 50, 60, 255];
+    flatten_alpha(&mut bgra);
+    assert_eq!(bgra, [10, 20, 30, 255, 40, 50, 60, 255]);
+
+    // an all zero alpha channel is ignored, not treated as fully transparent.
+    let mut bgra = vec![10, 20, 30, 0, 40, 50, 60, 0];
+    flatten_alpha(&mut bgra);
+    assert_eq!(bgra, [10, 20, 30, 255, 40, 50, 60, 255]);
+
+    // black text on a transparent page ends up on white.
+    let mut bgra = vec![0, 0, 0, 0, 0, 0, 0, 255, 0, 0, 0, 128, 0, 0, 255, 128];
+    flatten_alpha(&mut bgra);
+    assert_eq!(
+        bgra,
+        [255, 255, 255, 255, 0, 0, 0, 255, 127, 127, 127, 255, 127, 127, 255, 255]
+    );
+}
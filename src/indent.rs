@@ -0,0 +1,126 @@
+//! Leading indentation from the geometry of recognized lines, e.g. of code screenshots.
+//! The engine drops the spaces a line starts with; they are put back from how far the
+//! line starts right of the leftmost one, in characters of the median width.
+
+use super::ocr::Line;
+
+/// The width of a character, the median over the words of `lines` of their width by
+/// their characters. None without any words.
+fn char_width(lines: &[Line]) -> Option<f32> {
+    let mut widths: Vec<f32> = lines
+        .iter()
+        .flat_map(|line| &line.words)
+        .filter_map(|word| {
+            let chars = word.text.chars().count();
+            (chars > 0).then(|| word.width / chars as f32)
+        })
+        .collect();
+    widths.sort_by(f32::total_cmp);
+    widths
+        .get(widths.len() / 2)
+        .copied()
+        .filter(|&width| width > 0.0)
+}
+
+/// The spaces to indent each of `lines` with. Lines starting within half a character of
+/// each other are indented alike, so a block doesn't come out ragged. Lines without
+/// words aren't indented.
+pub fn indents(lines: &[Line]) -> Vec<usize> {
+    let starts: Vec<Option<f32>> = lines
+        .iter()
+        .map(|line| line.words.first().map(|word| word.x))
+        .collect();
+    let Some(width) = char_width(lines) else {
+        return vec![0; lines.len()];
+    };
+    let mut sorted: Vec<f32> = starts.iter().flatten().copied().collect();
+    sorted.sort_by(f32::total_cmp);
+    // each start snapped to the leftmost of the starts running up to it.
+    let mut snapped: Vec<(f32, f32)> = Vec::new();
+    for x in sorted {
+        let anchor = match snapped.last() {
+            Some(&(previous, anchor)) if x - previous < width / 2.0 => anchor,
+            _ => x,
+        };
+        snapped.push((x, anchor));
+    }
+    let Some(&(_, left)) = snapped.first() else {
+        return vec![0; lines.len()];
+    };
+    starts
+        .iter()
+        .map(|start| {
+            let Some(x) = start else {
+                return 0;
+            };
+            let anchor = snapped
+                .iter()
+                .find(|&&(s, _)| s == *x)
+                .map_or(*x, |&(_, anchor)| anchor);
+            ((anchor - left) / width).round() as usize
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn code(lines: &[(f32, &str)]) -> Vec<Line> {
+    use super::ocr::Word;
+    // characters 8 wide, the words of a line a character apart, by left and text.
+    lines
+        .iter()
+        .map(|&(left, text)| {
+            let mut x = left;
+            let words = text
+                .split(' ')
+                .map(|word| {
+                    let width = word.chars().count() as f32 * 8.0;
+                    let word_box = Word {
+                        text: word.to_owned(),
+                        x,
+                        y: 0.0,
+                        width,
+                        height: 12.0,
+                    };
+                    x += width + 8.0;
+                    word_box
+                })
+                .collect();
+            Line {
+                text: text.to_owned(),
+                words,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn indents_test() {
+    let lines = code(&[
+        (10.0, "def f(x):"),
+        (42.0, "if x:"),
+        (74.0, "return 1"),
+        (42.0, "return 0"),
+    ]);
+    assert_eq!(indents(&lines), [0, 4, 8, 4]);
+
+    // starts off by a few pixels, as recognized, are the same indent.
+    let lines = code(&[
+        (10.0, "for i in range(3):"),
+        (43.5, "print(i)"),
+        (40.0, "print(i * 2)"),
+        (45.0, "x = i"),
+        (11.0, "done()"),
+    ]);
+    assert_eq!(indents(&lines), [0, 4, 4, 4, 0]);
+}
+
+#[test]
+fn no_indents_test() {
+    assert_eq!(indents(&[]), []);
+    // a line without words, e.g. from an old JSON export.
+    let mut lines = code(&[(10.0, "a = 1"), (42.0, "b")]);
+    lines[1].words.clear();
+    assert_eq!(indents(&lines), [0, 0]);
+    // a line alone isn't indented, however far right it starts.
+    assert_eq!(indents(&code(&[(100.0, "x")])), [0]);
+}
@@ -0,0 +1,133 @@
+use super::ocr::{Line, Recognition, Word};
+use std::fmt::Write;
+
+/// Serializes the layout of a recognition:
+///
+/// ```text
+/// {"lang":"en-US","width":640,"height":480,"lines":[{"text":"Hello world","words":[
+///   {"text":"Hello","x":10,"y":12,"width":48,"height":16}, ...]}]}
+/// ```
+pub fn recognition(recognition: &Recognition) -> String {
+    let mut json = String::new();
+    json.push_str("{\"lang\":");
+    string(&recognition.lang, &mut json);
+    write!(
+        json,
+        ",\"width\":{},\"height\":{},\"lines\":[",
+        recognition.width, recognition.height
+    )
+    .unwrap_or_default();
+    for (i, line) in recognition.lines.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        self::line(line, &mut json);
+    }
+    json.push_str("]}");
+    json
+}
+
+fn line(line: &Line, json: &mut String) {
+    json.push_str("{\"text\":");
+    string(&line.text, json);
+    json.push_str(",\"words\":[");
+    for (i, word) in line.words.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        self::word(word, json);
+    }
+    json.push_str("]}");
+}
+
+fn word(word: &Word, json: &mut String) {
+    json.push_str("{\"text\":");
+    string(&word.text, json);
+    write!(
+        json,
+        ",\"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+        word.x, word.y, word.width, word.height
+    )
+    .unwrap_or_default();
+}
+
+/// Writes a quoted JSON string, escaping quotes, backslashes and control characters.
+fn string(text: &str, json: &mut String) {
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' || c == '\u{7f}' => {
+                write!(json, "\\u{:04x}", c as u32).unwrap_or_default();
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[test]
+fn recognition_test() {
+    let word = |text: &str, x: f32| Word {
+        text: text.to_owned(),
+        x,
+        y: 2.0,
+        width: 30.5,
+        height: 12.0,
+    };
+    let result = Recognition {
+        lang: "en-US".to_owned(),
+        width: 200,
+        height: 40,
+        lines: vec![
+            Line {
+                text: "Hello world".to_owned(),
+                words: vec![word("Hello", 1.0), word("world", 40.25)],
+            },
+            Line {
+                text: String::new(),
+                words: vec![],
+            },
+        ],
+        angle: None,
+        rotation: None,
+        notes: Vec::new(),
+    };
+    assert_eq!(
+        recognition(&result),
+        r#"{"lang":"en-US","width":200,"height":40,"lines":[{"text":"Hello world","words":[{"text":"Hello","x":1,"y":2,"width":30.5,"height":12},{"text":"world","x":40.25,"y":2,"width":30.5,"height":12}]},{"text":"","words":[]}]}"#
+    );
+
+    let empty = Recognition {
+        lang: "ja".to_owned(),
+        width: 1,
+        height: 1,
+        lines: vec![],
+        angle: Some(3.5),
+        rotation: None,
+        notes: Vec::new(),
+    };
+    assert_eq!(
+        recognition(&empty),
+        r#"{"lang":"ja","width":1,"height":1,"lines":[]}"#
+    );
+}
+
+#[test]
+fn string_test() {
+    let quoted = |text: &str| {
+        let mut json = String::new();
+        string(text, &mut json);
+        json
+    };
+    assert_eq!(quoted(""), r#""""#);
+    assert_eq!(quoted(r#"say "hi""#), r#""say \"hi\"""#);
+    assert_eq!(quoted(r"C:\dir"), r#""C:\\dir""#);
+    assert_eq!(quoted("a\r\n\tb"), r#""a\r\n\tb""#);
+    assert_eq!(quoted("\u{0}\u{1b}\u{7f}"), r#""\u0000\u001b\u007f""#);
+    assert_eq!(quoted("日本語 / é"), "\"日本語 / é\"");
+}
@@ -0,0 +1,116 @@
+//! The recognition languages listed in the combobox. Each item carries the index of its
+//! language as item data instead of being looked up by its text, since two languages can
+//! share a display name.
+
+// the item data of the entry using the user profile languages.
+pub const AUTO_ITEM: isize = -2;
+// the `language` setting picking that entry.
+pub const AUTO_LANGUAGE: &str = "auto";
+
+/// What a combobox item recognizes with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item<'a> {
+    /// The user profile languages.
+    Auto,
+    /// The language with this tag.
+    Tag(&'a str),
+}
+
+/// The display names and tags of the installed recognizer languages.
+#[derive(Debug, Default)]
+pub struct Languages(Vec<(String, String)>);
+
+impl Languages {
+    pub const fn new(languages: Vec<(String, String)>) -> Self {
+        Self(languages)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The display names, each with the item data to store on its item.
+    pub fn items(&self) -> impl Iterator<Item = (&str, isize)> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.as_str(), i as isize))
+    }
+
+    /// What the item with `data` recognizes with, None if no item has it.
+    pub fn resolve(&self, data: isize) -> Option<Item<'_>> {
+        if data == AUTO_ITEM {
+            return Some(Item::Auto);
+        }
+        let (_, tag) = self.0.get(usize::try_from(data).ok()?)?;
+        Some(Item::Tag(tag))
+    }
+
+    /// The item data of the item for `language`, a tag or `auto`. Languages not
+    /// installed go to the Auto item.
+    pub fn item_data(&self, language: &str) -> isize {
+        self.0
+            .iter()
+            .position(|(_, tag)| tag.eq_ignore_ascii_case(language))
+            .map_or(AUTO_ITEM, |i| i as isize)
+    }
+}
+
+/// Whether an engine recognizing in the `available` languages recognizes in `tag`, as it
+/// does in other variants of a language it has, e.g. `en-GB` with `en-US`.
+pub fn is_available(tag: &str, available: &[String]) -> bool {
+    let primary = |tag: &str| {
+        tag.split('-')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    };
+    available.iter().any(|other| primary(other) == primary(tag))
+}
+
+#[test]
+fn languages_test() {
+    let languages = Languages::new(vec![
+        ("English (United States)".to_owned(), "en-US".to_owned()),
+        (
+            "English (United States)".to_owned(),
+            "en-US-x-pack".to_owned(),
+        ),
+        ("中文(中华人民共和国)".to_owned(), "zh-Hans-CN".to_owned()),
+    ]);
+    let items: Vec<_> = languages.items().collect();
+    assert_eq!(items[1], ("English (United States)", 1));
+
+    // the same display name, yet each item resolves to its own tag.
+    for (tag, data) in [("en-US", 0), ("en-US-x-pack", 1), ("zh-Hans-CN", 2)] {
+        assert_eq!(languages.item_data(tag), data);
+        assert_eq!(languages.resolve(data), Some(Item::Tag(tag)));
+    }
+    assert_eq!(languages.item_data("ZH-hans-cn"), 2);
+
+    assert_eq!(languages.item_data(AUTO_LANGUAGE), AUTO_ITEM);
+    assert_eq!(languages.item_data("ja"), AUTO_ITEM);
+    assert_eq!(languages.resolve(AUTO_ITEM), Some(Item::Auto));
+    // CB_ERR, or an item added without data.
+    assert_eq!(languages.resolve(-1), None);
+    assert_eq!(languages.resolve(3), None);
+
+    // nothing installed, even Auto has nothing to recognize with.
+    assert!(Languages::default().is_empty());
+    assert_eq!(languages.len(), 3);
+}
+
+#[test]
+fn is_available_test() {
+    let available = ["en-US".to_owned(), "ja".to_owned()];
+    assert!(is_available("en-US", &available));
+    assert!(is_available("EN-gb", &available));
+    assert!(is_available("ja-JP", &available));
+    assert!(!is_available("de-DE", &available));
+    assert!(!is_available("", &available));
+    assert!(!is_available("en-US", &[]));
+}
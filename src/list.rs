@@ -0,0 +1,216 @@
+//! Bulleted and numbered lists from recognized lines. A line starting with a marker like
+//! `•`, `-`, `1.` or `a)` is an item, nested if it starts right of the item before; the
+//! lines after it starting about where its text does continue it, up to a line starting
+//! further left.
+
+use super::ocr::Line;
+
+// characters that mark an item even without a space after them.
+const BULLETS: &[char] = &[
+    '•', '・', '·', '●', '○', '◦', '▪', '■', '‣', '►', '▶', '✓', '✔',
+];
+// characters that mark an item only with a space after them, unlike e.g. `-5°C`.
+const DASHES: &[char] = &['-', '*', '+', '–', '—'];
+// lines starting within this many line heights of each other start alike.
+const TOLERANCE: f32 = 0.5;
+
+/// The marker `text` starts with if it's a list item, e.g. `•` or `(3)`.
+pub fn marker(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    let first = text.chars().next()?;
+    let (marker, rest) = if BULLETS.contains(&first) {
+        text.split_at(first.len_utf8())
+    } else {
+        text.split_at(text.find(char::is_whitespace)?)
+    };
+    if rest.trim().is_empty() {
+        return None;
+    }
+    if BULLETS.contains(&first) || (marker.len() == first.len_utf8() && DASHES.contains(&first)) {
+        return Some(marker);
+    }
+    let label = match marker.strip_suffix(')') {
+        Some(label) => label.strip_prefix('(').unwrap_or(label),
+        None => marker.strip_suffix('.')?,
+    };
+    let number = (1..=3).contains(&label.len()) && label.chars().all(|c| c.is_ascii_digit());
+    let letter = label.len() == 1 && label.chars().all(|c| c.is_ascii_lowercase());
+    let roman = (1..=4).contains(&label.len()) && label.chars().all(|c| "ivx".contains(c));
+    (number || letter || roman).then_some(marker)
+}
+
+/// Where a line starts, where its text starts past any marker, and how high it is, in
+/// image pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListLine<'a> {
+    pub left: f32,
+    pub text_left: f32,
+    pub height: f32,
+    pub text: &'a str,
+}
+
+impl<'a> ListLine<'a> {
+    /// The geometry of `line`, None without any words.
+    pub fn of(line: &'a Line) -> Option<Self> {
+        let first = line.words.first()?;
+        // a marker recognized as a word of its own, the text starts at the next one; one
+        // stuck to the word, about as far in as it has characters.
+        let text_left = match (marker(&line.text), line.words.get(1)) {
+            (Some(marker), Some(next)) if first.text == marker => next.x,
+            (Some(marker), _) if first.text.starts_with(marker) => {
+                let share = marker.chars().count() as f32 / first.text.chars().count() as f32;
+                first.x + first.width * share
+            }
+            _ => first.x,
+        };
+        Some(Self {
+            left: first.x,
+            text_left,
+            height: first.height,
+            text: &line.text,
+        })
+    }
+}
+
+/// What a line is to a list. `indent` is the spaces it goes after to line up with the
+/// items around it: nested items and wrapped lines line up with the text of the item
+/// they belong to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Text,
+    Item { indent: usize },
+    Continuation { indent: usize },
+}
+
+/// The role of each of `lines`.
+pub fn roles(lines: &[ListLine]) -> Vec<Role> {
+    let mut heights: Vec<f32> = lines.iter().map(|line| line.height).collect();
+    heights.sort_by(f32::total_cmp);
+    let tolerance = heights.get(heights.len() / 2).copied().unwrap_or(0.0) * TOLERANCE;
+
+    // the items open at the line, outermost first, and the indent of their text.
+    let mut open: Vec<(&ListLine, usize)> = Vec::new();
+    lines
+        .iter()
+        .map(|line| {
+            if let Some(marker) = marker(line.text) {
+                // leave nested items, and a sibling.
+                while open
+                    .last()
+                    .is_some_and(|(item, _)| item.left > line.left - tolerance)
+                {
+                    open.pop();
+                }
+                let indent = open.last().map_or(0, |&(_, text)| text);
+                open.push((line, indent + marker.chars().count() + 1));
+                Role::Item { indent }
+            } else {
+                while open
+                    .last()
+                    .is_some_and(|(item, _)| line.left < item.text_left - tolerance)
+                {
+                    open.pop();
+                }
+                match open.last() {
+                    Some(&(_, indent)) => Role::Continuation { indent },
+                    None => Role::Text,
+                }
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn marker_test() {
+    for (text, expected) in [
+        ("• item", Some("•")),
+        ("•item", Some("•")),
+        ("- item", Some("-")),
+        ("  * item", Some("*")),
+        ("1. item", Some("1.")),
+        ("12) item", Some("12)")),
+        ("(3) item", Some("(3)")),
+        ("a) item", Some("a)")),
+        ("iv. item", Some("iv.")),
+        // not markers.
+        ("3.5 GHz", None),
+        ("-5°C outside", None),
+        ("e.g. this", None),
+        ("U.S. policy", None),
+        ("2024. A year", None),
+        ("Mr. Smith", None),
+        ("1.2.3 Section", None),
+        ("--- rule", None),
+        ("(12 twelve", None),
+        // a marker alone starts no item.
+        ("1.", None),
+        ("•", None),
+        ("", None),
+    ] {
+        assert_eq!(marker(text), expected, "{text:?}");
+    }
+}
+
+#[cfg(test)]
+fn list(lines: &[(f32, f32, &'static str)]) -> Vec<ListLine<'static>> {
+    // lines 10 high, by left, text left and text.
+    lines
+        .iter()
+        .map(|&(left, text_left, text)| ListLine {
+            left,
+            text_left,
+            height: 10.0,
+            text,
+        })
+        .collect()
+}
+
+#[test]
+fn roles_test() {
+    use Role::*;
+    let lines = list(&[
+        (0.0, 0.0, "Shopping:"),
+        (0.0, 12.0, "• fruit, for the"),
+        (12.0, 12.0, "whole week"),
+        (12.0, 24.0, "1. apples"),
+        (24.0, 24.0, "and pears"),
+        (12.0, 24.0, "2. plums"),
+        (0.0, 12.0, "• bread"),
+        (12.0, 12.0, "from the baker"),
+        (0.0, 0.0, "That's all."),
+    ]);
+    assert_eq!(
+        roles(&lines),
+        [
+            Text,
+            Item { indent: 0 },
+            Continuation { indent: 2 },
+            Item { indent: 2 },
+            Continuation { indent: 5 },
+            Item { indent: 2 },
+            Item { indent: 0 },
+            Continuation { indent: 2 },
+            Text,
+        ]
+    );
+
+    // a nested item's wrapped line can belong to the outer one.
+    let lines = list(&[
+        (0.0, 12.0, "- outer"),
+        (12.0, 24.0, "- inner"),
+        (12.0, 12.0, "outer again"),
+    ]);
+    assert_eq!(
+        roles(&lines),
+        [
+            Item { indent: 0 },
+            Item { indent: 2 },
+            Continuation { indent: 2 }
+        ]
+    );
+
+    // numbers starting a line are no list.
+    let lines = list(&[(0.0, 0.0, "Clock:"), (0.0, 0.0, "3.5 GHz")]);
+    assert_eq!(roles(&lines), [Text, Text]);
+    assert_eq!(roles(&[]), []);
+}
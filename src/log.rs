@@ -0,0 +1,9 @@
+use windows::{core::HSTRING, Win32::System::Diagnostics::Debug::OutputDebugStringW};
+
+/// Writes a line to the debugger output (see DebugView), and to stderr in debug builds.
+pub fn write(msg: &str) {
+    let line = format!("[{}] {msg}\n", env!("CARGO_PKG_NAME"));
+    unsafe { OutputDebugStringW(&HSTRING::from(&line)) };
+    #[cfg(debug_assertions)]
+    eprint!("{line}");
+}
@@ -1,38 +1,66 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use anyhow::{Context, Result};
-use std::sync::OnceLock;
-use std::{collections::HashMap, slice};
+use accumulator::Accumulator;
+use anyhow::{bail, ensure, Context, Result};
+use clipboard::{Flavor, LineEnding};
+use generation::Generations;
+use image::{Image, Region, Repeats};
+use languages::{Item, Languages, AUTO_ITEM, AUTO_LANGUAGE};
+use ocr::{Cancellable, Lang, Recognition, Recognizer, TextOptions, WinRt};
+use output::Output;
+use oversize::Oversize;
+use preprocess::Invert;
+use reprobe::Reprobe;
+use settings::Settings;
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use update::{Deferral, Update, UpdateFilter};
 use utf16_lit::utf16_null;
 use windows::{
-    core::{h, w, HSTRING, PCWSTR},
+    core::{w, HSTRING, PCWSTR},
+    Globalization::Language,
     Media::Ocr::OcrEngine,
+    System::UserProfile::GlobalizationPreferences,
     Win32::{
-        Foundation::{BOOL, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
+        Foundation::{BOOL, HWND, LPARAM, LRESULT, POINT, WPARAM},
         Graphics::Gdi::{ClientToScreen, GetSysColorBrush, COLOR_MENUBAR},
         System::{
-            DataExchange::{AddClipboardFormatListener, RemoveClipboardFormatListener},
-            LibraryLoader::{GetModuleHandleW, LoadLibraryW},
+            DataExchange::{
+                AddClipboardFormatListener, GetClipboardOwner, GetClipboardSequenceNumber,
+                RemoveClipboardFormatListener,
+            },
+            Threading::{
+                GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_BELOW_NORMAL,
+                THREAD_PRIORITY_NORMAL,
+            },
         },
         UI::{
             Controls::{
-                RichEdit::{
-                    EM_GETEVENTMASK, EM_GETTEXTLENGTHEX, EM_SETEVENTMASK, ENM_MOUSEEVENTS,
-                    EN_MSGFILTER, GETTEXTLENGTHEX, GTL_DEFAULT, MSFTEDIT_CLASS, MSGFILTER,
-                },
-                EM_REPLACESEL, EM_SETSEL, NMHDR, WC_COMBOBOXW,
+                InitCommonControls,
+                RichEdit::{EN_MSGFILTER, MSGFILTER},
+                NMHDR, WC_COMBOBOXW,
             },
+            Input::KeyboardAndMouse::VK_F5,
+            Shell::ShellExecuteW,
             WindowsAndMessaging::{
-                AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu,
-                DispatchMessageW, EnumWindows, GetClientRect, GetMessageW, GetWindowTextW,
-                IsIconic, PostQuitMessage, RegisterClassW, SendMessageW, SetForegroundWindow,
-                ShowWindow, TrackPopupMenuEx, TranslateMessage, CBS_DROPDOWNLIST, CBS_HASSTRINGS,
-                CBS_SORT, CB_ADDSTRING, CB_SELECTSTRING, CW_USEDEFAULT, ES_AUTOHSCROLL,
-                ES_AUTOVSCROLL, ES_MULTILINE, ES_WANTRETURN, HMENU, MF_STRING, MSG, SB_BOTTOM,
-                SW_SHOW, TPM_LEFTALIGN, WINDOW_EX_STYLE, WINDOW_STYLE, WM_CLIPBOARDUPDATE,
-                WM_COMMAND, WM_COPY, WM_CREATE, WM_DESTROY, WM_NOTIFY, WM_RBUTTONDOWN, WM_VSCROLL,
-                WNDCLASSW, WS_BORDER, WS_CAPTION, WS_CHILD, WS_EX_STATICEDGE, WS_HSCROLL,
-                WS_MINIMIZEBOX, WS_OVERLAPPED, WS_SYSMENU, WS_TABSTOP, WS_VISIBLE, WS_VSCROLL,
+                AppendMenuW, CreateAcceleratorTableW, CreatePopupMenu, CreateWindowExW,
+                DefWindowProcW, DestroyMenu, DispatchMessageW, EnumWindows, GetDlgItem,
+                GetMessageW, GetWindowTextW, IsIconic, KillTimer, MessageBoxW, PostMessageW,
+                PostQuitMessage, RegisterClassW, RegisterWindowMessageW, SendMessageW,
+                SetForegroundWindow, SetTimer, ShowWindow, TrackPopupMenuEx, TranslateAcceleratorW,
+                TranslateMessage, ACCEL, CBN_SELCHANGE, CBS_DROPDOWNLIST, CBS_HASSTRINGS, CBS_SORT,
+                CB_ADDSTRING, CB_ERR, CB_GETCOUNT, CB_GETCURSEL, CB_GETITEMDATA, CB_INSERTSTRING,
+                CB_RESETCONTENT, CB_SETCURSEL, CB_SETITEMDATA, CW_USEDEFAULT, FVIRTKEY, HMENU,
+                IDYES, MB_ICONWARNING, MB_YESNO, MF_STRING, MSG, PBT_APMRESUMEAUTOMATIC,
+                PBT_APMRESUMESUSPEND, SW_SHOW, SW_SHOWNORMAL, TPM_LEFTALIGN, WINDOW_EX_STYLE,
+                WINDOW_STYLE, WM_APP, WM_CLIPBOARDUPDATE, WM_COMMAND, WM_CREATE, WM_DESTROY,
+                WM_DESTROYCLIPBOARD, WM_NOTIFY, WM_POWERBROADCAST, WM_RBUTTONDOWN,
+                WM_RENDERALLFORMATS, WM_RENDERFORMAT, WM_TIMECHANGE, WM_TIMER, WNDCLASSW,
+                WS_CAPTION, WS_CHILD, WS_EX_STATICEDGE, WS_MINIMIZEBOX, WS_OVERLAPPED, WS_SYSMENU,
+                WS_VISIBLE, WS_VSCROLL,
             },
         },
     },
@@ -45,15 +73,85 @@ macro_rules! c {
     };
 }
 
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::log::write(&format!($($arg)*))
+    };
+}
+
 const ID_COMBO: i32 = 5457;
-const BUF_SIZE: usize = 8192;
+const AUTO_TEXT: PCWSTR = w!("Auto (user profile languages)");
+const NO_LANGUAGES: &str =
+    "no OCR language installed, add one then pick Options > Refresh languages";
+const NO_LANGUAGES_GUIDE: PCWSTR = w!("Recognizing text needs a language with optical \
+character recognition, and none is installed.\n\n\
+Add a language in Settings > Time & language > Language & region, with its optical \
+character recognition feature, then pick Options > Refresh languages.\n\n\
+Open the language settings now?");
+// blank pixels added around the image by the `pad_margin` setting.
+const PAD_MARGIN: i32 = 16;
+// the side of the blank image recognized to warm up the engine.
+const WARM_UP_SIZE: i32 = 32;
+// text skewed by more than this many degrees is straightened and recognized again.
+const DESKEW_MIN_ANGLE: f64 = 2.0;
 const ID_COPY: usize = 1000;
+const ID_COPY_JSON: usize = 1001;
+const ID_COPY_FILE: usize = 1002;
+// posted to handle clipboard updates deferred during a scan.
+const WM_CLIPBOARD_RECHECK: u32 = WM_APP + 1;
+// posted by a second instance, asking to scan the image on the clipboard.
+const WM_SCAN_NOW: u32 = WM_APP + 2;
+// a recognition finished on the worker thread, LPARAM pointing to a boxed `Recognized`.
+const WM_RECOGNIZED: u32 = WM_APP + 3;
+// posted at startup when no recognizer language is installed.
+const WM_NO_LANGUAGES: u32 = WM_APP + 4;
+const ID_TIMER_LISTENER: usize = 1;
+const ID_TIMER_REPROBE: usize = 2;
+const ID_TIMER_DEBOUNCE: usize = 3;
+const ID_TIMER_FORGET_IMAGE: usize = 4;
+
+// how often the clipboard listener registration is verified.
+const LISTENER_CHECK_INTERVAL: u32 = 10 * 60 * 1000;
+
+// the Remote Desktop processes that announce an image before it is available: the
+// clipboard redirection of the remote side, and the client.
+const RDP_CLIPBOARD_PROCESSES: [&str; 2] = ["rdpclip.exe", "mstsc.exe"];
+const RDP_REPROBE_ATTEMPTS: u32 = 2;
+const RDP_REPROBE_INTERVAL: u32 = 300;
+
+// the last image is kept for rescanning if it takes at most this many bytes, for this
+// many milliseconds.
+const KEEP_IMAGE_MAX_BYTES: usize = 64 << 20;
+const KEEP_IMAGE_TIMEOUT: u32 = 10 * 60 * 1000;
+
+// the accumulated text drops its oldest results beyond this.
+const MAX_ACCUMULATED_BYTES: usize = 4 << 20;
 
 const COPY_TEXT: PCWSTR = w!("Copy");
+const COPY_JSON_TEXT: PCWSTR = w!("Copy as JSON");
+const COPY_FILE_TEXT: PCWSTR = w!("Copy as file");
 
-static DISPLAY_NAMES: OnceLock<HashMap<Vec<u16>, Vec<u16>>> = OnceLock::new();
+static LANGUAGES: Mutex<Languages> = Mutex::new(Languages::new(Vec::new()));
 static HWND_MAIN_WINDOW: OnceLock<Hwnd> = OnceLock::new();
-static HWND_RICH_EDIT: OnceLock<Hwnd> = OnceLock::new();
+static WM_TASKBAR_CREATED: OnceLock<u32> = OnceLock::new();
+// tells new clipboard updates from repeated notifications and our own writes.
+static UPDATES: Mutex<UpdateFilter> = Mutex::new(UpdateFilter::new());
+// clipboard updates arriving while one is being handled.
+static DEFERRAL: Mutex<Deferral> = Mutex::new(Deferral::new());
+static REPROBE: Mutex<Reprobe> = Mutex::new(Reprobe::new());
+// the last image taken from the clipboard, before preprocessing, and the format it was
+// read from.
+static LAST_IMAGE: Mutex<Option<(Arc<Image>, Option<Flavor>)>> = Mutex::new(None);
+// the recognition whose result is applied, newer images superseding older ones.
+static GENERATIONS: Mutex<Generations> = Mutex::new(Generations::new());
+// the last image recognized, to skip copies of it.
+static REPEATS: Mutex<Repeats> = Mutex::new(Repeats::new());
+// the results copied in accumulate mode.
+static ACCUMULATOR: Mutex<Accumulator> = Mutex::new(Accumulator::new(MAX_ACCUMULATED_BYTES));
+// the layout of the last recognition, for "Copy as JSON".
+static LAST_RECOGNITION: Mutex<Option<Recognition>> = Mutex::new(None);
+// the text of the last result before the replacement rules, to test them on.
+static LAST_TEXT: Mutex<Option<String>> = Mutex::new(None);
 
 struct Hwnd(HWND);
 
@@ -70,8 +168,52 @@ impl Hwnd {
     }
 }
 
+mod accumulator;
+mod adjust;
+mod apps;
 mod clipboard;
+mod columns;
+mod crop;
+mod detect;
+mod dib;
+mod fold;
+mod furigana;
+mod generation;
+mod hdrop;
+mod html;
+mod hyphen;
+mod image;
+mod indent;
+mod json;
+mod languages;
+mod list;
+mod log;
+mod menu;
 mod ocr;
+mod output;
+mod oversize;
+mod paragraph;
+mod pipeline;
+mod preprocess;
+mod reference;
+mod regex;
+mod reprobe;
+mod rle;
+mod rtf;
+mod rules;
+mod settings;
+mod status;
+mod table;
+#[cfg(feature = "tesseract")]
+mod tesseract;
+mod update;
+mod view;
+mod virtual_file;
+mod wic;
+mod worker;
+
+#[cfg(test)]
+mod regression;
 
 const CLASS_NAME: PCWSTR = w!("ocr_win_class_name");
 const TITLE: &[u16] = &utf16_null!(concat!(
@@ -101,32 +243,108 @@ unsafe extern "system" fn wnd_proc(
         }
         WM_COMMAND => {
             let id = loword(wparam.0 as u32) as usize;
-            if let Some(hedit) = HWND_RICH_EDIT.get() {
-                // Some ID_XXX will be added in the future...
-                #[allow(clippy::single_match)]
-                match id {
-                    ID_COPY => {
-                        SendMessageW(hedit.handle(), WM_COPY, None, None);
+            if id == ID_COMBO as usize && hiword(wparam.0 as u32) as u32 == CBN_SELCHANGE {
+                if let Err(e) = change_language(hwnd) {
+                    report(&e);
+                }
+                return LRESULT::default();
+            }
+            if menu::command(hwnd, id) {
+                return LRESULT::default();
+            }
+            match id {
+                ID_COPY => {
+                    if let Err(e) = copy_selection() {
+                        report(&e);
+                    }
+                }
+                ID_COPY_JSON => {
+                    if let Err(e) = copy_json() {
+                        report(&e);
+                    }
+                }
+                ID_COPY_FILE => {
+                    if let Err(e) = copy_file() {
+                        report(&e);
+                    }
+                }
+                menu::ID_RESET_ACCUMULATION => accumulator().clear(),
+                menu::ID_RESCAN => {
+                    if let Err(e) = rescan(hwnd) {
+                        report(&e);
+                    }
+                }
+                menu::ID_CROP => {
+                    if let Err(e) = crop_clipboard(hwnd) {
+                        report(&e);
                     }
-                    _ => (),
                 }
+                menu::ID_REFRESH_LANGUAGES => {
+                    if let Err(e) = refresh_languages(hwnd) {
+                        report(&e);
+                    }
+                }
+                menu::ID_TEST_RULES => {
+                    if let Err(e) = test_rules() {
+                        report(&e);
+                    }
+                }
+                menu::ID_ADJUST => {
+                    adjust::open(hwnd).ok();
+                }
+                _ => (),
+            }
+        }
+        // WPARAM carries no meaning here; what's on the clipboard decides.
+        WM_CLIPBOARDUPDATE => debounce_clipboard_update(hwnd),
+        WM_CLIPBOARD_RECHECK => clipboard_update(hwnd),
+        WM_SCAN_NOW => scan_now(hwnd),
+        WM_NO_LANGUAGES => guide_language_install(hwnd),
+        WM_RECOGNIZED => {
+            // posted by `recognize` with a pointer to the box it leaked.
+            let recognized = unsafe { Box::from_raw(lparam.0 as *mut Recognized) };
+            if !generations().is_current(recognized.generation) {
+                log!("dropped the result of a superseded recognition.");
+            } else if let Err(e) = finish_recognition(*recognized) {
+                report(&e);
+            }
+        }
+        WM_RENDERFORMAT => {
+            if let Err(e) = clipboard::render(wparam.0 as u32) {
+                log!("failed to render clipboard format {}: {e:#}", wparam.0);
+            }
+        }
+        WM_RENDERALLFORMATS => {
+            if let Err(e) = clipboard::render_all(hwnd) {
+                log!("failed to render clipboard formats: {e:#}");
             }
         }
-        WM_CLIPBOARDUPDATE => {
-            // Adobe PDF reader:    WPARAM(0 | 3 | 5 | 6)
-            // Firefox:             WPARAM(6 | 4)
-            // Cut & Sketch:        WPARAM(7 | 7 | 4 | 8)
-            // Snipping Tool:       WPARAM(3 | 4)
-            // IrfanView:           WPARAM(3)
-            if wparam.eq(&WPARAM(3))
-                || wparam.eq(&WPARAM(4))
-                || wparam.eq(&WPARAM(6))
-                || wparam.eq(&WPARAM(7))
-            {
-                ocr(hwnd).ok();
+        WM_DESTROYCLIPBOARD => clipboard::discard_pending(),
+        WM_POWERBROADCAST => {
+            // resuming from sleep may leave the listener unregistered.
+            let event = wparam.0 as u32;
+            if event == PBT_APMRESUMEAUTOMATIC || event == PBT_APMRESUMESUSPEND {
+                refresh_clipboard_listener(hwnd);
             }
+            return LRESULT(1);
         }
+        WM_TIMECHANGE => refresh_clipboard_listener(hwnd),
+        WM_TIMER => match wparam.0 {
+            ID_TIMER_LISTENER => refresh_clipboard_listener(hwnd),
+            ID_TIMER_REPROBE => reprobe_clipboard(hwnd),
+            ID_TIMER_FORGET_IMAGE => {
+                unsafe { _ = KillTimer(hwnd, ID_TIMER_FORGET_IMAGE) };
+                *last_image() = None;
+            }
+            ID_TIMER_DEBOUNCE => {
+                unsafe { _ = KillTimer(hwnd, ID_TIMER_DEBOUNCE) };
+                clipboard_update(hwnd);
+            }
+            _ => (),
+        },
         WM_DESTROY => destroy(hwnd),
+        // Explorer has been restarted.
+        _ if WM_TASKBAR_CREATED.get() == Some(&msg) => refresh_clipboard_listener(hwnd),
         _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
     }
     LRESULT::default()
@@ -152,111 +370,346 @@ fn create_combobox(hwnd: HWND) -> Result<()> {
             None,
         )?
     };
-    let engine = OcrEngine::TryCreateFromUserProfileLanguages()?;
-    let lang = engine.RecognizerLanguage()?;
-
-    //dbg!(lang.DisplayName()?.as_wide().to_vec());
-
-    DISPLAY_NAMES.get_or_init(|| {
-        OcrEngine::AvailableRecognizerLanguages()
-            .unwrap()
-            .First()
-            .unwrap()
-            .filter_map(|lang| {
-                Some((
-                    lang.DisplayName()
-                        .ok()?
-                        .as_wide()
-                        .iter()
-                        .chain(Some(&0))
-                        .copied()
-                        .collect(),
-                    lang.LanguageTag()
-                        .ok()?
-                        .as_wide()
-                        .iter()
-                        .chain(Some(&0))
-                        .copied()
-                        .collect(),
-                ))
-            })
-            .collect()
-    });
 
-    DISPLAY_NAMES
-        .get()
-        .context(c!())?
-        .keys()
-        .filter_map(|k| HSTRING::from_wide(k).ok())
-        .for_each(|h| unsafe {
-            SendMessageW(hwnd, CB_ADDSTRING, None, LPARAM(h.as_ptr() as isize));
-        });
+    fill_combobox(hwnd)
+}
+
+/// Lists the installed recognizer languages in `combo`, replacing what it listed before,
+/// and selects the one in the settings.
+fn fill_combobox(combo: HWND) -> Result<()> {
+    let installed = OcrEngine::AvailableRecognizerLanguages()?
+        .into_iter()
+        .filter_map(|lang| {
+            Some((
+                lang.DisplayName().ok()?.to_string(),
+                lang.LanguageTag().ok()?.to_string(),
+            ))
+        })
+        .collect();
+    let languages = Languages::new(installed);
+    unsafe { SendMessageW(combo, CB_RESETCONTENT, None, None) };
+    if languages.is_empty() {
+        *self::languages() = languages;
+        return Ok(());
+    }
 
+    for (name, data) in languages.items() {
+        let name = HSTRING::from(name);
+        unsafe {
+            let index = SendMessageW(combo, CB_ADDSTRING, None, LPARAM(name.as_ptr() as isize));
+            SendMessageW(
+                combo,
+                CB_SETITEMDATA,
+                WPARAM(index.0 as usize),
+                LPARAM(data),
+            );
+        }
+    }
+    // pinned at the top, CB_INSERTSTRING doesn't sort.
     unsafe {
         SendMessageW(
+            combo,
+            CB_INSERTSTRING,
+            WPARAM(0),
+            LPARAM(AUTO_TEXT.as_ptr() as isize),
+        );
+        SendMessageW(combo, CB_SETITEMDATA, WPARAM(0), LPARAM(AUTO_ITEM));
+    }
+
+    let setting = settings::get().language;
+    let language = if setting.is_empty() {
+        OcrEngine::TryCreateFromUserProfileLanguages()
+            .and_then(|engine| engine.RecognizerLanguage()?.LanguageTag())
+            .map(|tag| tag.to_string())
+            .unwrap_or_default()
+    } else {
+        setting
+    };
+    // by item data, the sorted items don't follow `languages`.
+    let data = languages.item_data(&language);
+    unsafe {
+        let count = SendMessageW(combo, CB_GETCOUNT, None, None).0.max(0) as usize;
+        let index = (0..count)
+            .find(|&i| SendMessageW(combo, CB_GETITEMDATA, WPARAM(i), None).0 == data)
+            .unwrap_or_default();
+        SendMessageW(combo, CB_SETCURSEL, WPARAM(index), None);
+    }
+    *self::languages() = languages;
+    Ok(())
+}
+
+/// Lists the languages again, e.g. after one is installed.
+fn refresh_languages(hwnd: HWND) -> Result<()> {
+    fill_combobox(unsafe { GetDlgItem(hwnd, ID_COMBO)? })?;
+    let count = languages().len();
+    ensure!(count > 0, NO_LANGUAGES);
+    status::set(&format!("{count} recognition languages installed"));
+    Ok(())
+}
+
+/// Explains that recognition needs a language pack, offering to open the settings to
+/// add one.
+fn guide_language_install(hwnd: HWND) {
+    status::set(NO_LANGUAGES);
+    let answer = unsafe {
+        MessageBoxW(
             hwnd,
-            CB_SELECTSTRING,
-            None,
-            LPARAM(lang.DisplayName()?.as_ptr() as isize),
+            NO_LANGUAGES_GUIDE,
+            w!("No OCR language"),
+            MB_ICONWARNING | MB_YESNO,
         )
     };
+    if answer == IDYES {
+        let result = unsafe {
+            ShellExecuteW(
+                hwnd,
+                w!("open"),
+                w!("ms-settings:regionlanguage"),
+                None,
+                None,
+                SW_SHOWNORMAL,
+            )
+        };
+        // greater than 32 on success.
+        if result.0 as usize <= 32 {
+            log!(
+                "failed to open the language settings: {}",
+                result.0 as usize
+            );
+        }
+    }
+}
 
-    Ok(())
+fn create(hwnd: HWND) {
+    status::create(hwnd).ok();
+    view::create(hwnd).ok();
+    create_combobox(hwnd).ok();
+    if languages().is_empty() {
+        unsafe { PostMessageW(hwnd, WM_NO_LANGUAGES, None, None).ok() };
+    } else {
+        warm_up(hwnd);
+    }
+    unsafe { AddClipboardFormatListener(hwnd).ok() };
+    // an image copied before we started is handled like a fresh copy, once the window is
+    // up.
+    if settings::get().scan_at_startup && clipboard::is_bitmap_on_clipboard() {
+        unsafe { PostMessageW(hwnd, WM_CLIPBOARD_RECHECK, None, None).ok() };
+    } else {
+        updates().skip(unsafe { GetClipboardSequenceNumber() });
+    }
+
+    WM_TASKBAR_CREATED.get_or_init(|| unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) });
+    unsafe { SetTimer(hwnd, ID_TIMER_LISTENER, LISTENER_CHECK_INTERVAL, None) };
 }
 
-fn create_richedit(hwnd: HWND) -> Result<()> {
-    unsafe { LoadLibraryW(h!("Msftedit.dll"))? };
+/// Recognizes a blank image on the worker thread, at a lower priority, so the engine is
+/// created and the OCR runtime loaded before the first scan, which would otherwise take
+/// several times longer. A scan queued meanwhile runs after it and cancels its
+/// recognition, the engine it needs already created.
+fn warm_up(hwnd: HWND) {
+    let settings = settings::get();
+    let lang = match selected_language(hwnd) {
+        Ok(lang) => lang,
+        Err(e) => return log!("failed to warm up the engine: {e:#}"),
+    };
+    worker::queue(move || {
+        let start = Instant::now();
+        let thread = unsafe { GetCurrentThread() };
+        unsafe { SetThreadPriority(thread, THREAD_PRIORITY_BELOW_NORMAL).ok() };
+        let mut blank = |dst: &mut [u8]| dst.fill(0xff);
+        let result = lang.tag().and_then(|tag| {
+            recognizer(&settings).recognize(WARM_UP_SIZE, WARM_UP_SIZE, &mut blank, &tag)
+        });
+        unsafe { SetThreadPriority(thread, THREAD_PRIORITY_NORMAL).ok() };
+        match result {
+            Ok(_) => log!(
+                "warmed up the engine in {} ms.",
+                start.elapsed().as_millis()
+            ),
+            Err(e) => log!("failed to warm up the engine: {e:#}"),
+        }
+    });
+}
 
-    let mut rc = RECT::default();
-    unsafe { GetClientRect(hwnd, &mut rc)? };
+/// Waits for the clipboard to settle before handling an update: apps put their formats
+/// up one at a time, notifying for each. Every notification restarts the wait.
+fn debounce_clipboard_update(hwnd: HWND) {
+    let delay = settings::get().debounce_ms;
+    if delay == 0 {
+        clipboard_update(hwnd);
+        return;
+    }
+    unsafe { SetTimer(hwnd, ID_TIMER_DEBOUNCE, delay, None) };
+}
 
-    let hwnd = unsafe {
-        CreateWindowExW(
-            WINDOW_EX_STYLE::default(),
-            MSFTEDIT_CLASS,
-            None,
-            WINDOW_STYLE((ES_MULTILINE | ES_WANTRETURN | ES_AUTOHSCROLL | ES_AUTOVSCROLL) as _)
-                | WS_VISIBLE
-                | WS_CHILD
-                | WS_BORDER
-                | WS_TABSTOP
-                | WS_VSCROLL
-                | WS_HSCROLL,
-            0,
-            30,
-            rc.right,
-            rc.bottom - 30,
-            hwnd,
-            None,
-            GetModuleHandleW(None)?,
-            None,
-        )?
+fn clipboard_update(hwnd: HWND) {
+    if !deferral().begin() {
+        return;
+    }
+    handle_clipboard_update(hwnd);
+    // the clipboard has changed during the scan, look at it again once we're idle.
+    if deferral().end() {
+        unsafe { PostMessageW(hwnd, WM_CLIPBOARD_RECHECK, None, None).ok() };
+    }
+}
+
+fn handle_clipboard_update(hwnd: HWND) {
+    let sequence = unsafe { GetClipboardSequenceNumber() };
+    let owned = unsafe { GetClipboardOwner() }.ok() == Some(hwnd);
+    if updates().update(sequence, owned) != Update::New {
+        return;
+    }
+    reprobe().cancel();
+    let settings = settings::get();
+    if settings.paused || !is_source_allowed(&settings) {
+        return;
+    }
+
+    if clipboard::is_bitmap_on_clipboard() {
+        if let Err(e) = ocr(hwnd) {
+            report(&e);
+        }
+        return;
+    }
+
+    if settings.scan_paths || settings.scan_data_uris {
+        let text = clipboard::get_text().ok().flatten().unwrap_or_default();
+        if let Some(result) = scan_text(hwnd, &text, &settings) {
+            if let Err(e) = result {
+                report(&e);
+            }
+            return;
+        }
+    }
+
+    let owner = clipboard::owner_name().unwrap_or_default();
+    if RDP_CLIPBOARD_PROCESSES
+        .iter()
+        .any(|rdp| owner.eq_ignore_ascii_case(rdp))
+    {
+        log!(
+            "no image from {owner} yet, formats: {}",
+            clipboard::format_names()
+        );
+        let mut reprobe = reprobe();
+        reprobe.start(sequence, RDP_REPROBE_ATTEMPTS);
+        let interval = reprobe.interval(RDP_REPROBE_INTERVAL);
+        unsafe { SetTimer(hwnd, ID_TIMER_REPROBE, interval, None) };
+    }
+}
+
+/// Checks the app owning the clipboard against the allow and deny lists in the settings.
+fn is_source_allowed(settings: &Settings) -> bool {
+    let (allow, deny) = (&settings.allowed_apps, &settings.denied_apps);
+    if allow.is_empty() && deny.is_empty() {
+        return true;
+    }
+    let owner = clipboard::owner_name();
+    let Some(name) = owner.as_deref() else {
+        log!("the clipboard owner is unknown, scanning it regardless of the app lists.");
+        return true;
     };
+    let allowed = apps::is_allowed(owner.as_deref(), allow, deny);
+    if !allowed {
+        log!("ignored a clipboard update from {name}.");
+    }
+    allowed
+}
 
-    let result = unsafe { SendMessageW(hwnd, EM_GETEVENTMASK, None, None) };
-    let event = result.0 | ENM_MOUSEEVENTS as isize;
-    unsafe { SendMessageW(hwnd, EM_SETEVENTMASK, None, LPARAM(event)) };
+/// Surfaces a failed scan in the log and the status bar.
+fn report(e: &anyhow::Error) {
+    log!("{e:#}");
+    status::set(&format!("{e:#}"));
+}
 
-    HWND_RICH_EDIT.get_or_init(|| Hwnd::new(hwnd));
+fn updates() -> MutexGuard<'static, UpdateFilter> {
+    UPDATES.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-    Ok(())
+fn deferral() -> MutexGuard<'static, Deferral> {
+    DEFERRAL.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-fn create(hwnd: HWND) {
-    create_richedit(hwnd).ok();
-    create_combobox(hwnd).ok();
-    unsafe { AddClipboardFormatListener(hwnd).ok() };
+fn reprobe() -> MutexGuard<'static, Reprobe> {
+    REPROBE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn reprobe_clipboard(hwnd: HWND) {
+    let sequence = unsafe { GetClipboardSequenceNumber() };
+    if reprobe().fire(sequence, settings::get().paused) {
+        log!(
+            "re-probing the clipboard, formats: {}",
+            clipboard::format_names()
+        );
+        if clipboard::is_bitmap_on_clipboard() {
+            reprobe().cancel();
+            if let Err(e) = ocr(hwnd) {
+                report(&e);
+            }
+        }
+    }
+    let reprobe = reprobe();
+    if reprobe.is_pending() {
+        let interval = reprobe.interval(RDP_REPROBE_INTERVAL);
+        unsafe { SetTimer(hwnd, ID_TIMER_REPROBE, interval, None) };
+    } else {
+        unsafe { _ = KillTimer(hwnd, ID_TIMER_REPROBE) };
+    }
+}
+
+/// Re-registers the clipboard listener, which can silently get lost after a
+/// session or Explorer hiccup, and scans any image copied in the meantime.
+fn refresh_clipboard_listener(hwnd: HWND) {
+    // removing fails if the window is not registered anymore.
+    if unsafe { RemoveClipboardFormatListener(hwnd) }.is_err() {
+        log!("clipboard listener was lost, re-registering.");
+    }
+    if let Err(e) = unsafe { AddClipboardFormatListener(hwnd) } {
+        log!("failed to register clipboard listener: {e}");
+        return;
+    }
+    scan_pending_clipboard(hwnd);
+}
+
+/// Scans the image on the clipboard whether or not it has been handled, unless it's the
+/// one recognized last.
+fn scan_now(hwnd: HWND) {
+    if !clipboard::is_bitmap_on_clipboard() || !is_source_allowed(&settings::get()) {
+        return;
+    }
+    updates().skip(unsafe { GetClipboardSequenceNumber() });
+    if let Err(e) = ocr(hwnd) {
+        report(&e);
+    }
+}
+
+/// Scans the clipboard if it has changed since the last handled update.
+fn scan_pending_clipboard(hwnd: HWND) {
+    let settings = settings::get();
+    if settings.paused {
+        return;
+    }
+    let sequence = unsafe { GetClipboardSequenceNumber() };
+    let owned = unsafe { GetClipboardOwner() }.ok() == Some(hwnd);
+    if updates().update(sequence, owned) != Update::New || !is_source_allowed(&settings) {
+        return;
+    }
+    if let Err(e) = ocr(hwnd) {
+        log!("no pending image: {e}");
+    }
 }
 
 fn open_popup_menu(hwnd: HWND, x: u16, y: u16) -> Result<()> {
     let hmenu = unsafe { CreatePopupMenu()? };
     unsafe { AppendMenuW(hmenu, MF_STRING, ID_COPY, COPY_TEXT)? };
+    unsafe { AppendMenuW(hmenu, MF_STRING, ID_COPY_JSON, COPY_JSON_TEXT)? };
+    unsafe { AppendMenuW(hmenu, MF_STRING, ID_COPY_FILE, COPY_FILE_TEXT)? };
 
     let mut pt = POINT {
         x: x as _,
         y: y as _,
     };
-    let hedit = HWND_RICH_EDIT.get().context("no hwnd.")?.handle();
+    let hedit = view::get()?.handle();
     unsafe { ClientToScreen(hedit, &mut pt).ok()? };
 
     unsafe { TrackPopupMenuEx(hmenu, TPM_LEFTALIGN.0, pt.x, pt.y, hwnd, None).ok()? };
@@ -265,50 +718,890 @@ fn open_popup_menu(hwnd: HWND, x: u16, y: u16) -> Result<()> {
 }
 
 fn ocr(hwnd: HWND) -> Result<()> {
-    let (width, height, bgra) = clipboard::get()?;
+    let settings = settings::get();
+    let copied = clipboard::get(settings.keep_image)?;
+    log!("read {}", clipboard::format_name(copied.format));
+    let mut image = copied.image;
+    if settings.remember_crop {
+        if let Some(cropped) = image.crop(settings.crop_region) {
+            log!("cropped to {}.", settings.crop_region);
+            image = cropped;
+        }
+    }
+    scan(hwnd, image, copied.original)
+}
 
-    let mut buf = [0u8; BUF_SIZE];
-    let len = ocr::scan(hwnd, width, height, bgra, &mut buf)?;
+/// Opens the clipboard image in the crop window.
+fn crop_clipboard(hwnd: HWND) -> Result<()> {
+    let mut image = clipboard::get(false)?.image;
+    // as scanned, to tell whether it was the last image.
+    image::flatten_alpha(&mut image.bgra);
+    let fingerprint = image.fingerprint();
+    let scanned = last_image()
+        .as_ref()
+        .is_some_and(|(last, _)| last.fingerprint() == fingerprint);
+    let recognition = last_recognition().clone().filter(|recognition| {
+        scanned && (recognition.width, recognition.height) == (image.width, image.height)
+    });
+    crop::open(hwnd, image, recognition)
+}
 
-    let txt = unsafe { slice::from_raw_parts(buf.as_ptr() as *const u16, len / 2) };
-    clipboard::set(txt)?;
+/// Recognizes the part of `image` picked in the crop window, all of it if `region` is
+/// None, and remembers the region for the `remember_crop` setting. It's not skipped as a
+/// duplicate.
+fn scan_cropped(hwnd: HWND, image: Image, region: Option<Region>) -> Result<()> {
+    let image = match region {
+        Some(region) => {
+            settings::update(|s| s.crop_region = region);
+            image.crop(region).context("nothing to crop.")?
+        }
+        None => image,
+    };
+    repeats().forget();
+    scan(hwnd, image, None)
+}
+
+/// Recognizes an image the copied text refers to, if the settings allow it. Returns None
+/// if the text holds no such reference.
+fn scan_text(hwnd: HWND, text: &str, settings: &Settings) -> Option<Result<()>> {
+    if settings.scan_data_uris {
+        if let Some(bytes) = reference::data_uri(text) {
+            let image = bytes
+                .and_then(|bytes| wic::decode(&bytes).context("failed to decode the data URI"));
+            return Some(image.and_then(|image| scan(hwnd, image, None)));
+        }
+    }
+    if settings.scan_paths {
+        if let Some(path) = reference::path(text).filter(|path| path.is_file()) {
+            return Some(scan_file(hwnd, &path));
+        }
+    }
+    None
+}
 
-    let hedit = HWND_RICH_EDIT.get().context("no hedit.")?.handle();
+/// Recognizes the image file at `path`.
+fn scan_file(hwnd: HWND, path: &Path) -> Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let image =
+        wic::decode(&bytes).with_context(|| format!("failed to decode {}", path.display()))?;
+    scan(hwnd, image, None)
+}
 
-    // move the caret to the end of the text
-    let len = GETTEXTLENGTHEX {
-        flags: GTL_DEFAULT,
-        codepage: 1200,
+/// Recognizes a freshly copied image and keeps it for `rescan`. `original` is the
+/// clipboard format the image came from, put back with the text if the settings say so.
+fn scan(hwnd: HWND, mut image: Image, original: Option<Flavor>) -> Result<()> {
+    let Image { width, height, .. } = image;
+    if let Err(e) = image::check_size(width, height, image.bgra.len()) {
+        log!("skipped an image: {e}");
+        status::STATS.add_skipped();
+        status::set(&e.to_string());
+        return Ok(());
+    }
+    image::flatten_alpha(&mut image.bgra);
+    if image::is_blank(width, height, &image.bgra) {
+        let owner = clipboard::owner_name();
+        log!(
+            "skipped a blank {width}x{height} image from {}.",
+            owner.as_deref().unwrap_or("unknown")
+        );
+        status::STATS.add_skipped();
+        status::set("clipboard image is empty/blank");
+        return Ok(());
+    }
+
+    let fingerprint = image.fingerprint();
+    if repeats().is_repeat(fingerprint) {
+        log!("skipped a {width}x{height} image identical to the last one.");
+        status::STATS.add_skipped();
+        status::set("duplicate image skipped, rescan it from the Options menu");
+        return Ok(());
+    }
+
+    let image = Arc::new(image);
+    *last_image() = if image.bgra.len() <= KEEP_IMAGE_MAX_BYTES {
+        unsafe { SetTimer(hwnd, ID_TIMER_FORGET_IMAGE, KEEP_IMAGE_TIMEOUT, None) };
+        Some((image.clone(), original.clone()))
+    } else {
+        None
     };
-    let len = unsafe {
-        SendMessageW(
-            hedit,
-            EM_GETTEXTLENGTHEX,
-            WPARAM(&len as *const _ as _),
-            None,
-        )
-        .0 as usize
+    recognize(hwnd, image, original, Some(fingerprint))
+}
+
+/// Runs recognition again on the last clipboard image, e.g. after changing settings, and
+/// replaces its result. It's not skipped as a duplicate. Large images and those scanned
+/// long ago aren't kept.
+fn rescan(hwnd: HWND) -> Result<()> {
+    let (image, original) = last_image().clone().context("no image to rescan.")?;
+    recognize(hwnd, image, original, None)
+}
+
+fn languages() -> MutexGuard<'static, Languages> {
+    LANGUAGES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn generations() -> MutexGuard<'static, Generations> {
+    GENERATIONS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn repeats() -> MutexGuard<'static, Repeats> {
+    REPEATS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn accumulator() -> MutexGuard<'static, Accumulator> {
+    ACCUMULATOR.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn last_recognition() -> MutexGuard<'static, Option<Recognition>> {
+    LAST_RECOGNITION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn last_text() -> MutexGuard<'static, Option<String>> {
+    LAST_TEXT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Shows in the view what the replacement rules, read afresh, make of the last result.
+fn test_rules() -> Result<()> {
+    let before = last_text()
+        .clone()
+        .context("no result to test the rules on.")?;
+    let (rules, mut problems) = rules::read();
+    ensure!(
+        !rules.is_empty() || !problems.is_empty(),
+        "no replacement rules checked in {}.",
+        rules::file_name()
+    );
+    let (after, failed) = rules::apply(&rules, &before);
+    problems.extend(failed);
+    let mut shown = format!(
+        "\r\n{} replacement rules on the last result:\r\n",
+        rules.len()
+    );
+    for problem in &problems {
+        shown.push_str(&format!("! {problem}\r\n"));
+    }
+    shown.push_str(&rules::diff(&before, &after));
+    view::get()?.append(&shown);
+    match problems.len() {
+        0 => status::set("tested the replacement rules"),
+        count => status::set(&format!("{count} replacement rules failed")),
+    }
+    Ok(())
+}
+
+/// Copies the layout of the last recognition as JSON.
+fn copy_json() -> Result<()> {
+    let recognition = last_recognition()
+        .clone()
+        .context("nothing recognized yet.")?;
+    let json = json::recognition(&recognition);
+    set_clipboard(&json, &recognition.lang, &base_flavors())
+}
+
+/// Copies the selected text of the view, with the line endings of the settings.
+fn copy_selection() -> Result<()> {
+    let text = view::get()?.selection();
+    if text.is_empty() {
+        return Ok(());
+    }
+    set_clipboard(&text, &last_lang(), &base_flavors())
+}
+
+/// Copies the selected text of the view as a .txt file, and as text.
+fn copy_file() -> Result<()> {
+    let text = view::get()?.selection();
+    if text.is_empty() {
+        return Ok(());
+    }
+    let path = hdrop::write_temp(&clipboard::normalize_line_endings(&text, line_ending()))?;
+    let mut flavors = base_flavors();
+    flavors.push(Flavor {
+        format: clipboard::hdrop_format(),
+        data: hdrop::dropfiles(&[&path]),
+    });
+    set_clipboard(&text, &last_lang(), &flavors)
+}
+
+/// The language of the last recognition, empty if there was none.
+fn last_lang() -> String {
+    last_recognition()
+        .as_ref()
+        .map(|r| r.lang.clone())
+        .unwrap_or_default()
+}
+
+/// Copies `text` with the line endings of the settings. The clipboard update it causes
+/// is not scanned.
+fn set_clipboard(text: &str, lang: &str, flavors: &[Flavor]) -> Result<()> {
+    clipboard::set_text(text, lang, line_ending(), flavors)?;
+    updates().written(unsafe { GetClipboardSequenceNumber() });
+    Ok(())
+}
+
+/// The flavors that go with every text we copy.
+fn base_flavors() -> Vec<Flavor> {
+    if settings::get().exclude_from_history {
+        clipboard::history_exclusion().to_vec()
+    } else {
+        Vec::new()
+    }
+}
+
+fn line_ending() -> LineEnding {
+    if settings::get().lf_line_endings {
+        LineEnding::Lf
+    } else {
+        LineEnding::CrLf
+    }
+}
+
+fn last_image() -> MutexGuard<'static, Option<(Arc<Image>, Option<Flavor>)>> {
+    LAST_IMAGE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The tag of the language picked in the combobox, None for Auto.
+fn selected_tag(hwnd: HWND) -> Result<Option<String>> {
+    let data = unsafe {
+        let hctrl = GetDlgItem(hwnd, ID_COMBO)?;
+        let index = SendMessageW(hctrl, CB_GETCURSEL, None, None).0;
+        ensure!(
+            index != CB_ERR as isize,
+            "no recognition language selected."
+        );
+        SendMessageW(hctrl, CB_GETITEMDATA, WPARAM(index as usize), None).0
     };
-    unsafe { SendMessageW(hedit, EM_SETSEL, WPARAM(len), LPARAM(len as isize)) };
+    let languages = languages();
+    let item = languages
+        .resolve(data)
+        .context("no recognition language selected.")?;
+    Ok(match item {
+        Item::Tag(tag) => Some(tag.to_owned()),
+        Item::Auto => None,
+    })
+}
 
-    // insert the text at the new caret position
-    unsafe {
-        SendMessageW(
-            hedit,
-            EM_REPLACESEL,
-            WPARAM(1),
-            LPARAM(txt.as_ptr() as isize),
+/// The recognition language picked in the combobox.
+fn selected_language(hwnd: HWND) -> Result<Lang> {
+    Ok(match selected_tag(hwnd)? {
+        Some(tag) => Lang::Tag(Language::CreateLanguage(&HSTRING::from(&tag))?),
+        None => Lang::Profile,
+    })
+}
+
+/// Keeps the language just picked, and recognizes the last image again in it.
+fn change_language(hwnd: HWND) -> Result<()> {
+    save_language(hwnd)?;
+    let kept = last_image().is_some();
+    if kept {
+        rescan(hwnd)?;
+    }
+    Ok(())
+}
+
+/// Keeps the language picked in the combobox as the one to start with.
+fn save_language(hwnd: HWND) -> Result<()> {
+    let language = match selected_tag(hwnd)? {
+        Some(tag) => tag,
+        None => AUTO_LANGUAGE.to_owned(),
+    };
+    settings::update(|s| s.language = language);
+    Ok(())
+}
+
+/// A recognition finished on the worker thread, for `finish_recognition`.
+struct Recognized {
+    /// The text and its layout, and a note on what was done to the image.
+    result: Result<(String, Recognition, String)>,
+    settings: Settings,
+    /// The text is as recognized, see `TextOptions::raw`.
+    raw: bool,
+    original: Option<Flavor>,
+    /// Set for a new image, to skip it if it's copied again.
+    fingerprint: Option<u64>,
+    generation: u64,
+}
+
+/// Queues `image` for recognition on the worker thread, which posts the result back as
+/// WM_RECOGNIZED. `fingerprint` is that of a new image, without one the image is being
+/// rescanned and its result replaces the last one. Recognitions still queued or
+/// running are superseded: the running one is cancelled, and neither is applied.
+fn recognize(
+    hwnd: HWND,
+    image: Arc<Image>,
+    original: Option<Flavor>,
+    fingerprint: Option<u64>,
+) -> Result<()> {
+    let settings = settings::get();
+    ensure!(!recognizer(&settings).languages()?.is_empty(), NO_LANGUAGES);
+    let raw = menu::raw_text();
+    let lang = selected_language(hwnd)?;
+    let main = Hwnd::new(hwnd);
+    let generation = generations().start();
+    ocr::cancel();
+    worker::queue(move || {
+        // queued before a newer image, not worth starting.
+        if !generations().is_current(generation) {
+            return;
+        }
+        let recognized = Box::new(Recognized {
+            result: recognize_text(
+                recognizer(&settings).as_ref(),
+                &lang,
+                &image,
+                settings.clone(),
+                raw,
+                &|| generations().is_current(generation),
+            ),
+            settings,
+            raw,
+            original,
+            fingerprint,
+            generation,
+        });
+        let raw = Box::into_raw(recognized);
+        let posted =
+            unsafe { PostMessageW(main.handle(), WM_RECOGNIZED, None, LPARAM(raw as isize)) };
+        if posted.is_err() {
+            // the window is gone, nobody will take it.
+            drop(unsafe { Box::from_raw(raw) });
+        }
+    });
+    Ok(())
+}
+
+/// The engine `settings` pick.
+fn recognizer(settings: &Settings) -> Box<dyn Recognizer> {
+    #[cfg(feature = "tesseract")]
+    if settings.tesseract {
+        return Box::new(tesseract::Tesseract::new(&settings.tesseract_data));
+    }
+    let timeout = match settings.recognition_timeout_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs.into())),
+    };
+    Box::new(WinRt { timeout })
+}
+
+/// Recognizes `image` into its text, its layout, and a note on what was done to it.
+/// `raw` keeps the text of the lines as recognized. Once `current` turns false, no more
+/// passes are started and it fails with `ocr::SUPERSEDED`.
+fn recognize_text(
+    recognizer: &dyn Recognizer,
+    lang: &Lang,
+    image: &Image,
+    mut settings: Settings,
+    raw: bool,
+    current: &dyn Fn() -> bool,
+) -> Result<(String, Recognition, String)> {
+    let recognizer = &Cancellable {
+        recognizer,
+        current,
+    };
+    // decided once for the whole image, so that its bands agree.
+    let auto = settings.invert == Invert::Auto;
+    let inverted = preprocess::should_invert(settings.invert, &image.bgra);
+    settings.invert = if inverted {
+        Invert::Always
+    } else {
+        Invert::Never
+    };
+    let (mut recognition, mut note) = if settings.detect_language {
+        recognize_detected(recognizer, image, &settings)?
+    } else {
+        recognize_image(recognizer, lang, image, &settings)?
+    };
+    let mut fallen_back_from = None;
+    if !settings.detect_language
+        && settings.fallback_languages
+        && detect::needs_fallback(
+            &recognition.to_text(&TextOptions::default()),
+            settings.fallback_min_chars,
         )
+    {
+        if let Some((fallback, fallback_note)) =
+            recognize_fallback(recognizer, image, &settings, &recognition)?
+        {
+            fallen_back_from = Some(recognition.lang);
+            (recognition, note) = (fallback, fallback_note);
+        }
+    }
+    let mut text = recognition.to_text(&TextOptions {
+        keep_spaces: settings.keep_word_spaces,
+        mixed_spacing: settings.mixed_spacing,
+        layout: settings.layout,
+        detect_columns: settings.detect_columns,
+        preserve_indentation: settings.preserve_indentation,
+        indent_lists: settings.indent_lists,
+        drop_furigana: settings.drop_furigana,
+        raw,
+        dehyphenate: settings.dehyphenate,
+    });
+    if !raw && (settings.fold_width || settings.fold_compatibility) {
+        text = fold::fold(&text, settings.fold_compatibility);
+    }
+    if !raw && (settings.trim_trailing_spaces || settings.collapse_blank_lines) {
+        text = output::tidy(
+            &text,
+            settings.trim_trailing_spaces,
+            settings.collapse_blank_lines,
+        );
+    }
+    let mut notes = Vec::new();
+    if output::truncate(&mut text, settings.max_chars) {
+        log!(
+            "cut the recognized text to {} characters.",
+            settings.max_chars
+        );
+        notes.push(format!("text cut to {} characters", settings.max_chars));
+    }
+    if let Some(primary) = fallen_back_from {
+        notes.push(format!(
+            "recognized as {}, {primary} found too little",
+            recognition.lang
+        ));
+    } else if settings.detect_language {
+        notes.push(format!("detected {}", recognition.lang));
+    } else if matches!(lang, Lang::Profile) {
+        notes.push(format!("recognized as {}", recognition.lang));
+    }
+    if auto && inverted {
+        notes.push("inverted dark image".to_owned());
+    }
+    match recognition.rotation {
+        Some(degrees) if degrees % 90.0 == 0.0 => notes.push(format!("image turned {degrees}°")),
+        Some(degrees) => notes.push(format!("deskewed by {:.1}°", -degrees)),
+        None => (),
+    }
+    notes.extend(recognition.notes.iter().cloned());
+    for extra in notes {
+        if !note.is_empty() {
+            note.push_str(", ");
+        }
+        note.push_str(&extra);
+    }
+    Ok((text, recognition, note))
+}
+
+/// Recognizes `image` with each of the `detect_languages` and keeps the result scoring
+/// best, see `detect`.
+fn recognize_detected(
+    recognizer: &dyn Recognizer,
+    image: &Image,
+    settings: &Settings,
+) -> Result<(Recognition, String)> {
+    let mut results = Vec::new();
+    let mut scores = Vec::new();
+    for lang in detection_languages(recognizer, &settings.detect_languages)? {
+        let (recognition, note) = recognize_image(recognizer, &Lang::Tag(lang), image, settings)?;
+        let text = recognition.to_text(&TextOptions::default());
+        let score = detect::score(&text, &recognition.lang);
+        log!("recognized as {} scored {score:.1}.", recognition.lang);
+        results.push((recognition, note));
+        scores.push(score);
+        if detect::is_decided(&scores) {
+            break;
+        }
+    }
+    let best = detect::best(&scores).context("no language to detect.")?;
+    Ok(results.swap_remove(best))
+}
+
+/// Recognizes `image` with the other available languages, for when `primary` found little
+/// text. Returns the best of them if it reads better than `primary`.
+fn recognize_fallback(
+    recognizer: &dyn Recognizer,
+    image: &Image,
+    settings: &Settings,
+    primary: &Recognition,
+) -> Result<Option<(Recognition, String)>> {
+    let mut results = Vec::new();
+    let mut scores = Vec::new();
+    for tag in recognizer.languages()? {
+        if tag.eq_ignore_ascii_case(&primary.lang) {
+            continue;
+        }
+        let lang = Lang::Tag(Language::CreateLanguage(&HSTRING::from(tag))?);
+        let (recognition, note) = recognize_image(recognizer, &lang, image, settings)?;
+        let text = recognition.to_text(&TextOptions::default());
+        scores.push(detect::score(&text, &recognition.lang));
+        results.push((recognition, note));
+    }
+    let text = primary.to_text(&TextOptions::default());
+    let primary_score = detect::score(&text, &primary.lang);
+    log!(
+        "{} found too little text, fell back to languages scoring {scores:?}.",
+        primary.lang
+    );
+    Ok(detect::fallback(primary_score, &scores).map(|best| results.swap_remove(best)))
+}
+
+/// The languages to detect from: those of `shortlist`, comma-separated tags, or if it's
+/// empty the user profile languages. Languages `recognizer` doesn't recognize in are left
+/// out.
+fn detection_languages(recognizer: &dyn Recognizer, shortlist: &str) -> Result<Vec<Language>> {
+    let available = recognizer.languages()?;
+    let tags: Vec<HSTRING> = if shortlist.trim().is_empty() {
+        GlobalizationPreferences::Languages()?.into_iter().collect()
+    } else {
+        shortlist
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(HSTRING::from)
+            .collect()
     };
+    Ok(tags
+        .iter()
+        .filter(|tag| languages::is_available(&tag.to_string(), &available))
+        .filter_map(|tag| Language::CreateLanguage(tag).ok())
+        .collect())
+}
+
+/// Copies and shows the result of a recognition, back on the UI thread.
+fn finish_recognition(recognized: Recognized) -> Result<()> {
+    let Recognized {
+        result,
+        settings,
+        raw,
+        original,
+        fingerprint,
+        ..
+    } = recognized;
+    let (mut text, recognition, mut note) = result?;
+    *last_text() = Some(text.clone());
+    if !raw {
+        let (rules, mut problems) = rules::load();
+        let (replaced, failed) = rules::apply(&rules, &text);
+        text = replaced;
+        problems.extend(failed);
+        for problem in problems {
+            log!("{problem}");
+            if !note.is_empty() {
+                note.push_str(", ");
+            }
+            note.push_str(&problem);
+        }
+    }
+    if let Some(fingerprint) = fingerprint {
+        repeats().record(fingerprint);
+    }
+    let lang = recognition.lang.clone();
+    *last_recognition() = Some(recognition);
+
+    let output = Output::new(text, &lang, &settings);
+    let text = if settings.accumulate {
+        match fingerprint {
+            Some(_) => accumulator().push(&output.clipboard),
+            None => accumulator().replace_last(&output.clipboard),
+        }
+    } else {
+        output.clipboard
+    };
+    let mut flavors = base_flavors();
+    if settings.copy_html {
+        flavors.push(Flavor {
+            format: clipboard::html_format(),
+            data: html::cf_html(&text).into_bytes(),
+        });
+    }
+    if settings.copy_rtf {
+        flavors.push(Flavor {
+            format: clipboard::rtf_format(),
+            data: rtf::rtf(&text, &lang).into_bytes(),
+        });
+    }
+    if settings.keep_image {
+        flavors.extend(original);
+    }
+    set_clipboard(&text, &lang, &flavors)?;
+
+    status::STATS.add_scanned();
+    status::set(&note);
 
-    // scroll to the end of richedit
-    unsafe { SendMessageW(hedit, WM_VSCROLL, WPARAM(SB_BOTTOM.0 as _), None) };
+    match fingerprint {
+        Some(_) => view::get()?.append(&output.view),
+        None => view::get()?.replace_last(&output.view),
+    }
     Ok(())
 }
 
+/// Recognizes `image`, scaled down or cut into bands first if the engine can't take it
+/// whole. Returns the recognition, laid out over `image`, and a note on what was done to
+/// it.
+fn recognize_image(
+    recognizer: &dyn Recognizer,
+    lang: &Lang,
+    image: &Image,
+    settings: &Settings,
+) -> Result<(Recognition, String)> {
+    let mut max = recognizer.max_image_dimension()?;
+    if settings.pad_margin {
+        // the margin has to fit too.
+        max = max.saturating_sub(2 * PAD_MARGIN as u32);
+    }
+    let Image { width, height, .. } = *image;
+    let scale = oversize::fit_scale(width, height, max);
+    if scale == 1.0 {
+        let recognition = recognize_pixels(recognizer, lang, width, height, &image.bgra, settings)?;
+        return Ok((recognition, String::new()));
+    }
+
+    let (mut recognition, note) = match settings.oversize {
+        Oversize::Reject => {
+            bail!("the {width}x{height} image exceeds the engine's limit of {max} pixels per side.")
+        }
+        Oversize::Downscale => {
+            let scaled = downscale(image, scale);
+            let recognition = recognize_pixels(
+                recognizer,
+                lang,
+                scaled.width,
+                scaled.height,
+                &scaled.bgra,
+                settings,
+            )?;
+            let note = format!("image reduced to {:.0}%", scale * 100.0);
+            (recognition, note)
+        }
+        Oversize::Tile => {
+            // bands only split the height, a too wide image is scaled down to fit first.
+            let scale = oversize::fit_scale(width, 1, max);
+            let scaled = downscale(image, scale);
+            let recognition = recognize_bands(recognizer, lang, &scaled, max as i32, settings)?;
+            let mut note = "image recognized in bands".to_owned();
+            if scale < 1.0 {
+                note.push_str(&format!(", reduced to {:.0}%", scale * 100.0));
+            }
+            (recognition, note)
+        }
+    };
+    recognition.map_words(width as f32 / recognition.width as f32, 0.0, 0.0);
+    recognition.width = width;
+    recognition.height = height;
+    log!("{width}x{height} image: {note}.");
+    Ok((recognition, note))
+}
+
+/// `image` scaled by `scale`, itself if that's 1.
+fn downscale(image: &Image, scale: f32) -> Cow<'_, Image> {
+    if scale >= 1.0 {
+        return Cow::Borrowed(image);
+    }
+    let size = |side: i32| ((side as f32 * scale).round() as i32).max(1);
+    Cow::Owned(image.downscale(size(image.width), size(image.height)))
+}
+
+/// Recognizes `image` in overlapping bands of at most `max` rows, dropping the lines
+/// found twice in an overlap.
+fn recognize_bands(
+    recognizer: &dyn Recognizer,
+    lang: &Lang,
+    image: &Image,
+    max: i32,
+    settings: &Settings,
+) -> Result<Recognition> {
+    let row_len = image.width as usize * 4;
+    let mut recognition: Option<Recognition> = None;
+    for (top, rows) in oversize::bands(image.height, max, oversize::BAND_OVERLAP) {
+        let band = &image.bgra[top as usize * row_len..(top + rows) as usize * row_len];
+        let mut band_recognition =
+            recognize_pixels(recognizer, lang, image.width, rows, band, settings)?;
+        let texts = |recognition: &Recognition| -> Vec<String> {
+            recognition
+                .lines
+                .iter()
+                .map(|line| line.text.clone())
+                .collect()
+        };
+        let previous = recognition.as_ref().map(texts).unwrap_or_default();
+        let band_lines = texts(&band_recognition);
+        let repeated = oversize::overlap(
+            &previous.iter().map(String::as_str).collect::<Vec<_>>(),
+            &band_lines.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+
+        band_recognition.map_words(1.0, 0.0, top as f32);
+        let band_lines = band_recognition.lines.into_iter().skip(repeated);
+        match &mut recognition {
+            Some(recognition) => {
+                recognition.lines.extend(band_lines);
+                for note in band_recognition.notes {
+                    if !recognition.notes.contains(&note) {
+                        recognition.notes.push(note);
+                    }
+                }
+            }
+            None => {
+                band_recognition.lines = band_lines.collect();
+                recognition = Some(band_recognition);
+            }
+        }
+    }
+    let mut recognition = recognition.context("no bands to recognize.")?;
+    recognition.width = image.width;
+    recognition.height = image.height;
+    Ok(recognition)
+}
+
+/// Recognizes BGRA pixels, turned a quarter, a half and three quarters too if they read
+/// too little, as sideways scans do. The pass reading the most letters wins.
+fn recognize_pixels(
+    recognizer: &dyn Recognizer,
+    lang: &Lang,
+    width: i32,
+    height: i32,
+    bgra: &[u8],
+    settings: &Settings,
+) -> Result<Recognition> {
+    let mut best = recognize_straight(recognizer, lang, width, height, bgra, settings)?;
+    if !settings.try_rotations || best.letters() >= settings.rotation_min_letters {
+        return Ok(best);
+    }
+
+    let mut turned_better = false;
+    for turns in 1..4 {
+        let turned = image::rotate_quarters(width, height, bgra, turns);
+        let mut recognition = recognize_once(
+            recognizer,
+            lang,
+            turned.width,
+            turned.height,
+            &turned.bgra,
+            settings,
+        )?;
+        let degrees = turns as f64 * 90.0;
+        log!(
+            "{} letters read turned {degrees} degrees, {} before.",
+            recognition.letters(),
+            best.letters()
+        );
+        if recognition.letters() > best.letters() {
+            recognition.unrotate(degrees, width, height);
+            recognition.rotation = Some(degrees);
+            best = recognition;
+            turned_better = true;
+        }
+    }
+    if !turned_better {
+        best.notes
+            .push("little text found, turning the image read no more".to_owned());
+    }
+    Ok(best)
+}
+
+/// Recognizes BGRA pixels, and again straightened if the engine finds their text
+/// skewed, keeping the pass that reads more.
+fn recognize_straight(
+    recognizer: &dyn Recognizer,
+    lang: &Lang,
+    width: i32,
+    height: i32,
+    bgra: &[u8],
+    settings: &Settings,
+) -> Result<Recognition> {
+    let recognition = recognize_once(recognizer, lang, width, height, bgra, settings)?;
+    let Some(angle) = recognition
+        .angle
+        .filter(|angle| angle.abs() > DESKEW_MIN_ANGLE)
+    else {
+        return Ok(recognition);
+    };
+
+    let rotated = image::rotate(width, height, bgra, -angle);
+    let mut straight = recognize_once(
+        recognizer,
+        lang,
+        rotated.width,
+        rotated.height,
+        &rotated.bgra,
+        settings,
+    )?;
+    log!(
+        "text skewed by {angle:.1} degrees, {} letters read straightened against {}.",
+        straight.letters(),
+        recognition.letters()
+    );
+    if straight.letters() <= recognition.letters() {
+        let mut recognition = recognition;
+        recognition.notes.push(format!(
+            "text skewed by {angle:.1}°, straightened read no better"
+        ));
+        return Ok(recognition);
+    }
+    straight.unrotate(-angle, width, height);
+    straight.rotation = Some(-angle);
+    Ok(straight)
+}
+
+/// Recognizes BGRA pixels, preprocessed on their way into the engine's bitmap. Text
+/// touching the edges reads better with a margin of background around it.
+fn recognize_once(
+    recognizer: &dyn Recognizer,
+    lang: &Lang,
+    width: i32,
+    height: i32,
+    bgra: &[u8],
+    settings: &Settings,
+) -> Result<Recognition> {
+    if !settings.pad_margin {
+        return recognize_unpadded(recognizer, lang, width, height, bgra, settings);
+    }
+    let background = image::edge_color(width, height, bgra);
+    let padded = image::pad(width, height, bgra, PAD_MARGIN, background);
+    let mut recognition = recognize_unpadded(
+        recognizer,
+        lang,
+        padded.width,
+        padded.height,
+        &padded.bgra,
+        settings,
+    )?;
+    let margin = -PAD_MARGIN as f32;
+    recognition.map_words(1.0, margin, margin);
+    recognition.width = width;
+    recognition.height = height;
+    Ok(recognition)
+}
+
+fn recognize_unpadded(
+    recognizer: &dyn Recognizer,
+    lang: &Lang,
+    width: i32,
+    height: i32,
+    bgra: &[u8],
+    settings: &Settings,
+) -> Result<Recognition> {
+    let mut timings = Vec::new();
+    let mut pixels = |dst: &mut [u8]| timings = preprocess::run(bgra, dst, width, height, settings);
+    let outcome = recognizer.recognize(width, height, &mut pixels, &lang.tag()?)?;
+    let recognition = Recognition::new(outcome, width, height);
+    if !timings.is_empty() {
+        let steps: Vec<String> = timings
+            .iter()
+            .map(|(step, time)| format!("{step} {} ms", time.as_millis()))
+            .collect();
+        log!(
+            "preprocessed a {width}x{height} image: {}.",
+            steps.join(", ")
+        );
+    }
+    Ok(recognition)
+}
+
 fn destroy(hwnd: HWND) {
     unsafe {
+        _ = KillTimer(hwnd, ID_TIMER_LISTENER);
+        _ = KillTimer(hwnd, ID_TIMER_REPROBE);
+        _ = KillTimer(hwnd, ID_TIMER_DEBOUNCE);
         _ = RemoveClipboardFormatListener(hwnd);
+    }
+    // the text can't be rendered once we're gone.
+    if let Err(e) = clipboard::render_all(hwnd) {
+        log!("failed to render clipboard formats: {e:#}");
+    }
+    hdrop::remove_temp();
+    unsafe {
         PostQuitMessage(0);
     }
 }
@@ -322,6 +1615,7 @@ unsafe extern "system" fn enum_win(hwnd: HWND, lparam: LPARAM) -> BOOL {
                 _ = ShowWindow(hwnd, SW_SHOW);
             }
             _ = SetForegroundWindow(hwnd);
+            _ = PostMessageW(hwnd, WM_SCAN_NOW, None, None);
         }
         return false.into();
     }
@@ -332,6 +1626,7 @@ fn is_already_running() -> bool {
     unsafe { EnumWindows(Some(enum_win), None).is_err() }
 }
 
+/// Brings the running instance to the front, and has it scan the clipboard.
 fn set_focus_existing_window() {
     _ = unsafe { EnumWindows(Some(enum_win), LPARAM(1)) };
 }
@@ -350,6 +1645,13 @@ fn main() -> Result<()> {
     };
 
     unsafe { RegisterClassW(&wc) };
+    unsafe { InitCommonControls() };
+
+    settings::load();
+    if let Err(e) = settings::apply_args(std::env::args().skip(1)) {
+        log!("{e}");
+    }
+    let menu = menu::create()?;
 
     let hwnd = unsafe {
         CreateWindowExW(
@@ -362,7 +1664,7 @@ fn main() -> Result<()> {
             600,
             480,
             None,
-            None,
+            menu,
             None,
             None,
         )?
@@ -372,12 +1674,22 @@ fn main() -> Result<()> {
 
     unsafe { ShowWindow(hwnd, SW_SHOW).ok()? };
 
+    let accelerators = [ACCEL {
+        fVirt: FVIRTKEY,
+        key: VK_F5.0,
+        cmd: menu::ID_RESCAN as u16,
+    }];
+    let haccel = unsafe { CreateAcceleratorTableW(&accelerators)? };
+
     let mut msg = MSG::default();
     loop {
         if !unsafe { GetMessageW(&mut msg, None, 0, 0) }.as_bool() {
             break;
         }
         unsafe {
+            if TranslateAcceleratorW(hwnd, haccel, &msg) != 0 {
+                continue;
+            }
             _ = TranslateMessage(&msg);
             DispatchMessageW(&msg);
         }
@@ -394,3 +1706,74 @@ fn loword(dword: u32) -> u16 {
 fn hiword(dword: u32) -> u16 {
     (dword >> 16) as _
 }
+
+#[test]
+fn recognize_once_test() {
+    use ocr::{Line, MockRecognizer, OcrOutcome, Word};
+    // the engine sees the image padded, and its words come back where they are unpadded.
+    let word = Word {
+        text: "margin".to_owned(),
+        x: PAD_MARGIN as f32 + 5.0,
+        y: PAD_MARGIN as f32 + 2.0,
+        width: 30.0,
+        height: 10.0,
+    };
+    let recognizer = MockRecognizer::new(OcrOutcome {
+        lang: "en-US".to_owned(),
+        lines: vec![Line {
+            text: word.text.clone(),
+            words: vec![word],
+        }],
+        angle: None,
+    });
+    let settings = Settings {
+        pad_margin: true,
+        ..Settings::default()
+    };
+    let bgra = vec![255; 40 * 20 * 4];
+    let recognition =
+        recognize_once(&recognizer, &Lang::Profile, 40, 20, &bgra, &settings).unwrap();
+    assert_eq!((recognition.width, recognition.height), (40, 20));
+    let word = &recognition.lines[0].words[0];
+    assert_eq!((word.x, word.y), (5.0, 2.0));
+}
+
+#[test]
+fn superseded_test() {
+    use ocr::{MockRecognizer, OcrOutcome};
+    use std::cell::RefCell;
+    // reads nothing, so every rotation would be tried after the first pass.
+    let recognizer = MockRecognizer::new(OcrOutcome {
+        lang: "en-US".to_owned(),
+        lines: Vec::new(),
+        angle: None,
+    });
+    let generations = RefCell::new(Generations::new());
+    let generation = generations.borrow_mut().start();
+    let current = || {
+        // a new image comes in while the first pass runs.
+        if recognizer.passes.get() == 1 {
+            generations.borrow_mut().start();
+        }
+        generations.borrow().is_current(generation)
+    };
+    let image = Image {
+        width: 40,
+        height: 20,
+        bgra: vec![255; 40 * 20 * 4],
+    };
+    let settings = Settings {
+        try_rotations: true,
+        ..Settings::default()
+    };
+    let result = recognize_text(
+        &recognizer,
+        &Lang::Profile,
+        &image,
+        settings,
+        false,
+        &current,
+    );
+    assert_eq!(result.unwrap_err().to_string(), ocr::SUPERSEDED);
+    assert_eq!(recognizer.passes.get(), 1);
+}
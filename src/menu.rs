@@ -0,0 +1,283 @@
+use super::settings::{self, Settings};
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::HWND,
+        UI::WindowsAndMessaging::{
+            AppendMenuW, CheckMenuItem, CreateMenu, CreatePopupMenu, GetMenu, HMENU, MF_CHECKED,
+            MF_POPUP, MF_SEPARATOR, MF_STRING, MF_UNCHECKED,
+        },
+    },
+};
+
+const ID_STAMP_LANGUAGE: usize = 2000;
+const ID_PAUSED: usize = 2001;
+const ID_SCAN_PATHS: usize = 2002;
+const ID_SCAN_DATA_URIS: usize = 2003;
+const ID_COPY_HTML: usize = 2004;
+const ID_COPY_RTF: usize = 2005;
+const ID_KEEP_IMAGE: usize = 2006;
+const ID_LF_LINE_ENDINGS: usize = 2007;
+const ID_ACCUMULATE: usize = 2008;
+const ID_EXCLUDE_FROM_HISTORY: usize = 2009;
+const ID_SCAN_AT_STARTUP: usize = 2010;
+const ID_BINARIZE: usize = 2011;
+const ID_NORMALIZE: usize = 2012;
+const ID_TRY_ROTATIONS: usize = 2013;
+const ID_PAD_MARGIN: usize = 2014;
+const ID_DENOISE: usize = 2015;
+const ID_SHARPEN: usize = 2016;
+const ID_FORCE_CLEANUP: usize = 2017;
+const ID_REMEMBER_CROP: usize = 2018;
+const ID_DETECT_LANGUAGE: usize = 2019;
+const ID_FALLBACK_LANGUAGES: usize = 2020;
+const ID_DETECT_COLUMNS: usize = 2021;
+const ID_PRESERVE_INDENTATION: usize = 2022;
+const ID_INDENT_LISTS: usize = 2023;
+const ID_DROP_FURIGANA: usize = 2024;
+const ID_RAW_TEXT: usize = 2025;
+#[cfg(feature = "tesseract")]
+const ID_TESSERACT: usize = 2026;
+const ID_FOLD_WIDTH: usize = 2027;
+const ID_FOLD_COMPATIBILITY: usize = 2028;
+const ID_TRIM_TRAILING_SPACES: usize = 2029;
+const ID_COLLAPSE_BLANK_LINES: usize = 2030;
+pub const ID_ADJUST: usize = 2100;
+pub const ID_RESET_ACCUMULATION: usize = 2101;
+pub const ID_RESCAN: usize = 2102;
+pub const ID_CROP: usize = 2103;
+pub const ID_REFRESH_LANGUAGES: usize = 2104;
+pub const ID_TEST_RULES: usize = 2105;
+
+// checkable menu items and the setting each of them toggles.
+#[allow(clippy::type_complexity)]
+const TOGGLES: &[(usize, PCWSTR, fn(&mut Settings) -> &mut bool)] = &[
+    (ID_PAUSED, w!("&Pause monitoring"), |s| &mut s.paused),
+    (
+        ID_STAMP_LANGUAGE,
+        w!("Add &language tag to copied text"),
+        |s| &mut s.stamp_language,
+    ),
+    (ID_LF_LINE_ENDINGS, w!("Copy with L&F line endings"), |s| {
+        &mut s.lf_line_endings
+    }),
+    (
+        ID_TRIM_TRAILING_SPACES,
+        w!("Trim spaces at the end of &lines"),
+        |s| &mut s.trim_trailing_spaces,
+    ),
+    (ID_COLLAPSE_BLANK_LINES, w!("Collapse blank li&nes"), |s| {
+        &mut s.collapse_blank_lines
+    }),
+    (ID_COPY_HTML, w!("Also copy as &HTML"), |s| &mut s.copy_html),
+    (ID_COPY_RTF, w!("Also copy as &RTF"), |s| &mut s.copy_rtf),
+    (ID_KEEP_IMAGE, w!("&Keep the image on the clipboard"), |s| {
+        &mut s.keep_image
+    }),
+    (ID_ACCUMULATE, w!("Acc&umulate on clipboard"), |s| {
+        &mut s.accumulate
+    }),
+    (
+        ID_EXCLUDE_FROM_HISTORY,
+        w!("E&xclude from clipboard history"),
+        |s| &mut s.exclude_from_history,
+    ),
+    (
+        ID_SCAN_AT_STARTUP,
+        w!("Scan the clipboard at &startup"),
+        |s| &mut s.scan_at_startup,
+    ),
+    (ID_DETECT_LANGUAGE, w!("D&etect the language"), |s| {
+        &mut s.detect_language
+    }),
+    (
+        ID_FALLBACK_LANGUAGES,
+        w!("Tr&y other languages when little text is found"),
+        |s| &mut s.fallback_languages,
+    ),
+    (
+        ID_DETECT_COLUMNS,
+        w!("Reading order: detect &columns"),
+        |s| &mut s.detect_columns,
+    ),
+    (ID_PRESERVE_INDENTATION, w!("Preserve &indentation"), |s| {
+        &mut s.preserve_indentation
+    }),
+    (
+        ID_INDENT_LISTS,
+        w!("Indent list items and their &wrapped lines"),
+        |s| &mut s.indent_lists,
+    ),
+    (ID_DROP_FURIGANA, w!("Leave out &Japanese furigana"), |s| {
+        &mut s.drop_furigana
+    }),
+    (
+        ID_TRY_ROTATIONS,
+        w!("&Turn the image when no text is found"),
+        |s| &mut s.try_rotations,
+    ),
+    (
+        ID_REMEMBER_CROP,
+        w!("Keep cropping to the same re&gion"),
+        |s| &mut s.remember_crop,
+    ),
+    (ID_PAD_MARGIN, w!("Add a &margin around the image"), |s| {
+        &mut s.pad_margin
+    }),
+    (ID_NORMALIZE, w!("N&ormalize contrast"), |s| {
+        &mut s.normalize
+    }),
+    (ID_BINARIZE, w!("&Binarize before recognition"), |s| {
+        &mut s.binarize
+    }),
+    (ID_SCAN_PATHS, w!("Scan copied image &paths"), |s| {
+        &mut s.scan_paths
+    }),
+    (ID_SCAN_DATA_URIS, w!("Scan copied &data URIs"), |s| {
+        &mut s.scan_data_uris
+    }),
+];
+
+// the engine toggle, in builds with another engine than that of Windows.
+#[cfg(feature = "tesseract")]
+#[allow(clippy::type_complexity)]
+const ENGINE_TOGGLES: &[(usize, PCWSTR, fn(&mut Settings) -> &mut bool)] =
+    &[(ID_TESSERACT, w!("Recognize with Tesseract"), |s| {
+        &mut s.tesseract
+    })];
+#[cfg(not(feature = "tesseract"))]
+#[allow(clippy::type_complexity)]
+const ENGINE_TOGGLES: &[(usize, PCWSTR, fn(&mut Settings) -> &mut bool)] = &[];
+
+// the raw text toggle, for the session only.
+static RAW_TEXT: AtomicBool = AtomicBool::new(false);
+
+/// Whether to copy the lines as the engine recognized them, see `TextOptions::raw`.
+pub fn raw_text() -> bool {
+    RAW_TEXT.load(Ordering::Relaxed)
+}
+
+// the items of the Image cleanup submenu, like `TOGGLES`.
+#[allow(clippy::type_complexity)]
+const CLEANUP_TOGGLES: &[(usize, PCWSTR, fn(&mut Settings) -> &mut bool)] = &[
+    (ID_DENOISE, w!("&Denoise"), |s| &mut s.denoise),
+    (ID_SHARPEN, w!("&Sharpen"), |s| &mut s.sharpen),
+    (ID_FORCE_CLEANUP, w!("Also on &large images"), |s| {
+        &mut s.force_cleanup
+    }),
+];
+
+// the items of the Character width submenu, like `TOGGLES`.
+#[allow(clippy::type_complexity)]
+const FOLD_TOGGLES: &[(usize, PCWSTR, fn(&mut Settings) -> &mut bool)] = &[
+    (
+        ID_FOLD_WIDTH,
+        w!("&Half-width letters and digits, full-width kana"),
+        |s| &mut s.fold_width,
+    ),
+    (
+        ID_FOLD_COMPATIBILITY,
+        w!("Also &compatibility characters, like ① and ㌔ (NFKC)"),
+        |s| &mut s.fold_compatibility,
+    ),
+];
+
+/// Creates the menu bar of the main window.
+pub fn create() -> Result<HMENU> {
+    let mut current = settings::get();
+
+    let options = unsafe { CreatePopupMenu()? };
+    append_toggles(options, TOGGLES, &mut current)?;
+    append_toggles(options, ENGINE_TOGGLES, &mut current)?;
+    let check = if raw_text() { MF_CHECKED } else { MF_UNCHECKED };
+    let raw_text = w!("Ra&w text as recognized, until closed");
+    unsafe { AppendMenuW(options, MF_STRING | check, ID_RAW_TEXT, raw_text)? };
+
+    let cleanup = unsafe { CreatePopupMenu()? };
+    append_toggles(cleanup, CLEANUP_TOGGLES, &mut current)?;
+    unsafe { AppendMenuW(options, MF_POPUP, cleanup.0 as _, w!("&Image cleanup"))? };
+
+    let fold = unsafe { CreatePopupMenu()? };
+    append_toggles(fold, FOLD_TOGGLES, &mut current)?;
+    unsafe { AppendMenuW(options, MF_POPUP, fold.0 as _, w!("C&haracter width"))? };
+
+    unsafe {
+        AppendMenuW(
+            options,
+            MF_STRING,
+            ID_RESET_ACCUMULATION,
+            w!("&Reset accumulation"),
+        )?
+    };
+    unsafe { AppendMenuW(options, MF_SEPARATOR, 0, None)? };
+    unsafe { AppendMenuW(options, MF_STRING, ID_RESCAN, w!("Resca&n last image\tF5"))? };
+    unsafe { AppendMenuW(options, MF_STRING, ID_CROP, w!("&Crop then OCR..."))? };
+    unsafe { AppendMenuW(options, MF_STRING, ID_ADJUST, w!("&Adjust image..."))? };
+    unsafe {
+        AppendMenuW(
+            options,
+            MF_STRING,
+            ID_REFRESH_LANGUAGES,
+            w!("&Refresh languages"),
+        )?
+    };
+    unsafe {
+        AppendMenuW(
+            options,
+            MF_STRING,
+            ID_TEST_RULES,
+            w!("Test replacement r&ules on the last result"),
+        )?
+    };
+
+    let menu = unsafe { CreateMenu()? };
+    unsafe { AppendMenuW(menu, MF_POPUP, options.0 as _, w!("&Options"))? };
+    Ok(menu)
+}
+
+/// Appends checkable `toggles` to `menu`, checked as in `current`.
+#[allow(clippy::type_complexity)]
+fn append_toggles(
+    menu: HMENU,
+    toggles: &[(usize, PCWSTR, fn(&mut Settings) -> &mut bool)],
+    current: &mut Settings,
+) -> Result<()> {
+    for (id, text, field) in toggles {
+        let check = if *field(current) {
+            MF_CHECKED
+        } else {
+            MF_UNCHECKED
+        };
+        unsafe { AppendMenuW(menu, MF_STRING | check, *id, *text)? };
+    }
+    Ok(())
+}
+
+/// Handles a WM_COMMAND from the menu bar, returning false if `id` is not one of ours.
+pub fn command(hwnd: HWND, id: usize) -> bool {
+    if id == ID_RAW_TEXT {
+        let checked = !RAW_TEXT.fetch_xor(true, Ordering::Relaxed);
+        let check = if checked { MF_CHECKED } else { MF_UNCHECKED };
+        unsafe { CheckMenuItem(GetMenu(hwnd), id as u32, check.0) };
+        return true;
+    }
+    let Some((_, _, field)) = TOGGLES
+        .iter()
+        .chain(CLEANUP_TOGGLES)
+        .chain(FOLD_TOGGLES)
+        .chain(ENGINE_TOGGLES)
+        .find(|(toggle, _, _)| *toggle == id)
+    else {
+        return false;
+    };
+    let checked = settings::update(|s| {
+        let value = field(s);
+        *value = !*value;
+        *value
+    });
+    let check = if checked { MF_CHECKED } else { MF_UNCHECKED };
+    unsafe { CheckMenuItem(GetMenu(hwnd), id as u32, check.0) };
+    true
+}
@@ -1,23 +1,607 @@
-use super::{BUF_SIZE, DISPLAY_NAMES, ID_COMBO};
-use anyhow::{Context, Result};
-use std::io::{Cursor, Write};
+use super::columns;
+use super::furigana;
+use super::hyphen::{self, Dehyphenate};
+use super::image::Region;
+use super::indent;
+use super::list::{self, ListLine, Role};
+use super::paragraph::{self, Break, LineBox};
+use super::table;
+use anyhow::{bail, ensure, Context, Error, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::ptr;
 use std::slice;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
 use windows::{
     core::{Interface, HSTRING},
+    Foundation::{AsyncOperationCompletedHandler, IAsyncOperation},
     Globalization::Language,
     Graphics::Imaging::{BitmapBufferAccessMode, BitmapPixelFormat, SoftwareBitmap},
-    Media::Ocr::OcrEngine,
-    Win32::{
-        Foundation::{HWND, LPARAM, WPARAM},
-        System::WinRT::IMemoryBufferByteAccess,
-        UI::WindowsAndMessaging::{
-            GetDlgItem, SendMessageW, CB_GETCURSEL, CB_GETLBTEXT, CB_GETLBTEXTLEN,
-        },
-    },
+    Media::Ocr::{OcrEngine, OcrResult},
+    Win32::System::WinRT::IMemoryBufferByteAccess,
 };
 
-pub fn scan(hwnd: HWND, width: i32, height: i32, bgra: Vec<u8>, buf: &mut [u8]) -> Result<usize> {
+// the recognition running on the worker thread, for `cancel`.
+static IN_FLIGHT: Mutex<Option<IAsyncOperation<OcrResult>>> = Mutex::new(None);
+// how many times `cancel` was called, so a recognition it came too early for, still
+// preprocessing or not yet in flight, is cancelled too.
+static CANCELS: AtomicU64 = AtomicU64::new(0);
+
+/// The language to recognize.
+#[derive(Debug, Clone)]
+pub enum Lang {
+    /// The first of the user profile languages the engine supports.
+    Profile,
+    Tag(Language),
+}
+
+impl Lang {
+    /// The language tag, empty for the user profile languages.
+    pub fn tag(&self) -> Result<String> {
+        Ok(match self {
+            Self::Profile => String::new(),
+            Self::Tag(lang) => lang.LanguageTag()?.to_string(),
+        })
+    }
+}
+
+/// An OCR engine.
+pub trait Recognizer {
+    /// Recognizes a `width` x `height` image in `lang`, a language tag like `en-US`, empty
+    /// for the user profile languages. `pixels` writes its BGRA pixels into the buffer the
+    /// engine reads, sparing a copy.
+    fn recognize(
+        &self,
+        width: i32,
+        height: i32,
+        pixels: &mut dyn FnMut(&mut [u8]),
+        lang: &str,
+    ) -> Result<OcrOutcome>;
+
+    /// The tags of the languages the engine recognizes in.
+    fn languages(&self) -> Result<Vec<String>>;
+
+    /// The most pixels the engine takes on either side of an image.
+    fn max_image_dimension(&self) -> Result<u32>;
+}
+
+/// What an engine recognized in an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrOutcome {
+    /// The tag of the language the engine used.
+    pub lang: String,
+    pub lines: Vec<Line>,
+    /// Degrees the text is skewed by, None if the engine can't tell.
+    pub angle: Option<f64>,
+}
+
+/// The engine of Windows, `Windows.Media.Ocr`. A recognition still running after
+/// `timeout` is cancelled and its result never read, if it ever comes.
+pub struct WinRt {
+    pub timeout: Option<Duration>,
+}
+
+impl Recognizer for WinRt {
+    fn recognize(
+        &self,
+        width: i32,
+        height: i32,
+        pixels: &mut dyn FnMut(&mut [u8]),
+        lang: &str,
+    ) -> Result<OcrOutcome> {
+        let lang = match lang {
+            "" => Lang::Profile,
+            tag => Lang::Tag(Language::CreateLanguage(&HSTRING::from(tag))?),
+        };
+        recognize(&lang, width, height, pixels, self.timeout)
+    }
+
+    fn languages(&self) -> Result<Vec<String>> {
+        OcrEngine::AvailableRecognizerLanguages()?
+            .into_iter()
+            .map(|lang| Ok(lang.LanguageTag()?.to_string()))
+            .collect()
+    }
+
+    fn max_image_dimension(&self) -> Result<u32> {
+        Ok(OcrEngine::MaxImageDimension()?)
+    }
+}
+
+/// Recognizes with `recognizer` while `current` says the job it's for is, failing with
+/// `SUPERSEDED` instead of starting a pass once a newer job took over.
+pub struct Cancellable<'a> {
+    pub recognizer: &'a dyn Recognizer,
+    pub current: &'a dyn Fn() -> bool,
+}
+
+/// What the passes of a superseded job fail with.
+pub const SUPERSEDED: &str = "superseded by a newer recognition.";
+
+impl Recognizer for Cancellable<'_> {
+    fn recognize(
+        &self,
+        width: i32,
+        height: i32,
+        pixels: &mut dyn FnMut(&mut [u8]),
+        lang: &str,
+    ) -> Result<OcrOutcome> {
+        ensure!((self.current)(), SUPERSEDED);
+        let outcome = self.recognizer.recognize(width, height, pixels, lang)?;
+        // superseded while it ran, what it found won't be used.
+        ensure!((self.current)(), SUPERSEDED);
+        Ok(outcome)
+    }
+
+    fn languages(&self) -> Result<Vec<String>> {
+        self.recognizer.languages()
+    }
+
+    fn max_image_dimension(&self) -> Result<u32> {
+        self.recognizer.max_image_dimension()
+    }
+}
+
+/// An engine that finds the same lines in any image, for tests, counting its passes.
+#[cfg(test)]
+pub struct MockRecognizer {
+    pub outcome: OcrOutcome,
+    pub passes: std::cell::Cell<usize>,
+}
+
+#[cfg(test)]
+impl MockRecognizer {
+    pub fn new(outcome: OcrOutcome) -> Self {
+        Self {
+            outcome,
+            passes: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Recognizer for MockRecognizer {
+    fn recognize(
+        &self,
+        _: i32,
+        _: i32,
+        _: &mut dyn FnMut(&mut [u8]),
+        _: &str,
+    ) -> Result<OcrOutcome> {
+        self.passes.set(self.passes.get() + 1);
+        Ok(self.outcome.clone())
+    }
+
+    fn languages(&self) -> Result<Vec<String>> {
+        Ok(vec![self.outcome.lang.clone()])
+    }
+
+    fn max_image_dimension(&self) -> Result<u32> {
+        Ok(10000)
+    }
+}
+
+/// A recognized word and its bounding box in image pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Word {
+    /// Whether the box of the word overlaps `region`.
+    pub fn intersects(&self, region: &Region) -> bool {
+        !region.is_empty()
+            && self.x < (region.x + region.width) as f32
+            && self.x + self.width > region.x as f32
+            && self.y < (region.y + region.height) as f32
+            && self.y + self.height > region.y as f32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub text: String,
+    pub words: Vec<Word>,
+}
+
+impl Line {
+    /// The box around the words, None without any.
+    fn bounds(&self) -> Option<LineBox> {
+        let first = self.words.first()?;
+        let start = LineBox {
+            left: first.x,
+            top: first.y,
+            right: first.x + first.width,
+            bottom: first.y + first.height,
+        };
+        Some(self.words.iter().fold(start, |b, word| LineBox {
+            left: b.left.min(word.x),
+            top: b.top.min(word.y),
+            right: b.right.max(word.x + word.width),
+            bottom: b.bottom.max(word.y + word.height),
+        }))
+    }
+}
+
+/// The layout of a recognition, as reported by the engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recognition {
+    /// The tag of the language used.
+    pub lang: String,
+    pub width: i32,
+    pub height: i32,
+    pub lines: Vec<Line>,
+    /// The clockwise skew of the text in degrees, if the engine found one.
+    pub angle: Option<f64>,
+    /// The clockwise rotation the image went through before recognition, in degrees.
+    pub rotation: Option<f64>,
+    /// What else was tried on the image without reading better, for the status.
+    pub notes: Vec<String>,
+}
+
+/// How `Recognition::to_text` lays out the words and lines.
+#[derive(Debug, Clone, Default)]
+pub struct TextOptions {
+    /// Keep the space the engine puts between any two words. Otherwise words are spaced
+    /// by their scripts, see `join`.
+    pub keep_spaces: bool,
+    /// What goes between a CJK word and a Latin one.
+    pub mixed_spacing: MixedSpacing,
+    pub layout: Layout,
+    /// What to do with words hyphenated across lines.
+    pub dehyphenate: Dehyphenate,
+    /// Read the columns found one after another instead of the lines as recognized, see
+    /// `columns`.
+    pub detect_columns: bool,
+    /// Start each line with spaces for how far right it starts, e.g. to keep the
+    /// indentation of code. Only with the `lines` layout.
+    pub preserve_indentation: bool,
+    /// Indent nested list items and the wrapped lines of items to line up with the text
+    /// of their item, see `list`. Only with the `lines` layout, without
+    /// `preserve_indentation`.
+    pub indent_lists: bool,
+    /// Leave out the lines of furigana over Japanese text, see `furigana`.
+    pub drop_furigana: bool,
+    /// The text of each line exactly as the engine returned it, ignoring all the other
+    /// options.
+    pub raw: bool,
+}
+
+/// How recognized lines make up the text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// A line per line found.
+    #[default]
+    Lines,
+    /// The lines of a paragraph joined, paragraphs separated by a blank line, see
+    /// `paragraph`.
+    Paragraphs,
+    /// A line of tab-separated cells per table row, see `table`.
+    Table,
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(Self::Lines),
+            "paragraphs" => Ok(Self::Paragraphs),
+            "table" => Ok(Self::Table),
+            _ => bail!("unknown layout {s}."),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lines => "lines",
+            Self::Paragraphs => "paragraphs",
+            Self::Table => "table",
+        })
+    }
+}
+
+/// The separator between CJK and Latin text, e.g. `これは test です`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MixedSpacing {
+    None,
+    #[default]
+    Space,
+    /// A thin space, U+2009.
+    Thin,
+}
+
+impl MixedSpacing {
+    fn separator(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Space => " ",
+            Self::Thin => "\u{2009}",
+        }
+    }
+}
+
+impl FromStr for MixedSpacing {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "space" => Ok(Self::Space),
+            "thin" => Ok(Self::Thin),
+            _ => bail!("unknown mixed spacing {s}."),
+        }
+    }
+}
+
+impl fmt::Display for MixedSpacing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Space => "space",
+            Self::Thin => "thin",
+        })
+    }
+}
+
+impl Recognition {
+    /// What an engine recognized in a `width` x `height` image.
+    pub fn new(outcome: OcrOutcome, width: i32, height: i32) -> Self {
+        Self {
+            lang: outcome.lang,
+            width,
+            height,
+            lines: outcome.lines,
+            angle: outcome.angle,
+            rotation: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// The recognized text, CRLF-terminated lines laid out as `options` say.
+    pub fn to_text(&self, options: &TextOptions) -> String {
+        if options.raw {
+            return self
+                .lines
+                .iter()
+                .map(|line| format!("{}\r\n", line.text))
+                .collect();
+        }
+        if options.drop_furigana {
+            let laid_out: Option<Vec<_>> = self
+                .lines
+                .iter()
+                .map(|line| Some((line.bounds()?, line.text.as_str())))
+                .collect();
+            let ruby = laid_out.map_or_else(Vec::new, |lines| furigana::furigana(&lines));
+            if ruby.contains(&true) {
+                let mut rest = self.clone();
+                let mut ruby = ruby.into_iter();
+                rest.lines.retain(|_| !ruby.next().unwrap_or_default());
+                let options = TextOptions {
+                    drop_furigana: false,
+                    ..options.clone()
+                };
+                return rest.to_text(&options);
+            }
+        }
+        let mut lines: Vec<String> = self
+            .lines
+            .iter()
+            .map(|line| match options.keep_spaces {
+                true => line.text.clone(),
+                false => join(&tokens(&line.text), options.mixed_spacing),
+            })
+            .collect();
+        if options.preserve_indentation && options.layout == Layout::Lines {
+            for (line, indent) in lines.iter_mut().zip(indent::indents(&self.lines)) {
+                line.insert_str(0, &" ".repeat(indent));
+            }
+        }
+        // without word boxes, e.g. from an old JSON export, there is no telling.
+        let boxes: Option<Vec<LineBox>> = self.lines.iter().map(Line::bounds).collect();
+        if let (Layout::Table, Some(_)) = (options.layout, &boxes) {
+            let words: Vec<&Word> = self.lines.iter().flat_map(|line| &line.words).collect();
+            return table::tsv(&words, |words| match options.keep_spaces {
+                true => words.join(" "),
+                false => {
+                    let tokens: Vec<_> =
+                        words.iter().map(|word| Token { gap: " ", word }).collect();
+                    join(&tokens, options.mixed_spacing)
+                }
+            });
+        }
+        let blocks = match &boxes {
+            Some(boxes) if options.detect_columns => columns::blocks(boxes),
+            _ => vec![(0..lines.len()).collect()],
+        };
+        let indent_lists = options.indent_lists && !options.preserve_indentation && boxes.is_some();
+        let (Layout::Paragraphs, Some(boxes)) = (options.layout, boxes) else {
+            let order: Vec<usize> = blocks.iter().flatten().copied().collect();
+            let roles = match indent_lists {
+                true => list::roles(&self.list_lines(&order)),
+                false => Vec::new(),
+            };
+            let text: String = order
+                .iter()
+                .enumerate()
+                .map(|(n, &i)| {
+                    let indent = match roles.get(n) {
+                        Some(Role::Item { indent } | Role::Continuation { indent }) => *indent,
+                        _ => 0,
+                    };
+                    format!("{}{}\r\n", " ".repeat(indent), lines[i])
+                })
+                .collect();
+            return hyphen::dehyphenate(&text, options.dehyphenate);
+        };
+
+        // each line with the break before it, paragraphs laid out within a block.
+        let mut laid_out = Vec::new();
+        for block in &blocks {
+            let Some((&first, rest)) = block.split_first() else {
+                continue;
+            };
+            let roles = list::roles(&self.list_lines(block));
+            let block_lines: Vec<_> = block
+                .iter()
+                .zip(roles)
+                .map(|(&i, role)| (boxes[i], role))
+                .collect();
+            let breaks = paragraph::breaks(&block_lines);
+            let before = (!laid_out.is_empty()).then_some(Break::Paragraph);
+            laid_out.push((first, before));
+            laid_out.extend(rest.iter().zip(breaks).map(|(&i, b)| (i, Some(b))));
+        }
+        let mut text = String::new();
+        for (i, before) in laid_out {
+            let line = &lines[i];
+            match before {
+                None => (),
+                Some(Break::Line) => text.push_str("\r\n"),
+                Some(Break::Paragraph) => text.push_str("\r\n\r\n"),
+                Some(Break::Join) => match hyphen::keeps_hyphen(&text, line, options.dehyphenate) {
+                    Some(true) => (),
+                    Some(false) => _ = text.pop(),
+                    None => {
+                        let last = text.chars().last().map_or(Class::Other, class);
+                        let first = line.chars().next().map_or(Class::Other, class);
+                        if last == Class::Other && first == Class::Other {
+                            text.push(' ');
+                        }
+                    }
+                },
+            }
+            text.push_str(line);
+        }
+        if !text.is_empty() {
+            text.push_str("\r\n");
+        }
+        text
+    }
+
+    /// The geometry of the lines at `order` that have words, for `list`.
+    fn list_lines(&self, order: &[usize]) -> Vec<ListLine<'_>> {
+        order
+            .iter()
+            .filter_map(|&i| ListLine::of(&self.lines[i]))
+            .collect()
+    }
+
+    /// The number of letters recognized, what tells a better recognition of an image
+    /// from a worse one.
+    pub fn letters(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|line| line.text.chars().filter(|c| !c.is_whitespace()).count())
+            .sum()
+    }
+
+    /// The words whose boxes overlap `region` of the image, in reading order.
+    pub fn words_in(&self, region: Region) -> impl Iterator<Item = &Word> {
+        self.lines
+            .iter()
+            .flat_map(|line| &line.words)
+            .filter(move |word| word.intersects(&region))
+    }
+
+    /// Maps word boxes found in a part of the image back onto it: scaled by `scale`, then
+    /// moved right by `left` columns and down by `top` rows.
+    pub fn map_words(&mut self, scale: f32, left: f32, top: f32) {
+        for word in self.lines.iter_mut().flat_map(|line| &mut line.words) {
+            word.x = word.x * scale + left;
+            word.y = word.y * scale + top;
+            word.width *= scale;
+            word.height *= scale;
+        }
+    }
+
+    /// Maps word boxes found in the image rotated by `degrees`, see `image::rotate`, back
+    /// onto the `width` x `height` original. Boxes keep their size, their centers move.
+    pub fn unrotate(&mut self, degrees: f64, width: i32, height: i32) {
+        let (sin, cos) = (-degrees).to_radians().sin_cos();
+        let rotated_center = (self.width as f64 / 2.0, self.height as f64 / 2.0);
+        let center = (width as f64 / 2.0, height as f64 / 2.0);
+        for word in self.lines.iter_mut().flat_map(|line| &mut line.words) {
+            let x = (word.x + word.width / 2.0) as f64 - rotated_center.0;
+            let y = (word.y + word.height / 2.0) as f64 - rotated_center.1;
+            word.x = (x * cos - y * sin + center.0) as f32 - word.width / 2.0;
+            word.y = (x * sin + y * cos + center.1) as f32 - word.height / 2.0;
+        }
+        self.width = width;
+        self.height = height;
+    }
+}
+
+/// The engine for `lang`, created once per thread.
+fn engine(lang: &Lang) -> Result<OcrEngine> {
+    thread_local! {
+        // by language tag, empty for the user profile languages.
+        static ENGINES: RefCell<HashMap<String, OcrEngine>> = RefCell::default();
+    }
+
+    let key = lang.tag()?;
+    if let Some(engine) = ENGINES.with_borrow(|engines| engines.get(&key).cloned()) {
+        return Ok(engine);
+    }
+    let engine = match lang {
+        Lang::Profile => OcrEngine::TryCreateFromUserProfileLanguages()?,
+        Lang::Tag(lang) => OcrEngine::TryCreateFromLanguage(lang)?,
+    };
+    ENGINES.with_borrow_mut(|engines| engines.insert(key, engine.clone()));
+    Ok(engine)
+}
+
+/// Cancels the recognition in progress, if any. It fails instead of returning a result.
+pub fn cancel() {
+    CANCELS.fetch_add(1, Ordering::SeqCst);
+    if let Some(operation) = in_flight().take() {
+        // too late if it has just completed.
+        operation.Cancel().ok();
+    }
+}
+
+fn in_flight() -> MutexGuard<'static, Option<IAsyncOperation<OcrResult>>> {
+    IN_FLIGHT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Waits for `completed` to be signaled, for up to `timeout`. Returns false if it timed
+/// out. Its sender gone, there's nothing left to wait for.
+fn wait(completed: &Receiver<()>, timeout: Option<Duration>) -> bool {
+    match timeout {
+        Some(timeout) => !matches!(
+            completed.recv_timeout(timeout),
+            Err(RecvTimeoutError::Timeout)
+        ),
+        None => {
+            completed.recv().ok();
+            true
+        }
+    }
+}
+
+/// Recognizes the image with the engine of Windows in `lang`, see `WinRt`. `pixels`
+/// writes the `width` x `height` BGRA pixels straight into the buffer of the bitmap
+/// handed to the engine.
+fn recognize(
+    lang: &Lang,
+    width: i32,
+    height: i32,
+    pixels: impl FnOnce(&mut [u8]),
+    timeout: Option<Duration>,
+) -> Result<OcrOutcome> {
+    let cancels = CANCELS.load(Ordering::SeqCst);
     let bmp = SoftwareBitmap::Create(BitmapPixelFormat::Bgra8, width, height)?;
     {
         let bmp_buf = bmp.LockBuffer(BitmapBufferAccessMode::Write)?;
@@ -27,91 +611,648 @@ pub fn scan(hwnd: HWND, width: i32, height: i32, bgra: Vec<u8>, buf: &mut [u8])
         let mut capacity = 0;
         unsafe { array.GetBuffer(&mut data, &mut capacity)? };
 
-        assert_eq!((width * height * 4).abs(), capacity as i32);
-
-        let slice = unsafe { slice::from_raw_parts_mut(data, capacity as usize) };
-        slice.clone_from_slice(&bgra);
-    }
-
-    //let engine = OcrEngine::TryCreateFromUserProfileLanguages()?;
+        let buffer = unsafe { slice::from_raw_parts_mut(data, capacity as usize) };
 
-    let display_name = unsafe {
-        let hctrl = GetDlgItem(hwnd, ID_COMBO)?;
-        let index =
-            SendMessageW(hctrl, CB_GETCURSEL, WPARAM::default(), LPARAM::default()).0 as usize;
-        //dbg!(index);
-        let len = SendMessageW(hctrl, CB_GETLBTEXTLEN, WPARAM(index), LPARAM::default()).0 as usize;
-        //dbg!(len);
-        let mut buf = vec![0u16; len + 1];
-        SendMessageW(
-            hctrl,
-            CB_GETLBTEXT,
-            WPARAM(index),
-            LPARAM(buf.as_mut_ptr() as isize),
+        // rows may be padded, past a start offset.
+        let plane = bmp_buf.GetPlaneDescription(0)?;
+        ensure!(
+            (plane.Width, plane.Height) == (width, height),
+            "{width}x{height} image got a {}x{} bitmap.",
+            plane.Width,
+            plane.Height
         );
-        buf
-    };
+        let start = usize::try_from(plane.StartIndex).context("invalid bitmap plane start.")?;
+        let stride = usize::try_from(plane.Stride).context("invalid bitmap stride.")?;
+        let row_len = (width.unsigned_abs() as usize)
+            .checked_mul(4)
+            .context("image is too large.")?;
+        let rows = height.unsigned_abs() as usize;
+        let dst = buffer
+            .get_mut(start..)
+            .context("bitmap plane starts past its buffer.")?;
 
-    let lang_tag = DISPLAY_NAMES
-        .get()
-        .context(c!())?
-        .get(&display_name)
-        .context(c!())?;
-
-    let lang = Language::CreateLanguage(&HSTRING::from_wide(&lang_tag[..lang_tag.len() - 1])?)?;
+        if stride == row_len {
+            let len = row_len.checked_mul(rows).context("image is too large.")?;
+            let dst = dst.get_mut(..len).with_context(|| {
+                format!("{width}x{height} image doesn't fit a {capacity} byte bitmap.")
+            })?;
+            pixels(dst);
+        } else {
+            let mut packed = vec![0; row_len * rows];
+            pixels(&mut packed);
+            copy_rows(&packed, row_len, dst, stride, row_len, rows)?;
+        }
+    }
 
-    let engine = OcrEngine::TryCreateFromLanguage(&lang)?;
-    let mut cur = Cursor::new(buf);
-    engine
-        .RecognizeAsync(&bmp)?
-        .get()?
+    let engine = engine(lang)?;
+    let used = engine.RecognizerLanguage()?.LanguageTag()?.to_string();
+    let mut lines = Vec::new();
+    let operation = engine.RecognizeAsync(&bmp)?;
+    // called once when it completes, fails or is cancelled, at once if it already has.
+    let (completed, signal) = mpsc::channel();
+    operation.SetCompleted(&AsyncOperationCompletedHandler::new(move |_, _| {
+        completed.send(()).ok();
+        Ok(())
+    }))?;
+    *in_flight() = Some(operation.clone());
+    // cancelled before it was in flight for `cancel` to find.
+    if CANCELS.load(Ordering::SeqCst) != cancels {
+        operation.Cancel().ok();
+    }
+    let finished = wait(&signal, timeout);
+    *in_flight() = None;
+    if !finished {
+        operation.Cancel().ok();
+        let seconds = timeout.unwrap_or_default().as_secs();
+        bail!("recognition timed out after {seconds} seconds.");
+    }
+    let result = operation
+        .GetResults()
+        .context("recognition failed or was cancelled.")?;
+    // null when the engine couldn't tell.
+    let angle = result.TextAngle().and_then(|angle| angle.Value()).ok();
+    result
         .Lines()?
         .First()?
         .try_for_each(|line| -> Result<()> {
-            line.Text()?
-                .as_wide()
-                // split by whitespace
-                .split(|num| num == &0x0020)
-                .try_for_each(|data| -> Result<()> {
-                    let data = unsafe {
-                        slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 2)
-                    };
-                    // if ascii text, insert a space.
-                    if data.chunks(2).all(|n| n[0] < 0x80 && n[1] == 0) {
-                        let pos = cur.position() as usize;
-                        let r = cur.get_ref();
-                        // if the previous 4 bytes are "\r\n" or 2bytes are " ", do not insert a space.
-                        if pos > 3
-                            && ((*r)[pos - 4..pos] != [0x0d, 0x00, 0x0a, 0x00]
-                                && (*r)[pos - 2..pos] != [0x20, 0x00])
-                        {
-                            cur.write_all(&[0x20, 0x00])?;
-                        }
-                        cur.write_all(data)?;
-                        cur.write_all(&[0x20, 0x00])?;
-                    } else {
-                        cur.write_all(data)?;
-                    }
-                    Ok(())
-                })?;
-            // if the last 2bytes are " ", remove it.
-            let pos = cur.position() as usize;
-            let r = cur.get_ref();
-            if pos > 2 && (*r)[pos - 2..pos] == [0x20, 0x00] {
-                cur.set_position(pos as u64 - 2);
-            }
-            // add "\r\n"
-            cur.write_all(&[0x0d, 0x00, 0x0a, 0x00])?;
+            let words = line
+                .Words()?
+                .First()?
+                .map(|word| -> Result<Word> {
+                    let rect = word.BoundingRect()?;
+                    Ok(Word {
+                        text: word.Text()?.to_string(),
+                        x: rect.X,
+                        y: rect.Y,
+                        width: rect.Width,
+                        height: rect.Height,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            lines.push(Line {
+                text: line.Text()?.to_string(),
+                words,
+            });
             Ok(())
         })?;
-    // null termination.
-    cur.write_all(&[0, 0])?;
 
-    // the last 2 bytes of buffer should be null terminatation.
-    if cur.position() as usize > BUF_SIZE - 2 {
-        cur.set_position((BUF_SIZE - 2) as u64);
-        cur.write_all(&[0, 0])?;
+    Ok(OcrOutcome {
+        lang: used,
+        lines,
+        angle,
+    })
+}
+
+/// How a line is spaced between two words, by the last character of one and the first
+/// of the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    /// Han, kana and fullwidth letters and digits, set without spaces.
+    Cjk,
+    /// Fullwidth punctuation, which carries its own spacing.
+    CjkPunctuation,
+    /// Latin, Hangul and everything else, spaced.
+    Other,
+}
+
+fn class(c: char) -> Class {
+    match c {
+        '\u{3000}'..='\u{303f}' | '\u{30fb}' => Class::CjkPunctuation,
+        '\u{ff01}'..='\u{ff0f}' | '\u{ff1a}'..='\u{ff20}' => Class::CjkPunctuation,
+        '\u{ff3b}'..='\u{ff40}' | '\u{ff5b}'..='\u{ff65}' => Class::CjkPunctuation,
+        '\u{3040}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}' => Class::Cjk,
+        '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' => Class::Cjk,
+        '\u{ff10}'..='\u{ff19}' | '\u{ff21}'..='\u{ff3a}' | '\u{ff41}'..='\u{ff5a}' => Class::Cjk,
+        _ => Class::Other,
     }
+}
+
+/// A word of a line, and the spaces before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Token<'a> {
+    gap: &'a str,
+    word: &'a str,
+}
+
+/// The words of `line`, separated by any kind of space.
+fn tokens(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    loop {
+        let start = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        let (gap, after) = rest.split_at(start);
+        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+        let (word, after) = after.split_at(end);
+        if word.is_empty() {
+            return tokens;
+        }
+        tokens.push(Token { gap, word });
+        rest = after;
+    }
+}
+
+/// Joins `tokens` the way their scripts are spaced: a space between Latin words, nothing
+/// between CJK ones unless the engine put an ideographic space there, nothing next to
+/// fullwidth punctuation, and `mixed` where the two meet.
+fn join(tokens: &[Token], mixed: MixedSpacing) -> String {
+    let mut text = String::new();
+    let mut previous: Option<char> = None;
+    for token in tokens {
+        let (Some(last), Some(first)) = (previous, token.word.chars().next()) else {
+            text.push_str(token.word);
+            previous = token.word.chars().last();
+            continue;
+        };
+        text.push_str(match (class(last), class(first)) {
+            (Class::CjkPunctuation, _) | (_, Class::CjkPunctuation) => "",
+            (Class::Cjk, Class::Cjk) if token.gap.contains('\u{3000}') => "\u{3000}",
+            (Class::Cjk, Class::Cjk) => "",
+            (Class::Other, Class::Other) => " ",
+            _ => mixed.separator(),
+        });
+        text.push_str(token.word);
+        previous = token.word.chars().last();
+    }
+    text
+}
+
+/// Copies `rows` rows of `row_len` bytes between buffers whose rows start `src_stride`
+/// and `dst_stride` bytes apart. The last row needs no padding after it.
+fn copy_rows(
+    src: &[u8],
+    src_stride: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+    row_len: usize,
+    rows: usize,
+) -> Result<()> {
+    ensure!(
+        row_len <= src_stride && row_len <= dst_stride,
+        "rows of {row_len} bytes don't fit strides of {src_stride} and {dst_stride} bytes."
+    );
+    let size = |stride: usize| match rows {
+        0 => Some(0),
+        n => (n - 1).checked_mul(stride)?.checked_add(row_len),
+    };
+    ensure!(
+        size(src_stride).is_some_and(|size| size <= src.len())
+            && size(dst_stride).is_some_and(|size| size <= dst.len()),
+        "{rows} rows don't fit buffers of {} and {} bytes.",
+        src.len(),
+        dst.len()
+    );
+    if row_len == 0 {
+        return Ok(());
+    }
+    for (s, d) in src
+        .chunks(src_stride)
+        .zip(dst.chunks_mut(dst_stride))
+        .take(rows)
+    {
+        d[..row_len].copy_from_slice(&s[..row_len]);
+    }
+    Ok(())
+}
+
+#[test]
+fn to_text_test() {
+    let recognition = |lines: &[&str]| Recognition {
+        lang: "ja".to_owned(),
+        width: 100,
+        height: 100,
+        lines: lines
+            .iter()
+            .map(|&text| Line {
+                text: text.to_owned(),
+                words: vec![],
+            })
+            .collect(),
+        angle: Some(1.5),
+        rotation: None,
+        notes: Vec::new(),
+    };
+    let text = |lines: &[&str]| recognition(lines).to_text(&TextOptions::default());
+    assert_eq!(text(&["Hello world", "again"]), "Hello world\r\nagain\r\n");
+    // Japanese words are joined, next to ASCII ones spaced.
+    assert_eq!(text(&["日本 語 です"]), "日本語です\r\n");
+    assert_eq!(text(&["OCR は 便利"]), "OCR は便利\r\n");
+    assert_eq!(text(&["便利 な OCR"]), "便利な OCR\r\n");
+    assert_eq!(text(&["", "a"]), "\r\na\r\n");
+    assert_eq!(text(&[]), "");
+
+    // or kept, as Korean needs.
+    let options = TextOptions {
+        keep_spaces: true,
+        ..Default::default()
+    };
+    let korean = recognition(&["한국어 텍스트 OCR"]).to_text(&options);
+    assert_eq!(korean, "한국어 텍스트 OCR\r\n");
+
+    // far past the 8 KB the text used to be cut at, nothing is lost.
+    let line = "The quick brown fox jumps over the lazy dog 0123456789";
+    let many: Vec<&str> = (0..1000).map(|_| line).collect();
+    let long = text(&many);
+    assert_eq!(long.len(), 1000 * (line.len() + 2));
+    assert_eq!(long.lines().filter(|&l| l == line).count(), 1000);
+}
+
+#[test]
+fn layout_test() {
+    // a word box per line, as (left, top, right, text).
+    let recognition = |lines: &[(f32, f32, f32, &str)]| Recognition {
+        lang: "en".to_owned(),
+        width: 300,
+        height: 300,
+        lines: lines
+            .iter()
+            .map(|&(left, top, right, text)| Line {
+                text: text.to_owned(),
+                words: vec![Word {
+                    text: text.to_owned(),
+                    x: left,
+                    y: top,
+                    width: right - left,
+                    height: 10.0,
+                }],
+            })
+            .collect(),
+        angle: None,
+        rotation: None,
+        notes: Vec::new(),
+    };
+    let options = |layout, dehyphenate| TextOptions {
+        layout,
+        dehyphenate,
+        ..Default::default()
+    };
+    let lines = [
+        (0.0, 0.0, 200.0, "A paragraph broken"),
+        (0.0, 14.0, 200.0, "over lines, and a hy-"),
+        (0.0, 28.0, 80.0, "phen."),
+        (0.0, 60.0, 200.0, "段落は"),
+        (0.0, 74.0, 100.0, "続く。"),
+    ];
+    let paragraphs = recognition(&lines).to_text(&options(Layout::Paragraphs, Dehyphenate::Always));
+    assert_eq!(
+        paragraphs,
+        "A paragraph broken over lines, and a hyphen.\r\n\r\n段落は続く。\r\n"
+    );
+    let kept = recognition(&lines).to_text(&options(Layout::Paragraphs, Dehyphenate::Never));
+    assert!(kept.starts_with("A paragraph broken over lines, and a hy-phen.\r\n"));
+    let per_line = recognition(&lines).to_text(&options(Layout::Lines, Dehyphenate::Never));
+    assert_eq!(per_line.lines().count(), 5);
+
+    // without word boxes, a line per line.
+    let mut bare = recognition(&lines);
+    bare.lines[0].words.clear();
+    let text = bare.to_text(&options(Layout::Paragraphs, Dehyphenate::Never));
+    assert_eq!(text, per_line);
+    assert_eq!(
+        recognition(&[]).to_text(&options(Layout::Paragraphs, Dehyphenate::Never)),
+        ""
+    );
+
+    // two columns, read one after the other.
+    let columns = recognition(&[
+        (0.0, 0.0, 140.0, "Left column,"),
+        (160.0, 0.0, 300.0, "Right column,"),
+        (0.0, 14.0, 140.0, "first line"),
+        (160.0, 14.0, 300.0, "second line"),
+        (0.0, 28.0, 60.0, "ends."),
+        (160.0, 28.0, 200.0, "too."),
+    ]);
+    let options = |layout| TextOptions {
+        layout,
+        detect_columns: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        columns.to_text(&options(Layout::Paragraphs)),
+        "Left column, first line ends.\r\n\r\nRight column, second line too.\r\n"
+    );
+    assert!(columns
+        .to_text(&options(Layout::Lines))
+        .starts_with("Left column,\r\nfirst line\r\n"));
+    assert_eq!(
+        columns.to_text(&options(Layout::Table)),
+        "Left column,\tRight column,\r\nfirst line\tsecond line\r\nends.\ttoo.\r\n"
+    );
+    // raw text is the lines as recognized, whatever the other options.
+    let raw = TextOptions {
+        raw: true,
+        ..Default::default()
+    };
+    let recognized = recognition(&[
+        (0.0, 0.0, 200.0, "日本 語 の OCR "),
+        (0.0, 14.0, 200.0, "key: AB12-CDEF-"),
+        (0.0, 28.0, 200.0, "ghij  KL78"),
+    ]);
+    assert_eq!(
+        recognized.to_text(&raw),
+        "日本 語 の OCR \r\nkey: AB12-CDEF-\r\nghij  KL78\r\n"
+    );
+    let formatted = TextOptions {
+        layout: Layout::Paragraphs,
+        dehyphenate: Dehyphenate::Always,
+        ..Default::default()
+    };
+    assert_eq!(
+        recognized.to_text(&formatted),
+        "日本語の OCR key: AB12-CDEFghij KL78\r\n"
+    );
+    assert_eq!(
+        recognized.to_text(&TextOptions {
+            raw: true,
+            ..formatted
+        }),
+        recognized.to_text(&raw)
+    );
+
+    // furigana over a line, left out.
+    let mut ruby = recognition(&[
+        (10.0, 0.0, 40.0, "かんじ"),
+        (0.0, 11.0, 200.0, "漢字"),
+        (0.0, 25.0, 200.0, "です"),
+    ]);
+    ruby.lines[0].words[0].height = 5.0;
+    ruby.lines[0].words[0].y = 5.0;
+    let options = |drop_furigana| TextOptions {
+        drop_furigana,
+        ..Default::default()
+    };
+    assert_eq!(ruby.to_text(&options(true)), "漢字\r\nです\r\n");
+    assert_eq!(ruby.to_text(&options(false)), "かんじ\r\n漢字\r\nです\r\n");
+
+    // list items and their wrapped lines.
+    let list = recognition(&[
+        (0.0, 0.0, 200.0, "•a long item"),
+        (20.0, 14.0, 200.0, "wrapped"),
+        (0.0, 28.0, 100.0, "After."),
+    ]);
+    let options = |indent_lists| TextOptions {
+        indent_lists,
+        ..Default::default()
+    };
+    assert_eq!(
+        list.to_text(&options(true)),
+        "•a long item\r\n  wrapped\r\nAfter.\r\n"
+    );
+    assert_eq!(
+        list.to_text(&options(false)),
+        "•a long item\r\nwrapped\r\nAfter.\r\n"
+    );
+    for layout in [Layout::Lines, Layout::Paragraphs, Layout::Table] {
+        assert_eq!(layout.to_string().parse::<Layout>().unwrap(), layout);
+    }
+}
+
+#[test]
+fn wait_test() {
+    use std::thread;
+    use std::time::Instant;
+
+    let (completed, signal) = mpsc::channel();
+    completed.send(()).unwrap();
+    assert!(wait(&signal, None));
+
+    let start = Instant::now();
+    let timeout = Duration::from_millis(50);
+    assert!(!wait(&signal, Some(timeout)));
+    assert!(start.elapsed() >= timeout);
+
+    // signaled from another thread, as the engine does, well before the timeout.
+    let start = Instant::now();
+    let sender = completed.clone();
+    thread::spawn(move || sender.send(()).unwrap());
+    assert!(wait(&signal, Some(Duration::from_secs(10))));
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    drop(completed);
+    assert!(wait(&signal, Some(timeout)));
+    assert!(wait(&signal, None));
+}
+
+#[test]
+fn join_words_test() {
+    for (line, expected) in [
+        ("Hello world", "Hello world"),
+        ("日本 語 です", "日本語です"),
+        ("OCR は 便利", "OCR は便利"),
+        ("便利 な OCR", "便利な OCR"),
+        // an ideographic space between Japanese words stays.
+        ("日本語\u{3000}テキスト", "日本語\u{3000}テキスト"),
+        ("東京\u{3000}\u{3000}大阪", "東京\u{3000}大阪"),
+        // next to an ASCII word, it's a plain space like any other.
+        ("OCR\u{3000}テキスト", "OCR テキスト"),
+        ("テキスト\u{3000}OCR\u{3000}です", "テキスト OCR です"),
+        ("100\u{a0}km", "100 km"),
+        ("価格\u{a0}円", "価格円"),
+        // runs of spaces count once, leading and trailing ones not at all.
+        ("  a   b  ", "a b"),
+        ("\u{3000}日本\u{3000}", "日本"),
+        ("", ""),
+    ] {
+        assert_eq!(
+            join(&tokens(line), MixedSpacing::Space),
+            expected,
+            "{line:?}"
+        );
+    }
+}
+
+#[test]
+fn spacing_test() {
+    let join = |line: &str, mixed| join(&tokens(line), mixed);
+    // by the characters meeting, whatever the rest of the words.
+    for (line, expected) in [
+        // Latin and Latin.
+        ("The quick fox", "The quick fox"),
+        ("co operate", "co operate"),
+        ("Hello, world!", "Hello, world!"),
+        ("(see page 3)", "(see page 3)"),
+        // CJK and CJK.
+        ("これは テスト です", "これはテストです"),
+        ("漢字 かな カナ", "漢字かなカナ"),
+        ("ＡＢＣ 全角 １２３", "ＡＢＣ全角１２３"),
+        // next to fullwidth punctuation.
+        ("はい 、 そう です 。", "はい、そうです。"),
+        ("「 引用 」 と", "「引用」と"),
+        ("test 。", "test。"),
+        ("（ OCR ）", "（OCR）"),
+        ("東京 ・ 大阪", "東京・大阪"),
+        // Hangul is spaced like Latin.
+        ("한국어 텍스트", "한국어 텍스트"),
+        ("한국어 OCR", "한국어 OCR"),
+        // CJK and Latin, as set.
+        ("これは test です", "これは test です"),
+        ("OCR 結果", "OCR 結果"),
+        ("価格 100 円", "価格 100 円"),
+    ] {
+        assert_eq!(join(line, MixedSpacing::Space), expected, "{line:?}");
+    }
+
+    assert_eq!(
+        join("これは test です", MixedSpacing::None),
+        "これはtestです"
+    );
+    assert_eq!(
+        join("これは test です", MixedSpacing::Thin),
+        "これは\u{2009}test\u{2009}です"
+    );
+    // the setting only applies where the scripts meet.
+    assert_eq!(
+        join("ＯＣＲ の 結果 、 test case", MixedSpacing::None),
+        "ＯＣＲの結果、test case"
+    );
+
+    for mixed in [MixedSpacing::None, MixedSpacing::Space, MixedSpacing::Thin] {
+        assert_eq!(mixed.to_string().parse::<MixedSpacing>().unwrap(), mixed);
+    }
+    assert!("wide".parse::<MixedSpacing>().is_err());
+}
+
+#[test]
+fn map_words_test() {
+    let word = |x: f32, y: f32| Word {
+        text: "word".to_owned(),
+        x,
+        y,
+        width: 10.0,
+        height: 4.0,
+    };
+    // found in a padded copy reduced to half: the margin comes off first, then the scale.
+    let mut recognition = Recognition {
+        lang: "en".to_owned(),
+        width: 66,
+        height: 46,
+        lines: vec![Line {
+            text: "word word".to_owned(),
+            words: vec![word(16.0, 16.0), word(40.0, 30.0)],
+        }],
+        angle: None,
+        rotation: None,
+        notes: Vec::new(),
+    };
+    recognition.map_words(1.0, -16.0, -16.0);
+    recognition.map_words(2.0, 0.0, 0.0);
+    let boxes: Vec<_> = recognition.lines[0]
+        .words
+        .iter()
+        .map(|word| (word.x, word.y, word.width, word.height))
+        .collect();
+    assert_eq!(boxes, [(0.0, 0.0, 20.0, 8.0), (48.0, 28.0, 20.0, 8.0)]);
+
+    // a band starting 800 rows down.
+    recognition.map_words(1.0, 0.0, 800.0);
+    assert_eq!(recognition.lines[0].words[1].y, 828.0);
+}
+
+#[test]
+fn words_in_test() {
+    let line = |y: f32, words: &[(&str, f32)]| Line {
+        text: String::new(),
+        words: words
+            .iter()
+            .map(|&(text, x)| Word {
+                text: text.to_owned(),
+                x,
+                y,
+                width: 20.0,
+                height: 10.0,
+            })
+            .collect(),
+    };
+    let recognition = Recognition {
+        lang: "en".to_owned(),
+        width: 100,
+        height: 100,
+        lines: vec![
+            line(0.0, &[("top", 0.0), ("right", 60.0)]),
+            line(50.0, &[("bottom", 0.0), ("corner", 60.0)]),
+        ],
+        angle: None,
+        rotation: None,
+        notes: Vec::new(),
+    };
+    let words_in = |x, y, width, height| -> Vec<&str> {
+        let region = Region {
+            x,
+            y,
+            width,
+            height,
+        };
+        recognition
+            .words_in(region)
+            .map(|word| word.text.as_str())
+            .collect()
+    };
+    assert_eq!(
+        words_in(0, 0, 100, 100),
+        ["top", "right", "bottom", "corner"]
+    );
+    assert_eq!(words_in(50, 0, 50, 100), ["right", "corner"]);
+    // touching a word's edge isn't overlapping it.
+    assert_eq!(words_in(20, 10, 40, 40), Vec::<&str>::new());
+    assert_eq!(words_in(19, 9, 2, 2), ["top"]);
+    assert_eq!(words_in(0, 0, 0, 100), Vec::<&str>::new());
+}
+
+#[test]
+fn unrotate_test() {
+    let word = |x: f32, y: f32| Word {
+        text: "word".to_owned(),
+        x,
+        y,
+        width: 2.0,
+        height: 2.0,
+    };
+    // a 4x2 image turned a quarter clockwise is 2x4, its top left corner now top right.
+    let mut recognition = Recognition {
+        lang: "en".to_owned(),
+        width: 2,
+        height: 4,
+        lines: vec![Line {
+            text: "word".to_owned(),
+            words: vec![word(0.0, 0.0), word(0.0, 1.0)],
+        }],
+        angle: None,
+        rotation: Some(90.0),
+        notes: Vec::new(),
+    };
+    recognition.unrotate(90.0, 4, 2);
+    assert_eq!((recognition.width, recognition.height), (4, 2));
+    let boxes: Vec<_> = recognition.lines[0]
+        .words
+        .iter()
+        .map(|word| (word.x.round(), word.y.round()))
+        .collect();
+    assert_eq!(boxes, [(0.0, 0.0), (1.0, 0.0)]);
+}
+
+#[test]
+fn copy_rows_test() {
+    // 2 rows of 3 bytes, tightly packed into rows of 5 with padding.
+    let src = [1, 2, 3, 4, 5, 6];
+    let mut dst = [0xee; 9];
+    copy_rows(&src, 3, &mut dst, 5, 3, 2).unwrap();
+    assert_eq!(dst, [1, 2, 3, 0xee, 0xee, 4, 5, 6, 0xee]);
+
+    // and back, dropping the padding.
+    let mut packed = [0; 6];
+    copy_rows(&dst, 5, &mut packed, 3, 3, 2).unwrap();
+    assert_eq!(packed, src);
+
+    // equal strides copy everything.
+    let mut same = [0; 6];
+    copy_rows(&src, 3, &mut same, 3, 3, 2).unwrap();
+    assert_eq!(same, src);
 
-    Ok(cur.position() as usize)
+    // too small, or rows wider than the stride.
+    assert!(copy_rows(&src, 3, &mut [0; 7], 5, 3, 2).is_err());
+    assert!(copy_rows(&src[..5], 3, &mut dst, 5, 3, 2).is_err());
+    assert!(copy_rows(&src, 3, &mut dst, 2, 3, 2).is_err());
+    assert!(copy_rows(&src, 3, &mut dst, 5, 3, usize::MAX).is_err());
+    assert!(copy_rows(&[], 0, &mut [], 0, 0, 3).is_ok());
 }
@@ -0,0 +1,137 @@
+use super::settings::Settings;
+
+/// A recognition result as shown in the text view and as put on the clipboard.
+/// Options only ever decorate the clipboard copy.
+#[derive(Debug)]
+pub struct Output {
+    pub view: String,
+    pub clipboard: String,
+}
+
+impl Output {
+    /// `lang` is the tag of the language the engine actually used for `text`.
+    pub fn new(text: String, lang: &str, settings: &Settings) -> Self {
+        let mut clipboard = text.clone();
+        if settings.stamp_language {
+            // a line of its own, after text cut short or without a final line break.
+            if !clipboard.is_empty() && !clipboard.ends_with('\n') {
+                clipboard.push_str("\r\n");
+            }
+            clipboard.push_str(&format!("[lang:{lang}]"));
+        }
+        Self {
+            view: text,
+            clipboard,
+        }
+    }
+}
+
+/// Cuts `text` to its first `max_chars` characters, 0 leaving it whole. Returns whether
+/// anything was cut.
+pub fn truncate(text: &mut String, max_chars: usize) -> bool {
+    if max_chars == 0 {
+        return false;
+    }
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => {
+            text.truncate(end);
+            true
+        }
+        None => false,
+    }
+}
+
+/// `text` with the whitespace ending each line trimmed if `trim`, and runs of blank lines
+/// collapsed to one, leaving none at the start or end, if `collapse`. Tabs stay, an
+/// empty field of a table row may end it. Lines come out CRLF-terminated, the last one
+/// only if it was.
+pub fn tidy(text: &str, trim: bool, collapse: bool) -> String {
+    let mut tidied = String::with_capacity(text.len());
+    // where the last line starts, if it's blank.
+    let mut blank_start = None;
+    for line in text.lines() {
+        let line = if trim {
+            line.trim_end_matches(|c: char| c.is_whitespace() && c != '\t')
+        } else {
+            line
+        };
+        let blank = line.trim().is_empty();
+        if collapse && blank && (blank_start.is_some() || tidied.is_empty()) {
+            continue;
+        }
+        blank_start = blank.then_some(tidied.len());
+        tidied.push_str(line);
+        tidied.push_str("\r\n");
+    }
+    if let (true, Some(start)) = (collapse, blank_start) {
+        tidied.truncate(start);
+    } else if !text.ends_with('\n') {
+        tidied.truncate(tidied.len().saturating_sub(2));
+    }
+    tidied
+}
+
+#[test]
+fn truncate_test() {
+    let mut text = "日本語 text".to_owned();
+    assert!(!truncate(&mut text, 0));
+    assert!(!truncate(&mut text, 8));
+    assert_eq!(text, "日本語 text");
+    assert!(truncate(&mut text, 2));
+    assert_eq!(text, "日本");
+
+    let mut long = "x".repeat(10000);
+    assert!(truncate(&mut long, 9000));
+    assert_eq!(long.len(), 9000);
+}
+
+#[test]
+fn output_test() {
+    let text = "Hello world\r\n";
+
+    let output = Output::new(text.to_owned(), "en-US", &Settings::default());
+    assert_eq!(output.view, text);
+    assert_eq!(output.clipboard, text);
+
+    let settings = Settings {
+        stamp_language: true,
+        ..Default::default()
+    };
+    let output = Output::new(text.to_owned(), "ja-JP", &settings);
+    assert_eq!(output.view, text);
+    assert_eq!(output.clipboard, "Hello world\r\n[lang:ja-JP]");
+
+    let output = Output::new("Hello".to_owned(), "en-US", &settings);
+    assert_eq!(output.view, "Hello");
+    assert_eq!(output.clipboard, "Hello\r\n[lang:en-US]");
+}
+
+#[test]
+fn tidy_test() {
+    use super::clipboard::{normalize_line_endings, LineEnding};
+    let text = "\r\nfirst  \r\n\u{3000}\r\n \r\n\r\nsecond\t\r\nthird \r\n\r\n";
+    for (trim, collapse, expected) in [
+        (
+            false,
+            false,
+            "\r\nfirst  \r\n\u{3000}\r\n \r\n\r\nsecond\t\r\nthird \r\n\r\n",
+        ),
+        (
+            true,
+            false,
+            "\r\nfirst\r\n\r\n\r\n\r\nsecond\t\r\nthird\r\n\r\n",
+        ),
+        (false, true, "first  \r\n\u{3000}\r\nsecond\t\r\nthird \r\n"),
+        (true, true, "first\r\n\r\nsecond\t\r\nthird\r\n"),
+    ] {
+        let tidied = tidy(text, trim, collapse);
+        assert_eq!(tidied, expected, "trim: {trim}, collapse: {collapse}");
+        // the line endings are chosen when copied, whatever the other options.
+        let lf = normalize_line_endings(&tidied, LineEnding::Lf);
+        assert_eq!(lf, expected.replace("\r\n", "\n"));
+        assert_eq!(normalize_line_endings(&lf, LineEnding::CrLf), expected);
+    }
+    assert_eq!(tidy("", true, true), "");
+    assert_eq!(tidy("\r\n \r\n", true, true), "");
+    assert_eq!(tidy("no break ", true, true), "no break");
+}
@@ -0,0 +1,118 @@
+//! Images past `OcrEngine::MaxImageDimension` on either side are refused by the engine.
+//! They are scaled down to fit, cut into overlapping horizontal bands recognized one at a
+//! time, or rejected, as the settings say.
+
+use anyhow::{bail, Error};
+use std::fmt;
+use std::str::FromStr;
+
+// rows shared by neighboring bands, so a line cut by one band is whole in the other.
+pub const BAND_OVERLAP: i32 = 200;
+
+/// What to do with an image too large for the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Oversize {
+    Downscale,
+    Tile,
+    Reject,
+}
+
+impl FromStr for Oversize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "downscale" => Ok(Self::Downscale),
+            "tile" => Ok(Self::Tile),
+            "reject" => Ok(Self::Reject),
+            _ => bail!("unknown oversize mode {s}."),
+        }
+    }
+}
+
+impl fmt::Display for Oversize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Downscale => "downscale",
+            Self::Tile => "tile",
+            Self::Reject => "reject",
+        })
+    }
+}
+
+/// The scale that fits both sides within `max`, 1 if they already do.
+pub fn fit_scale(width: i32, height: i32, max: u32) -> f32 {
+    let longest = width.max(height).max(1) as f32;
+    (max as f32 / longest).min(1.0)
+}
+
+/// Splits `height` rows into bands of at most `max` rows, each overlapping the previous
+/// one by `overlap` rows, as `(top, rows)`.
+pub fn bands(height: i32, max: i32, overlap: i32) -> Vec<(i32, i32)> {
+    let max = max.max(1);
+    // every band has to make progress.
+    let overlap = overlap.clamp(0, max - 1);
+    let mut bands = Vec::new();
+    let mut top = 0;
+    loop {
+        let rows = max.min(height - top);
+        bands.push((top, rows));
+        if top + rows >= height {
+            return bands;
+        }
+        top += max - overlap;
+    }
+}
+
+/// The number of leading lines of `next` that repeat the trailing lines of `previous`,
+/// the text of the overlap between two bands. Lines are compared trimmed.
+pub fn overlap(previous: &[&str], next: &[&str]) -> usize {
+    (1..=previous.len().min(next.len()))
+        .rev()
+        .find(|&n| {
+            previous[previous.len() - n..]
+                .iter()
+                .zip(&next[..n])
+                .all(|(a, b)| a.trim() == b.trim())
+        })
+        .unwrap_or(0)
+}
+
+#[test]
+fn oversize_test() {
+    for mode in [Oversize::Downscale, Oversize::Tile, Oversize::Reject] {
+        assert_eq!(mode.to_string().parse::<Oversize>().unwrap(), mode);
+    }
+    assert!("shrink".parse::<Oversize>().is_err());
+}
+
+#[test]
+fn fit_scale_test() {
+    assert_eq!(fit_scale(100, 50, 10000), 1.0);
+    assert_eq!(fit_scale(10000, 50, 10000), 1.0);
+    assert_eq!(fit_scale(20000, 5000, 10000), 0.5);
+    assert_eq!(fit_scale(5000, 16000, 10000), 0.625);
+}
+
+#[test]
+fn bands_test() {
+    assert_eq!(bands(100, 1000, 200), [(0, 100)]);
+    assert_eq!(bands(1000, 1000, 200), [(0, 1000)]);
+    assert_eq!(bands(1500, 1000, 200), [(0, 1000), (800, 700)]);
+    assert_eq!(
+        bands(2600, 1000, 200),
+        [(0, 1000), (800, 1000), (1600, 1000)]
+    );
+    // the overlap can't swallow a whole band.
+    assert_eq!(bands(3, 1, 200), [(0, 1), (1, 1), (2, 1)]);
+}
+
+#[test]
+fn overlap_test() {
+    assert_eq!(overlap(&["a", "b", "c"], &["b", "c", "d"]), 2);
+    assert_eq!(overlap(&["a", "b", "c"], &["c ", "d"]), 1);
+    assert_eq!(overlap(&["a", "b"], &["c", "d"]), 0);
+    assert_eq!(overlap(&[], &["a"]), 0);
+    // the longest match wins.
+    assert_eq!(overlap(&["x", "x"], &["x", "x", "y"]), 2);
+}
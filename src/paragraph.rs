@@ -0,0 +1,236 @@
+//! Paragraphs from the geometry of recognized lines, for the `paragraphs` layout. Lines
+//! of a paragraph are joined; a gap well above the usual one, or an indented first line,
+//! starts a new paragraph; list items and lines ending short keep their line breaks.
+
+use super::list::Role;
+
+// a gap this many line heights above the usual one separates paragraphs.
+const PARAGRAPH_GAP: f32 = 0.6;
+// a line starting this many line heights right of the one before is a first line.
+const INDENT: f32 = 1.0;
+// a line reaching less than this share of the text width ends short.
+const FULL_LINE: f32 = 0.8;
+
+/// The box around the words of a line, in image pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineBox {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+/// The runs of open space between `spans`, each `(left, right)`, left to right: the
+/// strips between their edges crossed by at most `max_crossed` of them, merged where
+/// they touch. Gutters between columns, of text or of a table, are among them.
+pub fn open_runs(spans: &[(f32, f32)], max_crossed: usize) -> Vec<(f32, f32)> {
+    let mut edges: Vec<f32> = spans
+        .iter()
+        .flat_map(|&(left, right)| [left, right])
+        .collect();
+    edges.sort_by(f32::total_cmp);
+    edges.dedup();
+    let mut runs: Vec<(f32, f32)> = Vec::new();
+    for strip in edges.windows(2) {
+        let middle = (strip[0] + strip[1]) / 2.0;
+        let crossed = spans
+            .iter()
+            .filter(|&&(left, right)| left < middle && middle < right)
+            .count();
+        if crossed > max_crossed {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.1 == strip[0] => run.1 = strip[1],
+            _ => runs.push((strip[0], strip[1])),
+        }
+    }
+    runs
+}
+
+/// What goes between a line and the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Break {
+    /// The same paragraph, joined.
+    Join,
+    /// A line break.
+    Line,
+    /// A blank line.
+    Paragraph,
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f32::total_cmp);
+    values[values.len() / 2]
+}
+
+/// The break after each of `lines` but the last, each line its box and role in a list.
+pub fn breaks(lines: &[(LineBox, Role)]) -> Vec<Break> {
+    let height = median(lines.iter().map(|(b, _)| b.bottom - b.top).collect());
+    let gap = median(
+        lines
+            .windows(2)
+            .map(|w| w[1].0.top - w[0].0.bottom)
+            .collect(),
+    );
+    let left = lines
+        .iter()
+        .map(|(b, _)| b.left)
+        .fold(f32::INFINITY, f32::min);
+    let right = lines
+        .iter()
+        .map(|(b, _)| b.right)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let full = left + (right - left) * FULL_LINE;
+
+    lines
+        .windows(2)
+        .map(|pair| {
+            let ((line, role), (next, next_role)) = (pair[0], pair[1]);
+            // further up is another column.
+            if next.top - line.bottom > gap + height * PARAGRAPH_GAP || next.top < line.top {
+                return Break::Paragraph;
+            }
+            match (role, next_role) {
+                // an item never joins the line before.
+                (_, Role::Item { .. }) => Break::Line,
+                // a wrapped line continues its item, however short the line before.
+                (_, Role::Continuation { .. }) => Break::Join,
+                (Role::Item { .. } | Role::Continuation { .. }, Role::Text) => Break::Line,
+                (Role::Text, Role::Text) if next.left > line.left + height * INDENT => {
+                    Break::Paragraph
+                }
+                _ if line.right < full => Break::Line,
+                _ => Break::Join,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn layout(lines: &[(f32, f32, f32, &'static str)]) -> Vec<(LineBox, Role)> {
+    use super::list::{self, ListLine};
+    // lines 10 high with characters 10 wide, by left, top, right and text.
+    let list_lines: Vec<_> = lines
+        .iter()
+        .map(|&(left, _, _, text)| ListLine {
+            left,
+            text_left: left
+                + list::marker(text).map_or(0.0, |m| (m.chars().count() + 1) as f32 * 10.0),
+            height: 10.0,
+            text,
+        })
+        .collect();
+    lines
+        .iter()
+        .zip(list::roles(&list_lines))
+        .map(|(&(left, top, right, _), role)| {
+            let line = LineBox {
+                left,
+                top,
+                right,
+                bottom: top + 10.0,
+            };
+            (line, role)
+        })
+        .collect()
+}
+
+#[test]
+fn single_paragraph_test() {
+    let lines = layout(&[
+        (0.0, 0.0, 200.0, "Lorem ipsum dolor sit amet,"),
+        (0.0, 14.0, 195.0, "consectetur adipiscing elit, sed"),
+        (0.0, 28.0, 198.0, "do eiusmod tempor incididunt ut"),
+        (0.0, 42.0, 80.0, "labore."),
+    ]);
+    assert_eq!(breaks(&lines), [Break::Join; 3]);
+    assert_eq!(breaks(&lines[..1]), []);
+    assert_eq!(breaks(&[]), []);
+}
+
+#[test]
+fn paragraphs_test() {
+    // separated by a wider gap.
+    let lines = layout(&[
+        (0.0, 0.0, 200.0, "First paragraph"),
+        (0.0, 14.0, 120.0, "ends here."),
+        (0.0, 40.0, 200.0, "Second paragraph"),
+        (0.0, 54.0, 200.0, "goes on and"),
+        (0.0, 68.0, 90.0, "on."),
+    ]);
+    use Break::*;
+    assert_eq!(breaks(&lines), [Join, Paragraph, Join, Join]);
+
+    // or by an indented first line, with no gap.
+    let lines = layout(&[
+        (20.0, 0.0, 200.0, "First paragraph"),
+        (0.0, 14.0, 200.0, "ends here, at"),
+        (0.0, 28.0, 190.0, "the right edge."),
+        (20.0, 42.0, 200.0, "Second paragraph"),
+        (0.0, 56.0, 60.0, "ends."),
+    ]);
+    assert_eq!(breaks(&lines), [Join, Join, Paragraph, Join]);
+
+    // a second column starts further up.
+    let lines = layout(&[
+        (0.0, 14.0, 100.0, "left column"),
+        (120.0, 0.0, 220.0, "right column"),
+    ]);
+    assert_eq!(breaks(&lines), [Paragraph]);
+}
+
+#[test]
+fn list_test() {
+    use Break::*;
+    let lines = layout(&[
+        (0.0, 0.0, 200.0, "Things to bring along on the trip:"),
+        (0.0, 14.0, 200.0, "• a map of the area and a compass"),
+        (20.0, 28.0, 200.0, "or a phone"),
+        (0.0, 42.0, 120.0, "• water"),
+        (0.0, 56.0, 200.0, "1. a tent"),
+        (0.0, 70.0, 200.0, "a) poles"),
+    ]);
+    assert_eq!(breaks(&lines), [Line, Join, Line, Line, Line]);
+
+    // short lines without markers keep their breaks too.
+    let lines = layout(&[
+        (0.0, 0.0, 200.0, "The form was filled in as"),
+        (0.0, 14.0, 120.0, "follows:"),
+        (0.0, 28.0, 80.0, "Name: Alice"),
+        (0.0, 42.0, 60.0, "Age: 30"),
+        (0.0, 56.0, 90.0, "City: Paris"),
+    ]);
+    assert_eq!(breaks(&lines), [Join, Line, Line, Line]);
+
+    // a nested list, and the text after it joins none of its items.
+    let lines = layout(&[
+        (0.0, 0.0, 200.0, "1. The first step, which is"),
+        (30.0, 14.0, 120.0, "long"),
+        (30.0, 28.0, 200.0, "a) a detail that goes on"),
+        (60.0, 42.0, 200.0, "over lines"),
+        (0.0, 56.0, 200.0, "The text after the list runs"),
+        (0.0, 70.0, 100.0, "on."),
+    ]);
+    assert_eq!(breaks(&lines), [Join, Line, Join, Line, Join]);
+}
+
+#[test]
+fn open_runs_test() {
+    // two columns, and a heading across them.
+    let spans = [
+        (0.0, 40.0),
+        (0.0, 45.0),
+        (60.0, 100.0),
+        (62.0, 100.0),
+        (0.0, 100.0),
+    ];
+    assert_eq!(open_runs(&spans, 0), []);
+    assert_eq!(open_runs(&spans, 1), [(45.0, 60.0)]);
+    // strips crossed by nothing run together.
+    assert_eq!(open_runs(&[(0.0, 10.0), (20.0, 30.0)], 0), [(10.0, 20.0)]);
+    assert_eq!(open_runs(&[], 0), []);
+}
@@ -0,0 +1,212 @@
+//! The order of the preprocessing steps, e.g. `adjust,normalize,binarize` in the
+//! settings file. A step runs when it's listed and the settings enable it.
+
+use super::preprocess::{self, Lut};
+use super::settings::Settings;
+use anyhow::{bail, Error};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// A preprocessing step over a BGRA buffer, keeping its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Gamma and brightness.
+    Adjust,
+    Normalize,
+    Denoise,
+    Sharpen,
+    Invert,
+    Binarize,
+}
+
+impl Step {
+    /// Runs the step on a `width` x `height` image if the settings enable it, returning
+    /// whether it ran.
+    fn apply(self, bgra: &mut [u8], width: i32, height: i32, settings: &Settings) -> bool {
+        match self {
+            Self::Adjust => {
+                let lut = Lut::new(settings.gamma, settings.brightness);
+                if lut.is_identity() {
+                    return false;
+                }
+                lut.apply(bgra);
+            }
+            Self::Normalize if settings.normalize => {
+                preprocess::normalize(bgra, settings.normalize_clip);
+            }
+            Self::Denoise | Self::Sharpen
+                if !preprocess::is_cleaned_up(width, height, settings.force_cleanup) =>
+            {
+                return false;
+            }
+            Self::Denoise if settings.denoise => preprocess::denoise(bgra, width, height),
+            Self::Sharpen if settings.sharpen => preprocess::sharpen(bgra, width, height),
+            Self::Invert if preprocess::should_invert(settings.invert, bgra) => {
+                preprocess::invert(bgra);
+            }
+            Self::Binarize if settings.binarize => preprocess::binarize(bgra, width, height),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl FromStr for Step {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adjust" => Ok(Self::Adjust),
+            "normalize" => Ok(Self::Normalize),
+            "denoise" => Ok(Self::Denoise),
+            "sharpen" => Ok(Self::Sharpen),
+            "invert" => Ok(Self::Invert),
+            "binarize" => Ok(Self::Binarize),
+            _ => bail!("unknown preprocessing step {s}."),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Adjust => "adjust",
+            Self::Normalize => "normalize",
+            Self::Denoise => "denoise",
+            Self::Sharpen => "sharpen",
+            Self::Invert => "invert",
+            Self::Binarize => "binarize",
+        })
+    }
+}
+
+/// Preprocessing steps in the order they run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline(Vec<Step>);
+
+impl Default for Pipeline {
+    /// Every step: gamma and brightness first, the others expect their result, and
+    /// binarization last, the others have nothing left to work with after it.
+    fn default() -> Self {
+        Self(vec![
+            Step::Adjust,
+            Step::Normalize,
+            Step::Denoise,
+            Step::Sharpen,
+            Step::Invert,
+            Step::Binarize,
+        ])
+    }
+}
+
+impl Pipeline {
+    /// Runs the steps on a `width` x `height` BGRA image, returning how long each of the
+    /// ones that ran took.
+    pub fn run(
+        &self,
+        bgra: &mut [u8],
+        width: i32,
+        height: i32,
+        settings: &Settings,
+    ) -> Vec<(Step, Duration)> {
+        self.0
+            .iter()
+            .filter_map(|&step| {
+                let start = Instant::now();
+                step.apply(bgra, width, height, settings)
+                    .then(|| (step, start.elapsed()))
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let steps = s
+            .split(',')
+            .map(str::trim)
+            .filter(|step| !step.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Self(steps))
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let steps: Vec<String> = self.0.iter().map(Step::to_string).collect();
+        f.write_str(&steps.join(","))
+    }
+}
+
+#[test]
+fn pipeline_test() {
+    let pipeline = Pipeline::default();
+    assert_eq!(
+        pipeline.to_string(),
+        "adjust,normalize,denoise,sharpen,invert,binarize"
+    );
+    assert_eq!(pipeline.to_string().parse::<Pipeline>().unwrap(), pipeline);
+    assert_eq!(
+        " binarize, adjust ,".parse::<Pipeline>().unwrap(),
+        Pipeline(vec![Step::Binarize, Step::Adjust])
+    );
+    assert_eq!("".parse::<Pipeline>().unwrap(), Pipeline(vec![]));
+    assert!("adjust,upscale".parse::<Pipeline>().is_err());
+}
+
+#[test]
+fn run_order_test() {
+    let settings = Settings {
+        brightness: 100,
+        binarize: true,
+        invert: preprocess::Invert::Never,
+        ..Default::default()
+    };
+    let gray = |v: u8| [v, v, v, 255];
+    let src = [0, 0, 200, 200].map(gray).concat();
+    let steps = |pipeline: &str| -> (Vec<u8>, Vec<Step>) {
+        let mut bgra = src.clone();
+        let timings = pipeline
+            .parse::<Pipeline>()
+            .unwrap()
+            .run(&mut bgra, 4, 1, &settings);
+        (bgra, timings.into_iter().map(|(step, _)| step).collect())
+    };
+
+    // brightened, then black and white.
+    let (bgra, ran) = steps("adjust,binarize");
+    assert_eq!(bgra, [0, 0, 255, 255].map(gray).concat());
+    assert_eq!(ran, [Step::Adjust, Step::Binarize]);
+    // black and white, then brightened: the black is gray.
+    let (bgra, ran) = steps("binarize,adjust");
+    assert_eq!(bgra, [100, 100, 255, 255].map(gray).concat());
+    assert_eq!(ran, [Step::Binarize, Step::Adjust]);
+
+    // disabled steps don't run, nor count.
+    let (_, ran) = steps("denoise,invert,adjust");
+    assert_eq!(ran, [Step::Adjust]);
+}
+
+#[test]
+fn empty_pipeline_test() {
+    // everything enabled, nothing listed.
+    let settings = Settings {
+        gamma: 2.0,
+        brightness: 50,
+        normalize: true,
+        denoise: true,
+        sharpen: true,
+        invert: preprocess::Invert::Always,
+        binarize: true,
+        ..Default::default()
+    };
+    let src: Vec<u8> = (0..64).map(|v| v * 4).collect();
+    let mut bgra = src.clone();
+    let timings = Pipeline(vec![]).run(&mut bgra, 4, 4, &settings);
+    assert!(timings.is_empty());
+    assert_eq!(bgra, src);
+}
@@ -0,0 +1,551 @@
+use super::pipeline::Step;
+use super::settings::Settings;
+use anyhow::{bail, Error};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+// below this median luminance the background is taken to be dark.
+const DARK_MEDIAN: usize = 96;
+// pixels looked at to find the median, evenly spread over the image.
+const DARK_SAMPLES: usize = 65536;
+// larger images skip the cleanup filters unless forced, they'd take too long.
+const FILTER_MAX_PIXELS: usize = 4_000_000;
+// how much of the difference from the blurred image the sharpening adds.
+const SHARPEN_AMOUNT: f32 = 1.0;
+
+/// Whether to invert the image before recognition, for light text on a dark background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invert {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for Invert {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => bail!("unknown invert mode {s}."),
+        }
+    }
+}
+
+impl fmt::Display for Invert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Always => "always",
+            Self::Never => "never",
+        })
+    }
+}
+
+/// A per-channel lookup table mapping 8-bit values through gamma and brightness.
+pub struct Lut([u8; 256]);
+
+impl Lut {
+    /// A `gamma` above 1 lifts the mid-tones; `brightness` is added afterwards, in 0-255 units.
+    pub fn new(gamma: f32, brightness: i32) -> Self {
+        let gamma = if gamma > 0.0 { gamma } else { 1.0 };
+        let mut table = [0; 256];
+        table
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = adjust(i as u8, gamma, brightness));
+        Self(table)
+    }
+
+    /// Stretches the levels `low..=high` over the full range, clamping the others.
+    pub fn stretch(low: u8, high: u8) -> Self {
+        let range = (high.saturating_sub(low) as f32).max(1.0);
+        let mut table = [0; 256];
+        table.iter_mut().enumerate().for_each(|(i, v)| {
+            let stretched = (i as f32 - low as f32) * 255.0 / range;
+            *v = stretched.round().clamp(0.0, 255.0) as u8;
+        });
+        Self(table)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.0.iter().enumerate().all(|(i, &v)| i == v as usize)
+    }
+
+    /// Maps the color channels of a BGRA buffer, leaving alpha alone.
+    pub fn apply(&self, bgra: &mut [u8]) {
+        bgra.chunks_exact_mut(4).for_each(|p| {
+            p[0] = self.0[p[0] as usize];
+            p[1] = self.0[p[1] as usize];
+            p[2] = self.0[p[2] as usize];
+        });
+    }
+}
+
+fn adjust(value: u8, gamma: f32, brightness: i32) -> u8 {
+    let v = 255.0 * (value as f32 / 255.0).powf(1.0 / gamma);
+    (v.round() as i32 + brightness).clamp(0, 255) as u8
+}
+
+/// Rec. 601 luma of a BGRA pixel.
+fn luminance(p: &[u8]) -> u8 {
+    let (b, g, r) = (p[0] as u32, p[1] as u32, p[2] as u32);
+    ((r * 299 + g * 587 + b * 114 + 500) / 1000) as u8
+}
+
+/// The number of pixels of a BGRA buffer at each luminance level.
+fn histogram(bgra: &[u8]) -> [u32; 256] {
+    let mut histogram = [0; 256];
+    bgra.chunks_exact(4)
+        .for_each(|p| histogram[luminance(p) as usize] += 1);
+    histogram
+}
+
+/// The luminance levels with `clip` percent of the pixels below and above them, the
+/// range to stretch over 0-255. None if the pixels are all of a level, nothing to stretch.
+fn stretch_range(histogram: &[u32; 256], clip: f32) -> Option<(u8, u8)> {
+    let total: u64 = histogram.iter().map(|&n| n as u64).sum();
+    let clipped = (total as f64 * clip.clamp(0.0, 49.0) as f64 / 100.0) as u64;
+    // the first level past the clipped pixels, counting from either end.
+    fn past<'a>(mut levels: impl Iterator<Item = (usize, &'a u32)>, clipped: u64) -> Option<u8> {
+        let mut seen = 0;
+        levels
+            .find(|&(_, &n)| {
+                seen += n as u64;
+                seen > clipped
+            })
+            .map(|(level, _)| level as u8)
+    }
+    let low = past(histogram.iter().enumerate(), clipped)?;
+    let high = past(histogram.iter().enumerate().rev(), clipped)?;
+    (low < high).then_some((low, high))
+}
+
+/// Stretches the luminance of a BGRA buffer over the full range, after clipping `clip`
+/// percent of the darkest and the lightest pixels. Images already spanning the range
+/// are left alone, and so are flat ones.
+pub fn normalize(bgra: &mut [u8], clip: f32) {
+    let Some((low, high)) = stretch_range(&histogram(bgra), clip) else {
+        return;
+    };
+    let lut = Lut::stretch(low, high);
+    if !lut.is_identity() {
+        lut.apply(bgra);
+    }
+}
+
+/// The Otsu threshold of a luminance histogram: the level that best separates the pixels
+/// into a dark and a light class. Levels up to and including it are dark.
+fn otsu_threshold(histogram: &[u32; 256]) -> u8 {
+    let total: u64 = histogram.iter().map(|&n| n as u64).sum();
+    let sum: u64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(level, &n)| level as u64 * n as u64)
+        .sum();
+
+    let (mut dark, mut dark_sum) = (0u64, 0u64);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (level, &n) in histogram.iter().enumerate() {
+        dark += n as u64;
+        dark_sum += level as u64 * n as u64;
+        let light = total - dark;
+        if dark == 0 || light == 0 {
+            continue;
+        }
+        let dark_mean = dark_sum as f64 / dark as f64;
+        let light_mean = (sum - dark_sum) as f64 / light as f64;
+        let variance = dark as f64 * light as f64 * (dark_mean - light_mean).powi(2);
+        if variance > best_variance {
+            (best, best_variance) = (level, variance);
+        }
+    }
+    best as u8
+}
+
+/// Turns a `width` x `height` BGRA image into opaque black and white, split at the Otsu
+/// threshold of its luminance.
+pub fn binarize(bgra: &mut [u8], width: i32, height: i32) {
+    let len = width.max(0) as usize * height.max(0) as usize * 4;
+    let len = len.min(bgra.len());
+    let pixels = &mut bgra[..len];
+
+    let threshold = otsu_threshold(&histogram(pixels));
+
+    pixels.chunks_exact_mut(4).for_each(|p| {
+        let value = if luminance(p) <= threshold { 0 } else { 255 };
+        p.copy_from_slice(&[value, value, value, 255]);
+    });
+}
+
+/// Whether a BGRA image has a dark background, i.e. most of it is dark.
+pub fn is_dark(bgra: &[u8]) -> bool {
+    let pixels = bgra.len() / 4;
+    if pixels == 0 {
+        return false;
+    }
+    let step = (pixels / DARK_SAMPLES).max(1);
+    let mut histogram = [0usize; 256];
+    let mut samples = 0;
+    for p in bgra.chunks_exact(4).step_by(step) {
+        histogram[luminance(p) as usize] += 1;
+        samples += 1;
+    }
+    // the median is the level where half the samples have been counted.
+    let mut seen = 0;
+    let median = histogram
+        .iter()
+        .position(|&n| {
+            seen += n;
+            seen * 2 >= samples
+        })
+        .unwrap_or(255);
+    median < DARK_MEDIAN
+}
+
+/// Whether `mode` inverts the BGRA image.
+pub fn should_invert(mode: Invert, bgra: &[u8]) -> bool {
+    match mode {
+        Invert::Auto => is_dark(bgra),
+        Invert::Always => true,
+        Invert::Never => false,
+    }
+}
+
+/// Inverts the color channels of a BGRA buffer, leaving alpha alone.
+pub fn invert(bgra: &mut [u8]) {
+    bgra.chunks_exact_mut(4).for_each(|p| {
+        p[0] = !p[0];
+        p[1] = !p[1];
+        p[2] = !p[2];
+    });
+}
+
+/// The 3x3 neighborhood of the pixel at (`x`, `y`) in one channel of a `width` x
+/// `height` BGRA image, row by row. Pixels past the edges repeat the edge.
+fn neighborhood(
+    bgra: &[u8],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    channel: usize,
+) -> [u8; 9] {
+    let mut values = [0; 9];
+    let rows = [y.saturating_sub(1), y, (y + 1).min(height - 1)];
+    let columns = [x.saturating_sub(1), x, (x + 1).min(width - 1)];
+    for (i, (row, column)) in rows
+        .iter()
+        .flat_map(|&row| columns.map(|column| (row, column)))
+        .enumerate()
+    {
+        values[i] = bgra[(row * width + column) * 4 + channel];
+    }
+    values
+}
+
+/// Replaces every color channel of a `width` x `height` BGRA image with `f` of its 3x3
+/// neighborhood, see `neighborhood`. Alpha is left alone.
+fn filter(bgra: &mut [u8], width: i32, height: i32, f: impl Fn(u8, [u8; 9]) -> u8) {
+    let (width, height) = (width.max(0) as usize, height.max(0) as usize);
+    if width == 0 || height == 0 || bgra.len() < width * height * 4 {
+        return;
+    }
+    let src = bgra[..width * height * 4].to_vec();
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) * 4;
+            for channel in 0..3 {
+                let values = neighborhood(&src, width, height, x, y, channel);
+                bgra[i + channel] = f(src[i + channel], values);
+            }
+        }
+    }
+}
+
+/// Takes out specks and moiré with a 3x3 median filter.
+pub fn denoise(bgra: &mut [u8], width: i32, height: i32) {
+    filter(bgra, width, height, |_, mut values| {
+        *values.select_nth_unstable(4).1
+    });
+}
+
+/// Sharpens blurry edges with an unsharp mask over a 3x3 Gaussian blur.
+pub fn sharpen(bgra: &mut [u8], width: i32, height: i32) {
+    const KERNEL: [u32; 9] = [1, 2, 1, 2, 4, 2, 1, 2, 1];
+    filter(bgra, width, height, |value, values| {
+        let blurred = values
+            .iter()
+            .zip(KERNEL)
+            .map(|(&v, k)| v as u32 * k)
+            .sum::<u32>() as f32
+            / 16.0;
+        let sharpened = value as f32 + SHARPEN_AMOUNT * (value as f32 - blurred);
+        sharpened.round().clamp(0.0, 255.0) as u8
+    });
+}
+
+/// Whether the cleanup filters run on a `width` x `height` image, skipped on large
+/// ones unless `force`d.
+pub fn is_cleaned_up(width: i32, height: i32, force: bool) -> bool {
+    force || width.max(0) as usize * height.max(0) as usize <= FILTER_MAX_PIXELS
+}
+
+/// Writes the `width` x `height` BGRA buffer `src` to `dst`, of the same size, through
+/// the preprocessing pipeline of the settings. Returns how long each step took.
+pub fn run(
+    src: &[u8],
+    dst: &mut [u8],
+    width: i32,
+    height: i32,
+    settings: &Settings,
+) -> Vec<(Step, Duration)> {
+    dst.copy_from_slice(src);
+    settings.pipeline.run(dst, width, height, settings)
+}
+
+#[test]
+fn lut_test() {
+    assert!(Lut::new(1.0, 0).is_identity());
+    assert!(Lut::new(0.0, 0).is_identity());
+    assert!(!Lut::new(1.4, 0).is_identity());
+
+    let lut = Lut::new(2.0, 0);
+    assert_eq!(lut.0[0], 0);
+    assert_eq!(lut.0[128], 181);
+    assert_eq!(lut.0[255], 255);
+
+    let lut = Lut::new(1.0, 10);
+    assert_eq!(lut.0[0], 10);
+    assert_eq!(lut.0[250], 255);
+    let lut = Lut::new(1.0, -10);
+    assert_eq!(lut.0[5], 0);
+
+    let mut bgra = [0, 128, 255, 77];
+    Lut::new(2.0, 0).apply(&mut bgra);
+    assert_eq!(bgra, [0, 181, 255, 77]);
+}
+
+#[test]
+fn run_test() {
+    let src: Vec<u8> = (0..=255).collect();
+    let mut dst = vec![0; src.len()];
+
+    // nothing enabled, a plain copy.
+    run(&src, &mut dst, 8, 8, &Settings::default());
+    assert_eq!(dst, src);
+
+    let settings = Settings {
+        gamma: 1.4,
+        brightness: 10,
+        ..Default::default()
+    };
+    run(&src, &mut dst, 8, 8, &settings);
+    let mut expected = src.clone();
+    Lut::new(1.4, 10).apply(&mut expected);
+    assert_eq!(dst, expected);
+}
+
+#[test]
+fn normalize_test() {
+    let gray = |v: u8| [v, v, v, 255];
+
+    // a gray cast: everything between 100 and 180, with a stray pixel either way.
+    let mut histogram = [0; 256];
+    histogram[100..=180].fill(10);
+    histogram[5] = 1;
+    histogram[250] = 1;
+    assert_eq!(stretch_range(&histogram, 1.0), Some((100, 180)));
+    assert_eq!(stretch_range(&histogram, 0.0), Some((5, 250)));
+
+    // flat images have no range, whatever the clipping.
+    let mut histogram = [0; 256];
+    histogram[0] = 50;
+    assert_eq!(stretch_range(&histogram, 1.0), None);
+    assert_eq!(stretch_range(&[0; 256], 1.0), None);
+    histogram[255] = 50;
+    assert_eq!(stretch_range(&histogram, 1.0), Some((0, 255)));
+    assert_eq!(stretch_range(&histogram, 100.0), Some((0, 255)));
+
+    let mut bgra = [gray(100), gray(140), gray(180), [90, 100, 110, 0]].concat();
+    normalize(&mut bgra, 0.0);
+    assert_eq!(
+        bgra,
+        [gray(0), gray(128), gray(255), [0, 0, 32, 0]].concat()
+    );
+
+    // black and white, or all white: no change.
+    for pixels in [[gray(0), gray(255)], [gray(255), gray(255)]] {
+        let mut bgra = pixels.concat();
+        normalize(&mut bgra, 1.0);
+        assert_eq!(bgra, pixels.concat());
+    }
+
+    assert!(Lut::stretch(0, 255).is_identity());
+    assert_eq!(Lut::stretch(7, 7).0[8], 255);
+}
+
+#[test]
+fn denoise_test() {
+    let gray = |v: u8| [v, v, v, 255];
+
+    // a white speck in the middle of black goes.
+    let mut bgra = [0, 0, 0, 0, 255, 0, 0, 0, 0].map(gray).concat();
+    denoise(&mut bgra, 3, 3);
+    assert_eq!(bgra, [gray(0); 9].concat());
+
+    // at the edges, the edge pixels count again: a 2 pixel wide stroke stays.
+    let mut bgra = [0, 0, 255, 255].map(gray).concat();
+    denoise(&mut bgra, 4, 1);
+    assert_eq!(bgra, [0, 0, 255, 255].map(gray).concat());
+    let mut bgra = [255, 0, 0, 0].map(gray).concat();
+    denoise(&mut bgra, 4, 1);
+    assert_eq!(bgra, [255, 0, 0, 0].map(gray).concat());
+    let mut bgra = [0, 255, 0, 0].map(gray).concat();
+    denoise(&mut bgra, 4, 1);
+    assert_eq!(bgra, [gray(0); 4].concat());
+
+    // channels are filtered apart, alpha is left alone.
+    let mut bgra = [[10, 0, 0, 1], [20, 200, 0, 2], [30, 0, 0, 3]].concat();
+    denoise(&mut bgra, 3, 1);
+    assert_eq!(bgra, [[10, 0, 0, 1], [20, 0, 0, 2], [30, 0, 0, 3]].concat());
+
+    assert_eq!(
+        neighborhood(&[1, 2, 3, 4].map(gray).concat(), 2, 2, 0, 0, 0),
+        [1, 1, 2, 1, 1, 2, 3, 3, 4]
+    );
+}
+
+#[test]
+fn sharpen_test() {
+    let gray = |v: u8| [v, v, v, 255];
+
+    // a soft step gets steeper, overshooting on either side.
+    let mut bgra = [50, 50, 200, 200].map(gray).concat();
+    sharpen(&mut bgra, 4, 1);
+    assert_eq!(bgra, [50, 13, 238, 200].map(gray).concat());
+
+    // flat areas don't change, and values stay in range.
+    let mut bgra = [gray(90); 6].concat();
+    sharpen(&mut bgra, 3, 2);
+    assert_eq!(bgra, [gray(90); 6].concat());
+    let mut bgra = [0, 255, 0].map(gray).concat();
+    sharpen(&mut bgra, 3, 1);
+    assert_eq!(bgra, [0, 255, 0].map(gray).concat());
+}
+
+#[test]
+fn cleanup_test() {
+    assert!(is_cleaned_up(2000, 2000, false));
+    assert!(!is_cleaned_up(2000, 2001, false));
+    assert!(is_cleaned_up(2000, 2001, true));
+
+    // a speck, taken out when enabled.
+    let speck = [0, 0, 0, 0, 255, 0, 0, 0, 0]
+        .map(|v| [v, v, v, 255])
+        .concat();
+    let mut dst = vec![0; speck.len()];
+    let mut settings = Settings {
+        invert: Invert::Never,
+        ..Default::default()
+    };
+    run(&speck, &mut dst, 3, 3, &settings);
+    assert_eq!(dst, speck);
+    settings.denoise = true;
+    run(&speck, &mut dst, 3, 3, &settings);
+    assert_eq!(dst, [[0, 0, 0, 255]; 9].concat());
+}
+
+#[test]
+fn otsu_threshold_test() {
+    // two clusters, split between them.
+    let mut histogram = [0; 256];
+    histogram[40] = 100;
+    histogram[200] = 300;
+    let threshold = otsu_threshold(&histogram);
+    assert!((40..200).contains(&threshold), "{threshold}");
+
+    let mut histogram = [0; 256];
+    histogram[10..20].fill(5);
+    histogram[100..110].fill(5);
+    let threshold = otsu_threshold(&histogram);
+    assert!((19..100).contains(&threshold), "{threshold}");
+
+    // a single level can't be split.
+    let mut histogram = [0; 256];
+    histogram[128] = 10;
+    assert_eq!(otsu_threshold(&histogram), 0);
+    assert_eq!(otsu_threshold(&[0; 256]), 0);
+}
+
+#[test]
+fn binarize_test() {
+    // dark gray text on a light gray, slightly tinted background.
+    let (ink, paper) = ([60, 70, 80, 255], [190, 200, 180, 128]);
+    let mut bgra = [ink, paper, paper, ink, paper, paper].concat();
+    binarize(&mut bgra, 3, 2);
+    let (k, w) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+    assert_eq!(bgra, [k, w, w, k, w, w].concat());
+
+    // a uniform image comes out white, whatever its shade.
+    let mut bgra = [ink; 4].concat();
+    binarize(&mut bgra, 2, 2);
+    assert_eq!(bgra, [[255; 4]; 4].concat());
+
+    assert_eq!(luminance(&[0, 0, 255, 255]), 76);
+    assert_eq!(luminance(&[255, 255, 255, 0]), 255);
+}
+
+#[test]
+fn invert_test() {
+    for mode in [Invert::Auto, Invert::Always, Invert::Never] {
+        assert_eq!(mode.to_string().parse::<Invert>().unwrap(), mode);
+    }
+    assert!("sometimes".parse::<Invert>().is_err());
+
+    // light text on a dark terminal, and the other way around.
+    let (text, background) = ([220, 220, 220, 255], [30, 30, 30, 255]);
+    let terminal = [background, text, background, background, text].concat();
+    let page = [text, background, text, text, background].concat();
+    assert!(is_dark(&terminal));
+    assert!(!is_dark(&page));
+    assert!(!is_dark(&[]));
+
+    assert!(should_invert(Invert::Auto, &terminal));
+    assert!(!should_invert(Invert::Auto, &page));
+    assert!(should_invert(Invert::Always, &page));
+    assert!(!should_invert(Invert::Never, &terminal));
+
+    let mut bgra = [0, 100, 255, 7];
+    invert(&mut bgra);
+    assert_eq!(bgra, [255, 155, 0, 7]);
+}
+
+#[test]
+#[ignore = "benchmark, run with --ignored --nocapture"]
+fn lut_benchmark() {
+    use std::time::Instant;
+
+    let (gamma, brightness) = (1.4, 10);
+    let src: Vec<u8> = (0..3840 * 2160 * 4).map(|i| (i % 251) as u8).collect();
+
+    let mut naive = src.clone();
+    let start = Instant::now();
+    naive.chunks_exact_mut(4).for_each(|p| {
+        p[..3]
+            .iter_mut()
+            .for_each(|v| *v = adjust(*v, gamma, brightness))
+    });
+    let naive_time = start.elapsed();
+
+    let mut fast = src.clone();
+    let start = Instant::now();
+    Lut::new(gamma, brightness).apply(&mut fast);
+    let lut_time = start.elapsed();
+
+    println!("4K gamma/brightness: powf {naive_time:?}, lut {lut_time:?}");
+    assert_eq!(naive, fast);
+}
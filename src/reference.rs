@@ -0,0 +1,204 @@
+use anyhow::{anyhow, ensure, Result};
+use std::path::{Path, PathBuf};
+
+// extensions of the image files worth handing to the decoder.
+const IMAGE_EXTENSIONS: &[&str] = &["bmp", "gif", "jpeg", "jpg", "png", "tif", "tiff"];
+
+/// Parses clipboard text naming a single image file: a plain or quoted path, or a
+/// `file://` URI. Multi-line text never qualifies. Whether the file exists is up to the
+/// caller.
+pub fn path(text: &str) -> Option<PathBuf> {
+    let text = text.trim();
+    if text.contains(['\r', '\n']) {
+        return None;
+    }
+    let text = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(text);
+
+    let path = match strip_prefix_ignore_case(text, "file://") {
+        // file:///C:/dir/a.png is local, file://server/share/a.png is UNC.
+        Some(rest) => match rest.strip_prefix('/') {
+            Some(local) => percent_decode(local)?.replace('/', "\\"),
+            None => format!("\\\\{}", percent_decode(rest)?.replace('/', "\\")),
+        },
+        None => text.to_owned(),
+    };
+    if !is_absolute(&path) {
+        return None;
+    }
+
+    let path = PathBuf::from(path);
+    has_image_extension(&path).then_some(path)
+}
+
+/// True if the file name ends with one of the image extensions, in any case.
+pub fn has_image_extension(name: &Path) -> bool {
+    name.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Upper bound of the decoded payload of a data URI.
+pub const MAX_DATA_URI_BYTES: usize = 64 << 20;
+
+/// Decodes the payload of clipboard text holding a base64 `data:image/...` URI. Returns
+/// None if the text is not such a URI, and an error if its payload is broken.
+pub fn data_uri(text: &str) -> Option<Result<Vec<u8>>> {
+    let rest = strip_prefix_ignore_case(text.trim(), "data:image/")?;
+    let (meta, payload) = rest.split_once(',')?;
+    if !meta
+        .rsplit(';')
+        .next()
+        .is_some_and(|m| m.eq_ignore_ascii_case("base64"))
+    {
+        return Some(Err(anyhow!("data URI is not base64 encoded.")));
+    }
+    Some(base64_decode(payload, MAX_DATA_URI_BYTES))
+}
+
+/// Decodes standard or URL-safe base64, skipping whitespace. Padding is optional.
+fn base64_decode(text: &str, limit: usize) -> Result<Vec<u8>> {
+    ensure!(
+        text.len() / 4 * 3 <= limit,
+        "data URI is larger than {} MB.",
+        limit >> 20
+    );
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut acc, mut bits) = (0u32, 0);
+    let mut padding = 0;
+    for (i, c) in text.bytes().enumerate() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            c if c.is_ascii_whitespace() => continue,
+            c => return Err(anyhow!("invalid base64 character {:?} at {i}.", c as char)),
+        };
+        ensure!(padding == 0, "base64 data continues after padding at {i}.");
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    // a single leftover character can't hold a whole byte.
+    ensure!(bits < 6 && padding <= 2, "base64 data is truncated.");
+    Ok(bytes)
+}
+
+/// A drive letter path (`C:\` or `C:/`) or a UNC one (`\\server`).
+fn is_absolute(path: &str) -> bool {
+    match path.as_bytes() {
+        [drive, b':', b'\\' | b'/', ..] => drive.is_ascii_alphabetic(),
+        [b'\\', b'\\', ..] => true,
+        _ => false,
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+/// Decodes `%XX` escapes, returning None for malformed ones or invalid UTF-8.
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[test]
+fn path_test() {
+    let some = |p: &str| Some(PathBuf::from(p));
+
+    assert_eq!(
+        path(r"C:\Users\me\Pictures\shot.png"),
+        some(r"C:\Users\me\Pictures\shot.png")
+    );
+    assert_eq!(path("  C:\\a b\\shot.JPG\r\n"), some(r"C:\a b\shot.JPG"));
+    assert_eq!(path(r#""C:\a b\shot.png""#), some(r"C:\a b\shot.png"));
+    assert_eq!(
+        path("file:///C:/Users/me/My%20Pictures/shot.png"),
+        some(r"C:\Users\me\My Pictures\shot.png")
+    );
+    assert_eq!(
+        path("FILE:///C:/%E7%94%BB%E5%83%8F.bmp"),
+        some(r"C:\画像.bmp")
+    );
+    assert_eq!(
+        path("file://server/share/shot.tiff"),
+        some(r"\\server\share\shot.tiff")
+    );
+
+    // not an image, not a single line, or not a path at all.
+    assert_eq!(path(r"C:\notes.txt"), None);
+    assert_eq!(path(r"C:\dir"), None);
+    assert_eq!(path("shot.png"), None);
+    assert_eq!(path(r"see C:\shot.png"), None);
+    assert_eq!(path("C:\\a.png\r\nC:\\b.png"), None);
+    assert_eq!(path("The quick brown fox jumps over the lazy dog."), None);
+    assert_eq!(path(""), None);
+    assert_eq!(path("file:///C:/bad%2.png"), None);
+}
+
+#[test]
+fn data_uri_test() {
+    assert_eq!(
+        data_uri("data:image/png;base64,iVBORw0KGgo=")
+            .unwrap()
+            .unwrap(),
+        b"\x89PNG\r\n\x1a\n"
+    );
+    // wrapped, unpadded and URL-safe payloads.
+    assert_eq!(
+        data_uri(" DATA:image/gif;base64,R0lG\r\nODlh\n")
+            .unwrap()
+            .unwrap(),
+        b"GIF89a"
+    );
+    assert_eq!(
+        data_uri("data:image/bmp;base64,Qk0").unwrap().unwrap(),
+        b"BM"
+    );
+    assert_eq!(
+        data_uri("data:image/png;base64,-_8").unwrap().unwrap(),
+        [0xfb, 0xff]
+    );
+
+    // not an image data URI.
+    assert!(data_uri("data:text/plain;base64,SGk=").is_none());
+    assert!(data_uri("see data:image/png;base64,iVBORw0KGgo=").is_none());
+    assert!(data_uri("data:image/png").is_none());
+
+    // broken ones.
+    assert!(data_uri("data:image/svg+xml,<svg/>").unwrap().is_err());
+    assert!(data_uri("data:image/png;base64,iVBO*w0K").unwrap().is_err());
+    assert!(data_uri("data:image/png;base64,iVBORw0KG")
+        .unwrap()
+        .is_err());
+    assert!(data_uri("data:image/png;base64,iV==BO").unwrap().is_err());
+}
+
+#[test]
+fn base64_decode_limit_test() {
+    assert_eq!(base64_decode("QUJD", 3).unwrap(), b"ABC");
+    assert!(base64_decode("QUJDRA==", 3).is_err());
+}
@@ -0,0 +1,650 @@
+//! A small backtracking regular expression matcher for the replacement rules, taking the
+//! usual syntax:
+//!
+//! - literal characters, `\` escaping any punctuation, and `\t`, `\r`, `\n`
+//! - `.` for any character but a line break, `\d`, `\w`, `\s` and their negations `\D`,
+//!   `\W`, `\S`, and classes like `[a-z_]` or `[^0-9\s]`
+//! - `^` and `$` at the start and end of each line, `\b` at the edge of a word
+//! - groups `(...)`, captured, and `(?:...)`, not captured, with alternatives `a|b`
+//! - repetitions `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}` up to `MAX_REPEAT`, lazy when
+//!   followed by `?`
+//! - `(?i)` starting the pattern to ignore case
+//!
+//! The pattern compiles to a program run with a backtracking stack of its own, so long
+//! text can't overflow the thread's. Matching from any one position gives up after
+//! `MAX_STEPS`, so a pattern that backtracks without end can't hang the scan.
+
+use anyhow::{bail, ensure, Context, Result};
+use std::iter::Peekable;
+use std::str::Chars;
+
+// the most times `{n}`, `{n,}` or `{n,m}` may repeat.
+const MAX_REPEAT: usize = 1000;
+
+// the most steps matching from one position may take.
+const MAX_STEPS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Self::Digit => c.is_ascii_digit(),
+            Self::Word => is_word(c),
+            Self::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<Item>,
+        negated: bool,
+    },
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// A compiled pattern.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    // the captures, two each, then one for each repetition that may match nothing.
+    slots: usize,
+    groups: usize,
+    ignore_case: bool,
+}
+
+/// The spans of a match, in characters: the whole match first, then each group, None
+/// for a group that took no part in it.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    groups: usize,
+}
+
+impl Parser<'_> {
+    fn alternation(&mut self) -> Result<Node> {
+        let mut branches = vec![self.concat()?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            branches.push(self.concat()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap(),
+            _ => Node::Alternation(branches),
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repeat(atom)?);
+        }
+        Ok(match nodes.len() {
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let c = self.chars.next().context("unexpected end.")?;
+        Ok(match c {
+            '(' => {
+                let index = if self.chars.next_if_eq(&'?').is_some() {
+                    ensure!(self.chars.next() == Some(':'), "unknown group kind.");
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let node = self.alternation()?;
+                ensure!(self.chars.next() == Some(')'), "unclosed group.");
+                Node::Group(Box::new(node), index)
+            }
+            '[' => self.class()?,
+            '.' => Node::Any,
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '\\' if self.chars.next_if_eq(&'b').is_some() => Node::WordBoundary,
+            '\\' => match self.escape()? {
+                Item::Range(c, _) => Node::Char(c),
+                item => Node::Class {
+                    items: vec![item],
+                    negated: false,
+                },
+            },
+            '*' | '+' | '?' | '{' => bail!("nothing to repeat before `{c}`."),
+            c => Node::Char(c),
+        })
+    }
+
+    /// What follows a `\`: a character, or a class like `\d`.
+    fn escape(&mut self) -> Result<Item> {
+        let c = self.chars.next().context("unexpected end after `\\`.")?;
+        let perl = |perl, negated| Ok(Item::Perl(perl, negated));
+        let c = match c {
+            'd' => return perl(Perl::Digit, false),
+            'D' => return perl(Perl::Digit, true),
+            'w' => return perl(Perl::Word, false),
+            'W' => return perl(Perl::Word, true),
+            's' => return perl(Perl::Space, false),
+            'S' => return perl(Perl::Space, true),
+            't' => '\t',
+            'r' => '\r',
+            'n' => '\n',
+            c if c.is_alphanumeric() => bail!("unknown escape `\\{c}`."),
+            c => c,
+        };
+        Ok(Item::Range(c, c))
+    }
+
+    fn class(&mut self) -> Result<Node> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut items = Vec::new();
+        loop {
+            let c = self.chars.next().context("unclosed `[`.")?;
+            let item = match c {
+                // a `]` first is taken literally.
+                ']' if !items.is_empty() => break,
+                '\\' => self.escape()?,
+                c => Item::Range(c, c),
+            };
+            let Item::Range(first, _) = item else {
+                items.push(item);
+                continue;
+            };
+            let mut lookahead = self.chars.clone();
+            if lookahead.next() == Some('-') && lookahead.peek().is_some_and(|&c| c != ']') {
+                self.chars.next();
+                let last = match self.chars.next() {
+                    Some('\\') => match self.escape()? {
+                        Item::Range(last, _) => last,
+                        _ => bail!("a class can't end a range."),
+                    },
+                    Some(last) => last,
+                    None => bail!("unclosed `[`."),
+                };
+                ensure!(first <= last, "range `{first}-{last}` out of order.");
+                items.push(Item::Range(first, last));
+            } else {
+                items.push(item);
+            }
+        }
+        Ok(Node::Class { items, negated })
+    }
+
+    fn repeat(&mut self, node: Node) -> Result<Node> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.chars.next();
+                let mut bounds = String::new();
+                loop {
+                    match self.chars.next().context("unclosed `{`.")? {
+                        '}' => break,
+                        c => bounds.push(c),
+                    }
+                }
+                let number = |s: &str| s.trim().parse::<usize>().context("bad repetition.");
+                let (min, max) = match bounds.split_once(',') {
+                    None => (number(&bounds)?, Some(number(&bounds)?)),
+                    Some((min, "")) => (number(min)?, None),
+                    Some((min, max)) => (number(min)?, Some(number(max)?)),
+                };
+                ensure!(max.is_none_or(|max| min <= max), "repetition out of order.");
+                ensure!(
+                    max.unwrap_or(min) <= MAX_REPEAT,
+                    "repetition over {MAX_REPEAT}."
+                );
+                let greedy = self.chars.next_if_eq(&'?').is_none();
+                return self.repeated(node, min, max, greedy);
+            }
+            _ => return Ok(node),
+        };
+        self.chars.next();
+        let greedy = self.chars.next_if_eq(&'?').is_none();
+        self.repeated(node, min, max, greedy)
+    }
+
+    fn repeated(
+        &mut self,
+        node: Node,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    ) -> Result<Node> {
+        if let Some(&c) = self.chars.peek() {
+            ensure!(!"*+?{".contains(c), "nothing to repeat before `{c}`.");
+        }
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        })
+    }
+}
+
+/// An instruction of a compiled pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    /// A `Char`, `Any` or `Class` node matching the next character.
+    One(Node),
+    /// A `LineStart`, `LineEnd` or `WordBoundary` node matching where it is.
+    Assert(Node),
+    /// Goes on at the first, coming back to the second when that fails.
+    Split(usize, usize),
+    Jump(usize),
+    /// Keeps where it is in a slot, a capture or the start of a repetition.
+    Save(usize),
+    /// Fails where the repetition started in the slot, as matching nothing would go on
+    /// forever.
+    Progress(usize),
+    Match,
+}
+
+// the most instructions a pattern may compile to, e.g. with large repetitions.
+const MAX_PROGRAM: usize = 100_000;
+
+struct Compiler {
+    program: Vec<Inst>,
+    slots: usize,
+    // nodes compiled, counted as some compile to nothing.
+    nodes: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    /// Points the `Split` at `at` to `body` and `exit`, in the order `greedy` tries them.
+    fn split(&mut self, at: usize, body: usize, exit: usize, greedy: bool) {
+        self.program[at] = if greedy {
+            Inst::Split(body, exit)
+        } else {
+            Inst::Split(exit, body)
+        };
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<()> {
+        self.nodes += 1;
+        ensure!(
+            self.program.len() <= MAX_PROGRAM && self.nodes <= MAX_PROGRAM,
+            "the pattern repeats too much."
+        );
+        match node {
+            Node::Char(_) | Node::Any | Node::Class { .. } => {
+                self.push(Inst::One(node.clone()));
+            }
+            Node::LineStart | Node::LineEnd | Node::WordBoundary => {
+                self.push(Inst::Assert(node.clone()));
+            }
+            Node::Group(node, index) => {
+                if let Some(index) = *index {
+                    self.push(Inst::Save(index * 2));
+                    self.compile(node)?;
+                    self.push(Inst::Save(index * 2 + 1));
+                } else {
+                    self.compile(node)?;
+                }
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternation(branches) => {
+                let mut jumps = Vec::new();
+                let (last, rest) = branches.split_last().unwrap();
+                for branch in rest {
+                    let split = self.push(Inst::Match);
+                    self.compile(branch)?;
+                    jumps.push(self.push(Inst::Match));
+                    self.split(split, split + 1, self.program.len(), true);
+                }
+                self.compile(last)?;
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(self.program.len());
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Match));
+                            self.compile(node)?;
+                        }
+                        for split in splits {
+                            self.split(split, split + 1, self.program.len(), *greedy);
+                        }
+                    }
+                    None => {
+                        // a single character always moves on, other nodes may not.
+                        let moves =
+                            matches!(**node, Node::Char(_) | Node::Any | Node::Class { .. });
+                        let split = self.push(Inst::Match);
+                        let slot = self.slots;
+                        if !moves {
+                            self.slots += 1;
+                            self.push(Inst::Save(slot));
+                        }
+                        self.compile(node)?;
+                        if !moves {
+                            self.push(Inst::Progress(slot));
+                        }
+                        self.push(Inst::Jump(split));
+                        self.split(split, split + 1, self.program.len(), *greedy);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What to do when backtracking: try from an instruction, or put a slot back.
+enum Backtrack {
+    Try(usize, usize),
+    Restore(usize, Option<usize>),
+}
+
+/// A search over `chars`.
+struct Search<'a> {
+    regex: &'a Regex,
+    chars: &'a [char],
+}
+
+impl Search<'_> {
+    fn same(&self, a: char, b: char) -> bool {
+        a == b || (self.regex.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    /// Whether the `One` node `node` matches `c`.
+    fn one(&self, node: &Node, c: char) -> bool {
+        match node {
+            Node::Char(expected) => self.same(*expected, c),
+            Node::Any => c != '\n' && c != '\r',
+            Node::Class { items, negated } => {
+                let cases = if self.regex.ignore_case {
+                    [
+                        c,
+                        c.to_lowercase().next().unwrap_or(c),
+                        c.to_uppercase().next().unwrap_or(c),
+                    ]
+                } else {
+                    [c; 3]
+                };
+                let found = items.iter().any(|item| match *item {
+                    Item::Range(first, last) => cases.iter().any(|c| (first..=last).contains(c)),
+                    Item::Perl(perl, negated) => perl.matches(c) != negated,
+                });
+                found != *negated
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the `Assert` node `node` holds at `at`.
+    fn holds(&self, node: &Node, at: usize) -> bool {
+        let chars = self.chars;
+        match node {
+            Node::LineStart => at == 0 || chars[at - 1] == '\n',
+            Node::LineEnd => match chars.get(at) {
+                None | Some('\n') => true,
+                Some('\r') => chars.get(at + 1).is_none_or(|&c| c == '\n'),
+                _ => false,
+            },
+            Node::WordBoundary => {
+                let before = at > 0 && is_word(chars[at - 1]);
+                let after = chars.get(at).is_some_and(|&c| is_word(c));
+                before != after
+            }
+            _ => false,
+        }
+    }
+
+    /// The slots of the first match starting at `start`, trying the alternatives in order
+    /// and backtracking through a stack of its own rather than by recursing.
+    fn run(&self, start: usize) -> Result<Option<Vec<Option<usize>>>> {
+        let program = &self.regex.program;
+        let mut steps = 0;
+        let mut slots = vec![None; self.regex.slots];
+        let mut stack = vec![Backtrack::Try(0, start)];
+        while let Some(backtrack) = stack.pop() {
+            let (mut pc, mut at) = match backtrack {
+                Backtrack::Try(pc, at) => (pc, at),
+                Backtrack::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            loop {
+                steps += 1;
+                ensure!(steps <= MAX_STEPS, "the pattern takes too long.");
+                match &program[pc] {
+                    Inst::One(node) => {
+                        if !self.chars.get(at).is_some_and(|&c| self.one(node, c)) {
+                            break;
+                        }
+                        at += 1;
+                    }
+                    Inst::Assert(node) => {
+                        if !self.holds(node, at) {
+                            break;
+                        }
+                    }
+                    Inst::Split(first, second) => {
+                        stack.push(Backtrack::Try(*second, at));
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Jump(to) => {
+                        pc = *to;
+                        continue;
+                    }
+                    Inst::Save(slot) => {
+                        stack.push(Backtrack::Restore(*slot, slots[*slot]));
+                        slots[*slot] = Some(at);
+                    }
+                    Inst::Progress(slot) => {
+                        if slots[*slot] == Some(at) {
+                            break;
+                        }
+                    }
+                    Inst::Match => return Ok(Some(slots)),
+                }
+                pc += 1;
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let (ignore_case, pattern) = match pattern.strip_prefix("(?i)") {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: pattern.chars().peekable(),
+            groups: 0,
+        };
+        let node = parser.alternation()?;
+        ensure!(parser.chars.next().is_none(), "unmatched `)`.");
+        // the whole match is group 0, its slots the first two.
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            slots: (parser.groups + 1) * 2,
+            nodes: 0,
+        };
+        compiler.compile(&Node::Group(Box::new(node), Some(0)))?;
+        compiler.push(Inst::Match);
+        Ok(Self {
+            program: compiler.program,
+            slots: compiler.slots,
+            groups: parser.groups,
+            ignore_case,
+        })
+    }
+
+    /// The number of capturing groups.
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    /// The leftmost match in `chars` starting at `from` or after, None without one. An
+    /// error if the search took too long.
+    pub fn find(&self, chars: &[char], from: usize) -> Result<Option<Captures>> {
+        let search = Search { regex: self, chars };
+        for start in from..=chars.len() {
+            if let Some(slots) = search.run(start)? {
+                let caps = (0..=self.groups)
+                    .map(|group| slots[group * 2].zip(slots[group * 2 + 1]))
+                    .collect();
+                return Ok(Some(caps));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+fn matches(pattern: &str, text: &str) -> Vec<String> {
+    // every match of `pattern` in `text`.
+    let regex = Regex::new(pattern).unwrap();
+    let chars: Vec<char> = text.chars().collect();
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(caps) = regex.find(&chars, from).unwrap() {
+        let (start, end) = caps[0].unwrap();
+        found.push(chars[start..end].iter().collect());
+        from = if end > start { end } else { end + 1 };
+        if from > chars.len() {
+            break;
+        }
+    }
+    found
+}
+
+#[test]
+fn regex_test() {
+    assert_eq!(matches("2X-1OO", "a 2X-1OO b 2X-1OO"), ["2X-1OO", "2X-1OO"]);
+    assert_eq!(matches(r"\d+", "ab 12 c3"), ["12", "3"]);
+    assert_eq!(
+        matches(r"[A-Z][a-z]*", "Hello World ok"),
+        ["Hello", "World"]
+    );
+    assert_eq!(matches(r"[^\s,]+", "a,bc d"), ["a", "bc", "d"]);
+    assert_eq!(matches("colou?r", "color colour"), ["color", "colour"]);
+    assert_eq!(matches("a{2,3}", "a aa aaaa"), ["aa", "aaa"]);
+    assert_eq!(matches("<.+?>", "<a><b>"), ["<a>", "<b>"]);
+    assert_eq!(matches("<.+>", "<a><b>"), ["<a><b>"]);
+    assert_eq!(matches("(ab)+c", "ababc abc"), ["ababc", "abc"]);
+    assert_eq!(matches("cat|dog", "a dog, a cat"), ["dog", "cat"]);
+    assert_eq!(matches(r"\bis\b", "this is"), ["is"]);
+    assert_eq!(matches("^x.*$", "x1\r\ny\r\nx2"), ["x1", "x2"]);
+    assert_eq!(matches("(?i)page", "Page PAGE"), ["Page", "PAGE"]);
+    assert_eq!(matches("(?i)[a-c]+", "ABC"), ["ABC"]);
+    assert_eq!(matches(r"\$\d+\.\d\d", "for $5.00"), ["$5.00"]);
+    assert_eq!(matches(r"[]x-]", "a]-x"), ["]", "-", "x"]);
+    assert_eq!(matches("日本+", "日本本語"), ["日本本"]);
+    assert_eq!(matches("(a*)*b", "aab"), ["aab"]);
+    assert_eq!(matches("x*", "ab").len(), 3);
+
+    let caps = Regex::new(r"(\w+)@(?:(x)|(\w+))")
+        .unwrap()
+        .find(&"me@host".chars().collect::<Vec<_>>(), 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!(caps, [Some((0, 7)), Some((0, 2)), None, Some((3, 7))]);
+}
+
+#[test]
+fn invalid_test() {
+    for pattern in [
+        "(a", "a)", "[a", "*a", "a**", "a{2", "a{3,1}", r"\q", "[z-a]", "(?<n>a)", "\\",
+    ] {
+        assert!(Regex::new(pattern).is_err(), "{pattern}");
+    }
+    // backtracking without end gives up.
+    let regex = Regex::new("(a|aa)+$").unwrap();
+    let chars: Vec<char> = "a".repeat(40).chars().chain(['b']).collect();
+    assert!(regex.find(&chars, 0).is_err());
+    assert!(Regex::new("(?:a{1000}){1000}").is_err());
+    // repeating what compiles to nothing is refused before compiling it.
+    assert!(Regex::new("x(?:){4000000000}").is_err());
+    assert!(Regex::new("a{1001}").is_err());
+    assert!(Regex::new("a{2,1001}").is_err());
+    assert!(Regex::new("a{1000}").is_ok());
+    assert!(Regex::new("(?:(?:(?:){1000}){1000}){1000}").is_err());
+}
+
+#[test]
+fn long_test() {
+    // repeating a group over long text takes no stack for each repetition.
+    let text = format!("{}x", "ab ".repeat(40_000));
+    assert_eq!(matches("(?:ab )+", &text), [&text[..text.len() - 1]]);
+    assert_eq!(matches(r"(\w+ )*x", &text), [text.as_str()]);
+    let chars: Vec<char> = text.chars().collect();
+    let caps = Regex::new(r"(\w+ )*x").unwrap().find(&chars, 0).unwrap();
+    assert_eq!(caps.unwrap()[1], Some((chars.len() - 4, chars.len() - 1)));
+}
+
+#[test]
+fn long_text_test() {
+    // ordinary rules over a long result of many pages, each start its own budget.
+    let page = "Invoice 2X-1OO, page total 500 yen\r\nThank you for your order.\r\n".repeat(40);
+    let text = format!("{}DRAFT watermark\r\n", page.repeat(100));
+    assert!(text.len() > 200_000);
+    assert_eq!(matches(".*watermark", &text), ["DRAFT watermark"]);
+    assert_eq!(matches("watermark", &text), ["watermark"]);
+    assert_eq!(matches("2X-1OO", &text).len(), 4000);
+    assert!(matches("missing", &text).is_empty());
+}
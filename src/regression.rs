@@ -0,0 +1,163 @@
+//! Recognition regression tests against checked-in fixtures.
+//!
+//! Each fixture in `tests/fixtures` is an image plus a golden `.txt` holding the expected
+//! output. The suite needs the Windows OCR engine and the fixtures' language packs, so it is
+//! ignored by default:
+//!
+//! ```text
+//! cargo test regression -- --ignored
+//! ```
+//!
+//! When a change is meant to alter the output, rewrite the goldens with
+//! `OCR_UPDATE_GOLDENS=1` set and review the diff before committing it.
+
+use super::ocr::{Lang, WinRt};
+use super::{image, image::Image, recognize_text, settings::Settings, wic};
+use anyhow::{Context, Result};
+use std::{env, fs, path::PathBuf};
+use windows::{core::HSTRING, Globalization::Language};
+
+struct Fixture {
+    name: &'static str,
+    lang: &'static str,
+    // largest edit distance from the golden, after whitespace normalization.
+    max_distance: usize,
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "clean",
+        lang: "en-US",
+        max_distance: 0,
+    },
+    Fixture {
+        name: "dark",
+        lang: "en-US",
+        max_distance: 2,
+    },
+    Fixture {
+        name: "japanese",
+        lang: "ja",
+        max_distance: 2,
+    },
+    Fixture {
+        name: "lowres",
+        lang: "en-US",
+        max_distance: 4,
+    },
+    Fixture {
+        name: "two_column",
+        lang: "en-US",
+        max_distance: 6,
+    },
+];
+
+fn fixture_path(name: &str, ext: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{name}.{ext}"))
+}
+
+/// The path of the fixture image, a BMP or else a PNG, None if there is neither.
+fn image_path(name: &str) -> Option<PathBuf> {
+    ["bmp", "png"]
+        .iter()
+        .map(|ext| fixture_path(name, ext))
+        .find(|path| path.exists())
+}
+
+/// Loads the fixture image.
+fn load(name: &str) -> Result<Image> {
+    let path = image_path(name).context("no fixture image.")?;
+    let bytes = fs::read(&path).context(c!())?;
+    wic::decode(&bytes)
+}
+
+/// Runs the image through the same steps as a clipboard scan with default settings: the
+/// whole recognition pipeline of `recognize`, with the engine of Windows.
+fn run(mut image: Image, lang: &str) -> Result<String> {
+    image::flatten_alpha(&mut image.bgra);
+    let lang = Lang::Tag(Language::CreateLanguage(&HSTRING::from(lang))?);
+    let engine = WinRt { timeout: None };
+    let (text, _, _) =
+        recognize_text(&engine, &lang, &image, Settings::default(), false, &|| true)?;
+    Ok(text)
+}
+
+/// Collapses every run of whitespace into a single space and trims both ends.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Levenshtein distance counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[test]
+#[ignore = "needs the Windows OCR engine and language packs"]
+fn regression_test() {
+    let update = env::var_os("OCR_UPDATE_GOLDENS").is_some();
+    let mut failures = Vec::new();
+    for fixture in FIXTURES {
+        let actual = load(fixture.name)
+            .and_then(|image| run(image, fixture.lang))
+            .unwrap_or_else(|e| panic!("{}: {e:#}", fixture.name));
+        let golden = fixture_path(fixture.name, "txt");
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&golden).unwrap();
+        let distance = edit_distance(&normalize(&expected), &normalize(&actual));
+        if distance > fixture.max_distance {
+            failures.push(format!(
+                "{}: distance {distance} > {}\n--- expected\n{expected}\n--- actual\n{actual}",
+                fixture.name, fixture.max_distance
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn fixtures_test() {
+    // every fixture is checked in with its image and golden.
+    for fixture in FIXTURES {
+        assert!(image_path(fixture.name).is_some(), "{}", fixture.name);
+        assert!(
+            fixture_path(fixture.name, "txt").exists(),
+            "{}",
+            fixture.name
+        );
+    }
+}
+
+#[test]
+fn normalize_test() {
+    assert_eq!(normalize(""), "");
+    assert_eq!(normalize("  a \r\n b\t\tc \r\n"), "a b c");
+    assert_eq!(normalize("日本語\u{3000}テキスト"), "日本語 テキスト");
+}
+
+#[test]
+fn edit_distance_test() {
+    assert_eq!(edit_distance("", ""), 0);
+    assert_eq!(edit_distance("abc", ""), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("日本語", "日本話"), 1);
+    assert_eq!(edit_distance("flaw", "lawn"), 2);
+}
@@ -0,0 +1,115 @@
+/// Bounded, cancelable re-checks of a clipboard update whose formats were not usable
+/// (yet) when it was notified. The caller drives it from a WM_TIMER and asks `fire`
+/// whether to probe again; a newer clipboard sequence number or paused monitoring
+/// cancels the remaining attempts. Each attempt waits twice as long as the one before.
+#[derive(Debug, Default)]
+pub struct Reprobe {
+    // the sequence number being re-probed, and the attempts left for it.
+    pending: Option<(u32, u32)>,
+    // attempts made for the current sequence number.
+    fired: u32,
+}
+
+impl Reprobe {
+    pub const fn new() -> Self {
+        Self {
+            pending: None,
+            fired: 0,
+        }
+    }
+
+    /// Schedules up to `attempts` re-probes of `sequence`, replacing any earlier schedule.
+    pub fn start(&mut self, sequence: u32, attempts: u32) {
+        self.pending = (attempts > 0).then_some((sequence, attempts));
+        self.fired = 0;
+    }
+
+    /// The wait before the next attempt, `base` milliseconds for the first one.
+    pub fn interval(&self, base: u32) -> u32 {
+        base.saturating_mul(1 << self.fired.min(16))
+    }
+
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Called when the timer elapses with the current clipboard sequence number.
+    /// Returns true if the clipboard should be probed now.
+    pub fn fire(&mut self, sequence: u32, paused: bool) -> bool {
+        let Some((expected, attempts)) = self.pending else {
+            return false;
+        };
+        if paused || sequence != expected {
+            self.cancel();
+            return false;
+        }
+        self.pending = (attempts > 1).then_some((expected, attempts - 1));
+        self.fired += 1;
+        true
+    }
+}
+
+#[test]
+fn reprobe_test() {
+    let mut reprobe = Reprobe::new();
+    assert!(!reprobe.fire(1, false));
+
+    reprobe.start(1, 2);
+    assert!(reprobe.fire(1, false));
+    assert!(reprobe.is_pending());
+    assert!(reprobe.fire(1, false));
+    assert!(!reprobe.is_pending());
+    assert!(!reprobe.fire(1, false));
+
+    reprobe.start(1, 0);
+    assert!(!reprobe.is_pending());
+}
+
+#[test]
+fn reprobe_cancel_test() {
+    let mut reprobe = Reprobe::new();
+    reprobe.start(1, 2);
+    reprobe.cancel();
+    assert!(!reprobe.fire(1, false));
+
+    // a newer clipboard update cancels the re-probes of the old one.
+    reprobe.start(1, 2);
+    assert!(!reprobe.fire(2, false));
+    assert!(!reprobe.is_pending());
+
+    // restarting for a newer sequence replaces the old schedule.
+    reprobe.start(1, 2);
+    reprobe.start(2, 1);
+    assert!(!reprobe.fire(1, false));
+    reprobe.start(2, 1);
+    assert!(reprobe.fire(2, false));
+    assert!(!reprobe.is_pending());
+}
+
+#[test]
+fn reprobe_paused_test() {
+    let mut reprobe = Reprobe::new();
+    reprobe.start(1, 2);
+    assert!(!reprobe.fire(1, true));
+    assert!(!reprobe.is_pending());
+    assert!(!reprobe.fire(1, false));
+}
+
+#[test]
+fn reprobe_interval_test() {
+    let mut reprobe = Reprobe::new();
+    reprobe.start(1, 3);
+    assert_eq!(reprobe.interval(300), 300);
+    assert!(reprobe.fire(1, false));
+    assert_eq!(reprobe.interval(300), 600);
+    assert!(reprobe.fire(1, false));
+    assert_eq!(reprobe.interval(300), 1200);
+
+    // a new schedule starts over.
+    reprobe.start(2, 1);
+    assert_eq!(reprobe.interval(300), 300);
+}
@@ -0,0 +1,167 @@
+use anyhow::{anyhow, ensure, Context, Result};
+
+/// Expands a BI_RLE8 (`bits_per_pixel` 8) or BI_RLE4 (4) stream into bottom-up palette
+/// indices, each row padded to `stride` bytes. Pixels the stream skips with delta or
+/// end-of-line escapes are left at index 0.
+pub fn decode(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    bits_per_pixel: u16,
+    stride: usize,
+) -> Result<Vec<u8>> {
+    ensure!(
+        matches!(bits_per_pixel, 4 | 8),
+        "run-length encoding of {bits_per_pixel} bits per pixel is not supported."
+    );
+    let mut out = Canvas {
+        data: vec![0; stride.checked_mul(height).context(c!())?],
+        width,
+        height,
+        stride,
+        bits_per_pixel,
+        x: 0,
+        y: 0,
+    };
+    let mut src = src.iter().copied();
+    let mut next = || src.next().context("run-length data ends unexpectedly.");
+
+    loop {
+        let (count, value) = (next()?, next()?);
+        match (count, value) {
+            // encoded run, RLE4 alternates the two nibbles.
+            (1.., _) => {
+                for i in 0..count {
+                    out.put(nibble_or_byte(value, i, bits_per_pixel))?;
+                }
+            }
+            // end of line.
+            (0, 0) => {
+                out.x = 0;
+                out.y += 1;
+            }
+            // end of bitmap.
+            (0, 1) => break,
+            // delta.
+            (0, 2) => {
+                out.x += next()? as usize;
+                out.y += next()? as usize;
+            }
+            // absolute run, padded to a 16-bit boundary.
+            (0, n) => {
+                let bytes = if bits_per_pixel == 8 {
+                    n as usize
+                } else {
+                    (n as usize).div_ceil(2)
+                };
+                let mut byte = 0;
+                for i in 0..n {
+                    if bits_per_pixel == 8 || i.is_multiple_of(2) {
+                        byte = next()?;
+                    }
+                    out.put(nibble_or_byte(byte, i, bits_per_pixel))?;
+                }
+                if !bytes.is_multiple_of(2) {
+                    next()?;
+                }
+            }
+        }
+    }
+    Ok(out.data)
+}
+
+// the `i`-th pixel of a run repeating `value`.
+fn nibble_or_byte(value: u8, i: u8, bits_per_pixel: u16) -> u8 {
+    match (bits_per_pixel, i % 2) {
+        (8, _) => value,
+        (_, 0) => value >> 4,
+        _ => value & 0x0f,
+    }
+}
+
+struct Canvas {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    stride: usize,
+    bits_per_pixel: u16,
+    x: usize,
+    y: usize,
+}
+
+impl Canvas {
+    fn put(&mut self, index: u8) -> Result<()> {
+        if self.x >= self.width || self.y >= self.height {
+            return Err(anyhow!(
+                "run-length data runs past the image at ({}, {}).",
+                self.x,
+                self.y
+            ));
+        }
+        let row = self.y * self.stride;
+        if self.bits_per_pixel == 8 {
+            self.data[row + self.x] = index;
+        } else {
+            let shift = if self.x.is_multiple_of(2) { 4 } else { 0 };
+            self.data[row + self.x / 2] |= index << shift;
+        }
+        self.x += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn rle8_test() {
+    // 5x3, stride 8.
+    let src = [
+        2, 7, // run of two 7s
+        0, 3, 1, 2, 3, 0, // absolute run of 1, 2, 3 plus padding
+        0, 0, // end of line
+        0, 2, 1, 1, // delta to (1, 2)
+        2, 9, // run of two 9s
+        0, 1, // end of bitmap
+    ];
+    assert_eq!(
+        decode(&src, 5, 3, 8, 8).unwrap(),
+        [
+            [7, 7, 1, 2, 3, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 9, 9, 0, 0, 0, 0, 0],
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn rle4_test() {
+    // 5x2, stride 4.
+    let src = [
+        2, 0x12, // run of 1, 2
+        0, 3, 0x34, 0x50, // absolute run of 3, 4, 5
+        0, 0, // end of line
+        0, 5, 0x56, 0x78, 0x90, 0, // absolute run of 5, 6, 7, 8, 9 plus padding
+        0, 1, // end of bitmap
+    ];
+    assert_eq!(
+        decode(&src, 5, 2, 4, 4).unwrap(),
+        [[0x12, 0x34, 0x50, 0], [0x56, 0x78, 0x90, 0]].concat()
+    );
+}
+
+#[test]
+fn malformed_test() {
+    // missing end of bitmap.
+    assert!(decode(&[2, 7], 5, 1, 8, 8).is_err());
+    // absolute run longer than the data.
+    assert!(decode(&[0, 4, 1, 2], 5, 1, 8, 8).is_err());
+    // absolute run missing its padding byte.
+    assert!(decode(&[0, 3, 1, 2, 3], 5, 1, 8, 8).is_err());
+    // delta below the last row.
+    assert!(decode(&[0, 2, 0, 5, 1, 7, 0, 1], 5, 2, 8, 8).is_err());
+    // run wider than the image.
+    assert!(decode(&[6, 7, 0, 1], 5, 1, 8, 8).is_err());
+    // unsupported depth.
+    assert!(decode(&[0, 1], 5, 1, 24, 16).is_err());
+    // a trailing delta past the image is harmless if nothing is drawn there.
+    assert!(decode(&[0, 2, 0, 5, 0, 1], 5, 2, 8, 8).is_ok());
+}
@@ -0,0 +1,92 @@
+use std::fmt::Write;
+
+/// Writes `text` as a minimal RTF document, one `\par` per line, in a font that covers
+/// `lang`, the tag of the recognition language.
+pub fn rtf(text: &str, lang: &str) -> String {
+    let (font, charset) = font(lang);
+    let mut rtf =
+        format!("{{\\rtf1\\ansi\\deff0{{\\fonttbl{{\\f0\\fcharset{charset} {font};}}}}\\uc1\\f0 ");
+    for line in text.lines() {
+        escape(line, &mut rtf);
+        rtf.push_str("\\par\r\n");
+    }
+    rtf.push('}');
+    rtf
+}
+
+/// A font, and its charset, with glyphs for the language so old apps don't fall back to
+/// boxes.
+fn font(lang: &str) -> (&'static str, u8) {
+    let lang = lang.to_ascii_lowercase();
+    let primary = lang.split('-').next().unwrap_or_default();
+    let traditional = ["-hant", "-tw", "-hk", "-mo"]
+        .iter()
+        .any(|s| lang.contains(s));
+    match primary {
+        "ja" => ("MS Gothic", 128),
+        "ko" => ("Malgun Gothic", 129),
+        "zh" if traditional => ("MingLiU", 136),
+        "zh" => ("SimSun", 134),
+        _ => ("Segoe UI", 0),
+    }
+}
+
+/// Escapes RTF control characters, and everything outside ASCII as `\uN?` per UTF-16 code
+/// unit, N being signed 16-bit.
+fn escape(text: &str, rtf: &mut String) {
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                rtf.push('\\');
+                rtf.push(c);
+            }
+            '\t' => rtf.push_str("\\tab "),
+            ' '..='~' => rtf.push(c),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    write!(rtf, "\\u{}?", *unit as i16).unwrap_or_default();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn rtf_test() {
+    assert_eq!(
+        rtf("Hello\r\nworld\r\n", "en-US"),
+        "{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fcharset0 Segoe UI;}}\\uc1\\f0 \
+         Hello\\par\r\nworld\\par\r\n}"
+    );
+    assert!(rtf("日本", "ja").contains("{\\f0\\fcharset128 MS Gothic;}"));
+}
+
+#[test]
+fn font_test() {
+    assert_eq!(font("ja-JP"), ("MS Gothic", 128));
+    assert_eq!(font("ko"), ("Malgun Gothic", 129));
+    assert_eq!(font("zh-Hans-CN"), ("SimSun", 134));
+    assert_eq!(font("zh-Hant-TW"), ("MingLiU", 136));
+    assert_eq!(font("zh-TW"), ("MingLiU", 136));
+    assert_eq!(font("en-US"), ("Segoe UI", 0));
+    assert_eq!(font(""), ("Segoe UI", 0));
+}
+
+#[test]
+fn escape_test() {
+    let escaped = |text: &str| {
+        let mut rtf = String::new();
+        escape(text, &mut rtf);
+        rtf
+    };
+    assert_eq!(escaped(r"a\b {c}"), r"a\\b \{c\}");
+    assert_eq!(escaped("a\tb"), "a\\tab b");
+    assert_eq!(escaped("é"), "\\u233?");
+    // code units past 0x7fff go negative.
+    assert_eq!(escaped("日本"), "\\u26085?\\u26412?");
+    assert_eq!(escaped("\u{ff01}"), "\\u-255?");
+    // a surrogate pair is two units, both negative.
+    assert_eq!(escaped("😀"), "\\u-10179?\\u-8704?");
+    assert_eq!(escaped("\u{7f}"), "\\u127?");
+}
@@ -0,0 +1,336 @@
+//! Replacement rules applied in order to the text of every result, e.g. to fix a product
+//! code the engine always misreads or to drop a watermark. They are kept in `ocr.rules`
+//! next to the executable, a line each, checked to be applied:
+//!
+//! ```text
+//! # our product code, as the engine reads it
+//! [x] 2X-1OO => ZX-100
+//! [x] (?i)^confidential\r?\n =>
+//! [ ] (\d+) ?yen => ¥$1
+//! ```
+//!
+//! The pattern, see `regex`, runs up to the first ` =>`. `$1` to `$9` or `${12}` in the
+//! replacement stand for what a group matched, `$0` for the whole match and `$$` for a
+//! dollar sign. Lines starting with `#` are comments.
+
+use super::regex::Regex;
+use anyhow::{bail, ensure, Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+// the rules as last read, and when their file was modified then.
+type Loaded = Option<(Option<SystemTime>, Arc<Vec<Rule>>)>;
+
+static LOADED: Mutex<Loaded> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Group(usize),
+}
+
+/// A checked rule.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    line: usize,
+    pattern: String,
+    regex: Regex,
+    replacement: Vec<Piece>,
+}
+
+/// The parts of `replacement`, checked against the `groups` of the pattern.
+fn replacement(replacement: &str, groups: usize) -> Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            text.push(c);
+            continue;
+        }
+        let group = match chars.next() {
+            Some('$') => {
+                text.push('$');
+                continue;
+            }
+            Some('{') => {
+                let digits: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                digits.parse().context("bad group in `${...}`.")?
+            }
+            Some(c @ '0'..='9') => c as usize - '0' as usize,
+            _ => bail!("`$` without a group, `$$` puts a dollar sign."),
+        };
+        ensure!(group <= groups, "no group {group} in the pattern.");
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(Piece::Group(group));
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// The rule on `line`, None if it's unchecked.
+fn rule(line: &str) -> Result<Option<(String, Regex, Vec<Piece>)>> {
+    let (checked, rule) = if let Some(rule) = line.strip_prefix("[x] ") {
+        (true, rule)
+    } else if let Some(rule) = line.strip_prefix("[ ] ") {
+        (false, rule)
+    } else {
+        bail!("a rule starts with `[x] ` or `[ ] `.");
+    };
+    let (pattern, replacement_text) = rule.split_once(" =>").context("no ` =>` in the rule.")?;
+    if !checked {
+        return Ok(None);
+    }
+    ensure!(!pattern.is_empty(), "the pattern is empty.");
+    let regex = Regex::new(pattern)?;
+    let replacement_text = replacement_text
+        .strip_prefix(' ')
+        .unwrap_or(replacement_text);
+    let pieces = replacement(replacement_text, regex.groups())?;
+    Ok(Some((pattern.to_owned(), regex, pieces)))
+}
+
+/// The checked rules in `text`, and what's wrong with each line that isn't a rule.
+pub fn parse(text: &str) -> (Vec<Rule>, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut problems = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match rule(line) {
+            Ok(Some((pattern, regex, replacement))) => rules.push(Rule {
+                line: i + 1,
+                pattern,
+                regex,
+                replacement,
+            }),
+            Ok(None) => (),
+            Err(e) => problems.push(format!("rule on line {}, `{line}`: {e:#}", i + 1)),
+        }
+    }
+    (rules, problems)
+}
+
+impl Rule {
+    fn apply(&self, text: &str) -> Result<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut replaced = String::with_capacity(text.len());
+        let mut copied = 0;
+        let mut from = 0;
+        while let Some(caps) = self.regex.find(&chars, from)? {
+            let (start, end) = caps[0].unwrap_or_default();
+            replaced.extend(&chars[copied..start]);
+            for piece in &self.replacement {
+                match piece {
+                    Piece::Text(text) => replaced.push_str(text),
+                    Piece::Group(group) => {
+                        if let Some((start, end)) = caps[*group] {
+                            replaced.extend(&chars[start..end]);
+                        }
+                    }
+                }
+            }
+            copied = end;
+            // an empty match moves on a character, keeping it.
+            from = if end > start { end } else { end + 1 };
+            if from > chars.len() {
+                break;
+            }
+        }
+        replaced.extend(&chars[copied.min(chars.len())..]);
+        Ok(replaced)
+    }
+}
+
+/// `text` with `rules` applied in order, and what went wrong with the rules skipped for
+/// taking too long.
+pub fn apply(rules: &[Rule], text: &str) -> (String, Vec<String>) {
+    let mut text = text.to_owned();
+    let mut problems = Vec::new();
+    for rule in rules {
+        match rule.apply(&text) {
+            Ok(replaced) => text = replaced,
+            Err(e) => problems.push(format!(
+                "rule on line {}, `{}`: {e:#}",
+                rule.line, rule.pattern
+            )),
+        }
+    }
+    (text, problems)
+}
+
+/// The lines of `before` and `after`, those only in `before` marked with `-`, those
+/// only in `after` with `+`, and the others indented to line up with them.
+pub fn diff(before: &str, after: &str) -> String {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+    // the length of the longest common subsequence of the lines from i and j on.
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut diff = String::new();
+    let mut line = |mark: &str, text: &str| {
+        diff.push_str(mark);
+        diff.push_str(text);
+        diff.push_str("\r\n");
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            line("  ", before[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if j == after.len() || (i < before.len() && common[i + 1][j] >= common[i][j + 1]) {
+            line("- ", before[i]);
+            i += 1;
+        } else {
+            line("+ ", after[j]);
+            j += 1;
+        }
+    }
+    diff
+}
+
+fn path() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .map(|exe| exe.with_extension("rules"))
+}
+
+fn loaded() -> MutexGuard<'static, Loaded> {
+    LOADED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Reads the rules file, without rules if there is none.
+pub fn read() -> (Vec<Rule>, Vec<String>) {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|text| parse(&text))
+        .unwrap_or_default()
+}
+
+/// The rules, read again when their file changed. What's wrong with them only comes
+/// along when they are, so it's reported once.
+pub fn load() -> (Arc<Vec<Rule>>, Vec<String>) {
+    let modified = path()
+        .and_then(|path| fs::metadata(path).ok())
+        .and_then(|metadata| metadata.modified().ok());
+    let mut loaded = loaded();
+    if let Some((read_at, rules)) = loaded.as_ref() {
+        if *read_at == modified {
+            return (rules.clone(), Vec::new());
+        }
+    }
+    let (rules, problems) = read();
+    let rules = Arc::new(rules);
+    *loaded = Some((modified, rules.clone()));
+    (rules, problems)
+}
+
+/// Where the rules are kept, for messages.
+pub fn file_name() -> String {
+    path()
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "ocr.rules".to_owned())
+}
+
+#[test]
+fn parse_test() {
+    let (rules, problems) = parse(
+        "# comment\r\n\
+         [x] 2X-1OO => ZX-100\r\n\
+         [ ] off => on\r\n\
+         \r\n\
+         [x] (a => b\r\n\
+         [x] (\\d+) yen => $2\r\n\
+         [x] no arrow\r\n\
+         plain => text\r\n\
+         [x] (?i)^draft\\r?\\n =>\r\n",
+    );
+    let patterns: Vec<(usize, &str)> = rules
+        .iter()
+        .map(|rule| (rule.line, rule.pattern.as_str()))
+        .collect();
+    assert_eq!(patterns, [(2, "2X-1OO"), (9, "(?i)^draft\\r?\\n")]);
+    assert_eq!(rules[1].replacement, []);
+    assert_eq!(
+        problems,
+        [
+            "rule on line 5, `[x] (a => b`: unclosed group.",
+            "rule on line 6, `[x] (\\d+) yen => $2`: no group 2 in the pattern.",
+            "rule on line 7, `[x] no arrow`: no ` =>` in the rule.",
+            "rule on line 8, `plain => text`: a rule starts with `[x] ` or `[ ] `.",
+        ]
+    );
+}
+
+#[test]
+fn apply_test() {
+    let (rules, problems) = parse(
+        "[x] 2X-1OO => ZX-100\n\
+         [x] ^(?:DRAFT|CONFIDENTIAL)\\r?\\n =>\n\
+         [x] (\\d+) ?yen => ¥$1\n\
+         [x] (\\w+)@(\\w+) => ${2} at $1, $$0\n\
+         [ ] ZX => XX\n",
+    );
+    assert!(problems.is_empty());
+    let text = "CONFIDENTIAL\r\nOrder 2X-1OO: 500 yen\r\nmail me@host\r\n2X-1OO DRAFT\r\n";
+    let (replaced, problems) = apply(&rules, text);
+    assert!(problems.is_empty());
+    assert_eq!(
+        replaced,
+        "Order ZX-100: ¥500\r\nmail host at me, $0\r\nZX-100 DRAFT\r\n"
+    );
+    assert_eq!(apply(&[], text).0, text);
+
+    // an empty match inserts between the characters.
+    let (rules, _) = parse("[x] \\b => |\n");
+    assert_eq!(apply(&rules, "ab cd").0, "|ab| |cd|");
+}
+
+#[test]
+fn replacement_test() {
+    use Piece::*;
+    assert_eq!(
+        replacement("a$1b${10}$$", 10).unwrap(),
+        [
+            Text("a".to_owned()),
+            Group(1),
+            Text("b".to_owned()),
+            Group(10),
+            Text("$".to_owned())
+        ]
+    );
+    assert!(replacement("$1", 0).is_err());
+    assert!(replacement("$x", 1).is_err());
+    assert!(replacement("${x}", 1).is_err());
+}
+
+#[test]
+fn diff_test() {
+    let shown = diff(
+        "CONFIDENTIAL\r\nOrder 2X-1OO\r\nthanks\r\n",
+        "Order ZX-100\r\nthanks\r\nend\r\n",
+    );
+    assert_eq!(
+        shown,
+        "- CONFIDENTIAL\r\n- Order 2X-1OO\r\n+ Order ZX-100\r\n  thanks\r\n+ end\r\n"
+    );
+    assert_eq!(diff("", ""), "");
+}
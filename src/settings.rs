@@ -0,0 +1,376 @@
+use super::apps::AppList;
+use super::hyphen::Dehyphenate;
+use super::image::Region;
+use super::ocr::{Layout, MixedSpacing};
+use super::oversize::Oversize;
+use super::pipeline::Pipeline;
+use super::preprocess::Invert;
+use anyhow::{bail, Context, Result};
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+static SETTINGS: OnceLock<Mutex<Settings>> = OnceLock::new();
+static OVERRIDES: Mutex<Overrides> = Mutex::new(Overrides {
+    gamma: None,
+    brightness: None,
+});
+
+// Declares the fields of `Settings` once, along with their defaults,
+// and derives the `key=value` parser and serializer from them.
+macro_rules! settings {
+    ($($(#[$meta:meta])* $name:ident: $ty:ty = $default:expr,)*) => {
+        /// User preferences, stored as `key=value` lines in an .ini file next to the executable.
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct Settings {
+            $($(#[$meta])* pub $name: $ty,)*
+        }
+
+        impl Default for Settings {
+            fn default() -> Self {
+                Self {
+                    $($name: $default,)*
+                }
+            }
+        }
+
+        impl Settings {
+            /// Unknown keys and malformed values are ignored, leaving the defaults in place.
+            fn parse(text: &str) -> Self {
+                let mut settings = Self::default();
+                for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+                    $(if key.trim() == stringify!($name) {
+                        read(value.trim(), &mut settings.$name);
+                    })*
+                }
+                settings
+            }
+
+            fn serialize(&self) -> String {
+                let mut text = String::new();
+                $(writeln!(text, "{}={}", stringify!($name), self.$name).ok();)*
+                text
+            }
+        }
+    };
+}
+
+settings! {
+    /// The tag of the recognition language to start with, `auto` for the user profile
+    /// languages. Empty starts with the first of those the engine supports.
+    language: String = String::new(),
+    /// Recognize with each of `detect_languages` and keep the result that reads best,
+    /// instead of using the language picked. Takes as much longer as there are languages.
+    detect_language: bool = false,
+    /// The languages `detect_language` chooses from as comma-separated tags, e.g.
+    /// `ja,en-US`. Empty uses the user profile languages.
+    detect_languages: String = String::new(),
+    /// Try the other installed languages when the one picked recognizes fewer than
+    /// `fallback_min_chars` characters, keeping the one that reads best. Off by default:
+    /// an image without text takes a pass per language, each with its `try_rotations`.
+    fallback_languages: bool = false,
+    fallback_min_chars: usize = 3,
+    /// Append a `[lang:xx-XX]` line to the clipboard copy of each result.
+    stamp_language: bool = false,
+    /// Also copy each result as HTML, keeping its lines and paragraphs.
+    copy_html: bool = false,
+    /// Also copy each result as RTF, in a font for the recognition language.
+    copy_rtf: bool = false,
+    /// Put the copied image back on the clipboard along with the text.
+    keep_image: bool = false,
+    /// Copy text with bare LF line endings instead of CRLF. The view keeps CRLF.
+    lf_line_endings: bool = false,
+    /// Trim the spaces ending each line of the text.
+    trim_trailing_spaces: bool = false,
+    /// Collapse runs of blank lines to one, and leave out those starting or ending the
+    /// text.
+    collapse_blank_lines: bool = false,
+    /// Append each result to the ones copied before instead of replacing them.
+    accumulate: bool = false,
+    /// Keep copied results out of the clipboard history (Win+V) and its cloud sync.
+    exclude_from_history: bool = false,
+    /// Scan the image already on the clipboard at startup.
+    scan_at_startup: bool = true,
+    /// Ignore clipboard updates.
+    paused: bool = false,
+    /// Only scan what these apps copy, e.g. `mspaint.exe,SnippingTool.exe`. Empty allows
+    /// every app.
+    allowed_apps: AppList = AppList::new(),
+    /// Never scan what these apps copy, even if allowed.
+    denied_apps: AppList = AppList::new(),
+    /// Recognize the image file whose path is copied as text.
+    scan_paths: bool = false,
+    /// Recognize images copied as a base64 `data:image/...` URI.
+    scan_data_uris: bool = false,
+    /// Milliseconds the clipboard has to stay unchanged before it's read, 0 reads it at
+    /// once.
+    debounce_ms: u32 = 150,
+    /// Seconds to wait for the engine to recognize an image before giving up on it, 0 to
+    /// wait as long as it takes.
+    recognition_timeout_secs: u32 = 60,
+    /// Recognize with Tesseract instead of the engine of Windows, in builds with the
+    /// `tesseract` feature.
+    tesseract: bool = false,
+    /// The `tessdata` folder with the Tesseract language data. Empty uses the
+    /// `TESSDATA_PREFIX` environment variable.
+    tesseract_data: String = String::new(),
+    /// What to do with images past the engine's size limit: `downscale` to fit, `tile`
+    /// into bands recognized one by one, or `reject`.
+    oversize: Oversize = Oversize::Downscale,
+    /// Crop every copied image to `crop_region`, the last one picked with Crop then OCR.
+    remember_crop: bool = false,
+    crop_region: Region = Region::default(),
+    /// Try the image turned by quarters when it reads too little, e.g. a sideways scan.
+    /// Up to four times slower on images without any text.
+    try_rotations: bool = true,
+    /// Reading fewer letters than this is too little for `try_rotations`.
+    rotation_min_letters: usize = 1,
+    /// Keep the spaces between all recognized words as the engine put them. Otherwise
+    /// words are spaced by their scripts, Latin ones spaced and Japanese ones joined.
+    keep_word_spaces: bool = false,
+    /// What goes between CJK and Latin words: `none`, `space` or a `thin` space.
+    mixed_spacing: MixedSpacing = MixedSpacing::Space,
+    /// Join words hyphenated across line breaks: `never`, `always` dropping the hyphen,
+    /// or `smart` keeping it in what looks like a compound, e.g. `e-mail`.
+    dehyphenate: Dehyphenate = Dehyphenate::Never,
+    /// `lines` for a line per recognized line, `paragraphs` to join the lines of a
+    /// paragraph and separate paragraphs with a blank line, or `table` for a line of
+    /// tab-separated cells per table row, to paste into a spreadsheet.
+    layout: Layout = Layout::Lines,
+    /// Read a multi-column layout column by column, instead of the lines across the
+    /// columns as the engine orders them. Without a clear gutter between columns, the
+    /// order stays as it is.
+    detect_columns: bool = false,
+    /// Put back the spaces lines start with, from how far right they start, e.g. for
+    /// screenshots of code. Only with the `lines` layout.
+    preserve_indentation: bool = false,
+    /// Indent nested list items, and the wrapped lines of an item to line up with its
+    /// text. Only with the `lines` layout, without `preserve_indentation`.
+    indent_lists: bool = false,
+    /// Leave out furigana, the small kana readings over Japanese text that come out as
+    /// lines of their own.
+    drop_furigana: bool = false,
+    /// Make full-width Latin letters, digits and punctuation half-width, and half-width
+    /// katakana full-width, as Unicode NFKC does.
+    fold_width: bool = false,
+    /// Also take apart compatibility characters as NFKC does, e.g. `㌔` to `キロ`, `①` to
+    /// `1` and `ﬁ` to `fi`.
+    fold_compatibility: bool = false,
+    /// Cut the recognized text to this many characters, 0 for no limit.
+    max_chars: usize = 0,
+    /// Surround the image with a margin of its background color before recognition, for
+    /// crops so tight that the text touches the edges.
+    pad_margin: bool = false,
+    /// The order of the preprocessing steps, comma-separated: `adjust` for gamma and
+    /// brightness, `normalize`, `denoise`, `sharpen`, `invert` and `binarize`. Steps left
+    /// out never run, those listed when enabled.
+    pipeline: Pipeline = Pipeline::default(),
+    /// Stretch the contrast of the image over the full range before recognition.
+    normalize: bool = false,
+    /// The percentage of the darkest and of the lightest pixels ignored when stretching.
+    normalize_clip: f32 = 1.0,
+    /// Take specks and moiré out of the image with a median filter before recognition.
+    denoise: bool = false,
+    /// Sharpen the image with an unsharp mask before recognition.
+    sharpen: bool = false,
+    /// Run `denoise` and `sharpen` on large images too, which takes a while.
+    force_cleanup: bool = false,
+    /// Invert the image before recognition: `always`, `never`, or `auto` when its
+    /// background is dark, as in terminals and dark-mode editors.
+    invert: Invert = Invert::Auto,
+    /// Turn the image black and white at its Otsu threshold before recognition.
+    binarize: bool = false,
+    /// Gamma applied to the image before recognition, 1 leaves it unchanged.
+    gamma: f32 = 1.0,
+    /// Added to every color channel before recognition, in 0-255 units.
+    brightness: i32 = 0,
+}
+
+/// Settings given on the command line, in effect for this session on top of the saved
+/// ones but never saved themselves.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Overrides {
+    gamma: Option<f32>,
+    brightness: Option<i32>,
+}
+
+impl Overrides {
+    fn apply(&self, settings: &mut Settings) {
+        if let Some(gamma) = self.gamma {
+            settings.gamma = gamma;
+        }
+        if let Some(brightness) = self.brightness {
+            settings.brightness = brightness;
+        }
+    }
+
+    /// Drops the overrides of what changed from `before` to `after`, so a setting
+    /// changed in the session takes effect.
+    fn forget_changed(&mut self, before: &Settings, after: &Settings) {
+        if before.gamma != after.gamma {
+            self.gamma = None;
+        }
+        if before.brightness != after.brightness {
+            self.brightness = None;
+        }
+    }
+}
+
+fn read<T: FromStr>(value: &str, field: &mut T) {
+    if let Ok(value) = value.parse() {
+        *field = value;
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .map(|exe| exe.with_extension("ini"))
+}
+
+fn lock() -> MutexGuard<'static, Settings> {
+    SETTINGS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn overrides() -> MutexGuard<'static, Overrides> {
+    OVERRIDES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Loads the settings file, falling back to the defaults if there is none.
+pub fn load() {
+    let settings = path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|text| Settings::parse(&text))
+        .unwrap_or_default();
+    *lock() = settings;
+}
+
+/// Overrides settings for this session from command line arguments,
+/// e.g. `--gamma 1.4 --brightness +10`. They aren't saved.
+pub fn apply_args(args: impl Iterator<Item = String>) -> Result<()> {
+    parse_args(&mut overrides(), args)
+}
+
+fn parse_args(overrides: &mut Overrides, mut args: impl Iterator<Item = String>) -> Result<()> {
+    fn value<T: FromStr>(name: &str, value: Option<String>) -> Result<T> {
+        value
+            .and_then(|v| v.parse().ok())
+            .with_context(|| format!("{name} needs a numeric value."))
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gamma" => overrides.gamma = Some(value(&arg, args.next())?),
+            "--brightness" => overrides.brightness = Some(value(&arg, args.next())?),
+            _ => bail!("unknown argument: {arg}"),
+        }
+    }
+    Ok(())
+}
+
+/// Returns a snapshot of the current settings, with the command line overrides.
+pub fn get() -> Settings {
+    let mut settings = lock().clone();
+    overrides().apply(&mut settings);
+    settings
+}
+
+/// Changes the saved settings with `f`, dropping the overrides of those it changes.
+fn change<T>(
+    settings: &mut Settings,
+    overrides: &mut Overrides,
+    f: impl FnOnce(&mut Settings) -> T,
+) -> T {
+    let before = settings.clone();
+    let result = f(settings);
+    overrides.forget_changed(&before, settings);
+    result
+}
+
+/// Changes the settings and writes them to disk, without the command line overrides.
+pub fn update<T>(f: impl FnOnce(&mut Settings) -> T) -> T {
+    let mut settings = lock();
+    let result = change(&mut settings, &mut overrides(), f);
+    if let Some(path) = path() {
+        if let Err(e) = fs::write(&path, settings.serialize()) {
+            log!("failed to save {}: {e}", path.display());
+        }
+    }
+    result
+}
+
+#[test]
+fn parse_test() {
+    assert_eq!(Settings::parse(""), Settings::default());
+
+    let settings = Settings::parse("stamp_language = true\nunknown=1\n");
+    assert!(settings.stamp_language);
+
+    // malformed values keep the default.
+    let settings = Settings::parse("stamp_language=yes");
+    assert_eq!(settings, Settings::default());
+
+    let settings = Settings::parse("denied_apps = KeePass.exe, draw.io.exe\n");
+    assert!(settings.denied_apps.contains("keepass.exe"));
+    assert!(settings.allowed_apps.is_empty());
+
+    let settings = Settings::parse("language=auto\n");
+    assert_eq!(settings.language, "auto");
+
+    let settings = Settings {
+        stamp_language: true,
+        language: "ja".to_owned(),
+        denied_apps: "a.exe,b.exe".parse().unwrap(),
+        ..Default::default()
+    };
+    assert_eq!(Settings::parse(&settings.serialize()), settings);
+}
+
+#[test]
+fn parse_args_test() {
+    let args = |s: &str| s.split_whitespace().map(str::to_owned).collect::<Vec<_>>();
+
+    let mut overrides = Overrides::default();
+    parse_args(
+        &mut overrides,
+        args("--gamma 1.4 --brightness +10").into_iter(),
+    )
+    .unwrap();
+    let mut settings = Settings::default();
+    overrides.apply(&mut settings);
+    assert_eq!(settings.gamma, 1.4);
+    assert_eq!(settings.brightness, 10);
+
+    let mut overrides = Overrides::default();
+    assert!(parse_args(&mut overrides, args("--gamma").into_iter()).is_err());
+    assert!(parse_args(&mut overrides, args("--brightness bright").into_iter()).is_err());
+    assert!(parse_args(&mut overrides, args("--verbose").into_iter()).is_err());
+}
+
+#[test]
+fn overrides_test() {
+    let mut settings = Settings::default();
+    let mut overrides = Overrides::default();
+    parse_args(
+        &mut overrides,
+        ["--gamma", "1.4"].map(str::to_owned).into_iter(),
+    )
+    .unwrap();
+
+    // toggling another setting saves the gamma as it was, not the override.
+    change(&mut settings, &mut overrides, |s| s.stamp_language = true);
+    assert_eq!(Settings::parse(&settings.serialize()).gamma, 1.0);
+    let mut current = settings.clone();
+    overrides.apply(&mut current);
+    assert_eq!(current.gamma, 1.4);
+
+    // changing the gamma itself saves it and ends the override.
+    change(&mut settings, &mut overrides, |s| s.gamma = 2.0);
+    assert_eq!(Settings::parse(&settings.serialize()).gamma, 2.0);
+    assert_eq!(overrides, Overrides::default());
+}
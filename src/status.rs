@@ -0,0 +1,127 @@
+use super::Hwnd;
+use anyhow::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Foundation::{HWND, LPARAM, RECT, WPARAM},
+        UI::{
+            Controls::{SB_SETPARTS, SB_SETTEXTW, STATUSCLASSNAMEW},
+            WindowsAndMessaging::{
+                CreateWindowExW, GetClientRect, GetWindowRect, SendMessageW, WINDOW_EX_STYLE,
+                WS_CHILD, WS_VISIBLE,
+            },
+        },
+    },
+};
+
+const STATS_PART_WIDTH: i32 = 180;
+
+static HWND_STATUS: OnceLock<Hwnd> = OnceLock::new();
+
+pub static STATS: Stats = Stats::new();
+
+/// Counters shown in the right part of the status bar.
+pub struct Stats {
+    scanned: AtomicU32,
+    skipped: AtomicU32,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Self {
+            scanned: AtomicU32::new(0),
+            skipped: AtomicU32::new(0),
+        }
+    }
+
+    pub fn add_scanned(&self) {
+        self.scanned.fetch_add(1, Ordering::Relaxed);
+        set_part(1, &self.to_string());
+    }
+
+    pub fn add_skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        set_part(1, &self.to_string());
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "scanned: {}, skipped: {}",
+            self.scanned.load(Ordering::Relaxed),
+            self.skipped.load(Ordering::Relaxed)
+        )
+    }
+}
+
+pub fn create(parent: HWND) -> Result<()> {
+    let hwnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            STATUSCLASSNAMEW,
+            None,
+            WS_CHILD | WS_VISIBLE,
+            0,
+            0,
+            0,
+            0,
+            parent,
+            None,
+            None,
+            None,
+        )?
+    };
+
+    let mut rc = RECT::default();
+    unsafe { GetClientRect(parent, &mut rc)? };
+    let parts = [rc.right - STATS_PART_WIDTH, -1];
+    unsafe {
+        SendMessageW(
+            hwnd,
+            SB_SETPARTS,
+            WPARAM(parts.len()),
+            LPARAM(parts.as_ptr() as isize),
+        )
+    };
+
+    HWND_STATUS.get_or_init(|| Hwnd::new(hwnd));
+    set_part(1, &STATS.to_string());
+    Ok(())
+}
+
+/// Height of the status bar, or 0 if it has not been created.
+pub fn height() -> i32 {
+    let Some(hwnd) = HWND_STATUS.get() else {
+        return 0;
+    };
+    let mut rc = RECT::default();
+    match unsafe { GetWindowRect(hwnd.handle(), &mut rc) } {
+        Ok(_) => rc.bottom - rc.top,
+        Err(_) => 0,
+    }
+}
+
+/// Shows a message in the left part of the status bar.
+pub fn set(msg: &str) {
+    set_part(0, msg);
+}
+
+fn set_part(index: usize, text: &str) {
+    let Some(hwnd) = HWND_STATUS.get() else {
+        return;
+    };
+    let text = HSTRING::from(text);
+    unsafe {
+        SendMessageW(
+            hwnd.handle(),
+            SB_SETTEXTW,
+            WPARAM(index),
+            LPARAM(text.as_ptr() as isize),
+        )
+    };
+}
@@ -0,0 +1,172 @@
+//! Tables from the boxes of recognized words, for the `table` layout. Words overlapping
+//! vertically make a row, words close together within a row a cell, and the gutters
+//! between the cells of all rows the columns. Each row comes out as a line of fields
+//! separated by tabs, what spreadsheets take when pasted.
+
+use super::ocr::Word;
+use super::paragraph;
+
+// words this many of their heights apart are in different cells.
+const CELL_GAP: f32 = 1.0;
+// at most one row in this many may have a cell across a gutter, e.g. a title.
+const SPANNING_SHARE: usize = 4;
+
+/// Words next to each other in a row, and the span they cover.
+#[derive(Debug)]
+struct Cell<'a> {
+    left: f32,
+    right: f32,
+    words: Vec<&'a str>,
+}
+
+/// `words` in rows top to bottom, each left to right.
+fn rows<'a>(words: &[&'a Word]) -> Vec<Vec<&'a Word>> {
+    let mut words = words.to_vec();
+    words.sort_by(|a, b| (a.y + a.height / 2.0).total_cmp(&(b.y + b.height / 2.0)));
+    let mut rows: Vec<(f32, f32, Vec<&Word>)> = Vec::new();
+    for word in words {
+        let center = word.y + word.height / 2.0;
+        match rows.last_mut() {
+            Some((top, bottom, row)) if center <= *bottom => {
+                *top = top.min(word.y);
+                *bottom = bottom.max(word.y + word.height);
+                row.push(word);
+            }
+            _ => rows.push((word.y, word.y + word.height, vec![word])),
+        }
+    }
+    rows.into_iter()
+        .map(|(_, _, mut row)| {
+            row.sort_by(|a, b| a.x.total_cmp(&b.x));
+            row
+        })
+        .collect()
+}
+
+/// The words of `row` in cells, left to right.
+fn cells<'a>(row: &[&'a Word]) -> Vec<Cell<'a>> {
+    let mut cells: Vec<Cell> = Vec::new();
+    for word in row {
+        match cells.last_mut() {
+            Some(cell) if word.x - cell.right < word.height * CELL_GAP => {
+                cell.right = cell.right.max(word.x + word.width);
+                cell.words.push(&word.text);
+            }
+            _ => cells.push(Cell {
+                left: word.x,
+                right: word.x + word.width,
+                words: vec![&word.text],
+            }),
+        }
+    }
+    cells
+}
+
+/// The middles of the gutters between the columns of `rows`, left to right.
+fn gutters(rows: &[Vec<Cell>]) -> Vec<f32> {
+    let spanning = rows.len() / SPANNING_SHARE;
+    let spans: Vec<(f32, f32)> = rows
+        .iter()
+        .flatten()
+        .map(|cell| (cell.left, cell.right))
+        .collect();
+    paragraph::open_runs(&spans, spanning)
+        .into_iter()
+        .map(|(left, right)| (left + right) / 2.0)
+        .collect()
+}
+
+/// `words` as tab-separated values, a CRLF-terminated line per row with a field per
+/// column. A cell across columns goes in the first of them, missing cells are empty
+/// fields. `join` puts the words of a cell together.
+pub fn tsv(words: &[&Word], join: impl Fn(&[&str]) -> String) -> String {
+    let rows: Vec<Vec<Cell>> = rows(words).iter().map(|row| cells(row)).collect();
+    let gutters = gutters(&rows);
+    let mut text = String::new();
+    for row in &rows {
+        let mut fields = vec![Vec::new(); gutters.len() + 1];
+        for cell in row {
+            let column = gutters.iter().filter(|&&g| g <= cell.left).count();
+            // two cells in a column without a gutter between them share the field.
+            fields[column].extend(&cell.words);
+        }
+        let fields: Vec<String> = fields.iter().map(|words| join(words)).collect();
+        text.push_str(&fields.join("\t"));
+        text.push_str("\r\n");
+    }
+    text
+}
+
+#[cfg(test)]
+fn table(words: &[(f32, f32, f32, &str)]) -> String {
+    // words 10 high, by left, top, right and text.
+    let words: Vec<Word> = words
+        .iter()
+        .map(|&(left, top, right, text)| Word {
+            text: text.to_owned(),
+            x: left,
+            y: top,
+            width: right - left,
+            height: 10.0,
+        })
+        .collect();
+    let words: Vec<&Word> = words.iter().collect();
+    tsv(&words, |words| words.join(" "))
+}
+
+#[test]
+fn table_test() {
+    // an invoice, the words of a row as the engine may return them, out of order.
+    let text = table(&[
+        (0.0, 0.0, 40.0, "Item"),
+        (100.0, 0.0, 130.0, "Qty"),
+        (160.0, 0.0, 200.0, "Price"),
+        (0.0, 20.0, 30.0, "Red"),
+        (34.0, 21.0, 60.0, "pen"),
+        (120.0, 19.0, 130.0, "2"),
+        (170.0, 20.0, 200.0, "1.50"),
+        (0.0, 40.0, 50.0, "Paper"),
+        (110.0, 40.0, 130.0, "10"),
+        (160.0, 40.0, 200.0, "12.00"),
+    ]);
+    assert_eq!(
+        text,
+        "Item\tQty\tPrice\r\nRed pen\t2\t1.50\r\nPaper\t10\t12.00\r\n"
+    );
+    assert_eq!(table(&[]), "");
+    assert_eq!(table(&[(0.0, 0.0, 40.0, "alone")]), "alone\r\n");
+}
+
+#[test]
+fn missing_cells_test() {
+    // a missing quantity and a missing name leave empty fields in their columns.
+    let text = table(&[
+        (0.0, 0.0, 40.0, "Item"),
+        (100.0, 0.0, 130.0, "Qty"),
+        (160.0, 0.0, 200.0, "Price"),
+        (0.0, 20.0, 30.0, "Pen"),
+        (170.0, 20.0, 200.0, "1.50"),
+        (120.0, 40.0, 130.0, "3"),
+        (160.0, 40.0, 200.0, "4.50"),
+    ]);
+    assert_eq!(text, "Item\tQty\tPrice\r\nPen\t\t1.50\r\n\t3\t4.50\r\n");
+}
+
+#[test]
+fn spanning_cells_test() {
+    // a title across the columns stays in the first field and shifts no other row.
+    let text = table(&[
+        (0.0, 0.0, 60.0, "Monthly"),
+        (65.0, 0.0, 120.0, "report"),
+        (0.0, 20.0, 40.0, "CPU"),
+        (100.0, 20.0, 130.0, "45%"),
+        (0.0, 40.0, 40.0, "RAM"),
+        (100.0, 40.0, 130.0, "60%"),
+        (0.0, 60.0, 40.0, "Disk"),
+        (100.0, 60.0, 130.0, "80%"),
+    ]);
+    assert_eq!(
+        text,
+        "Monthly report\t\r\nCPU\t45%\r\nRAM\t60%\r\nDisk\t80%\r\n"
+    );
+}
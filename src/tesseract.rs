@@ -0,0 +1,315 @@
+//! Recognition with Tesseract through its C API, in builds with the `tesseract` feature.
+//! Needs the Tesseract library to link against, and the language data of each language
+//! to recognize in the `tessdata` folder, e.g. `eng.traineddata`.
+
+use super::ocr::{Line, OcrOutcome, Recognizer, Word};
+use anyhow::{ensure, Context, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fs;
+use std::ptr;
+
+// Tesseract takes images up to this many pixels on either side.
+const MAX_DIMENSION: u32 = 32767;
+
+// the levels of `TessPageIteratorLevel`.
+const RIL_TEXTLINE: c_int = 2;
+const RIL_WORD: c_int = 3;
+
+#[repr(C)]
+struct TessBaseAPI {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct TessResultIterator {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct TessPageIterator {
+    _private: [u8; 0],
+}
+
+#[link(name = "tesseract")]
+extern "C" {
+    fn TessBaseAPICreate() -> *mut TessBaseAPI;
+    fn TessBaseAPIDelete(api: *mut TessBaseAPI);
+    fn TessBaseAPIInit3(
+        api: *mut TessBaseAPI,
+        datapath: *const c_char,
+        language: *const c_char,
+    ) -> c_int;
+    fn TessBaseAPIEnd(api: *mut TessBaseAPI);
+    fn TessBaseAPISetImage(
+        api: *mut TessBaseAPI,
+        imagedata: *const u8,
+        width: c_int,
+        height: c_int,
+        bytes_per_pixel: c_int,
+        bytes_per_line: c_int,
+    );
+    fn TessBaseAPIRecognize(api: *mut TessBaseAPI, monitor: *mut c_void) -> c_int;
+    fn TessBaseAPIGetIterator(api: *mut TessBaseAPI) -> *mut TessResultIterator;
+    fn TessResultIteratorDelete(it: *mut TessResultIterator);
+    fn TessResultIteratorNext(it: *mut TessResultIterator, level: c_int) -> c_int;
+    fn TessResultIteratorGetUTF8Text(it: *const TessResultIterator, level: c_int) -> *mut c_char;
+    fn TessResultIteratorGetPageIteratorConst(
+        it: *const TessResultIterator,
+    ) -> *const TessPageIterator;
+    fn TessPageIteratorIsAtBeginningOf(it: *const TessPageIterator, level: c_int) -> c_int;
+    fn TessPageIteratorBoundingBox(
+        it: *const TessPageIterator,
+        level: c_int,
+        left: *mut c_int,
+        top: *mut c_int,
+        right: *mut c_int,
+        bottom: *mut c_int,
+    ) -> c_int;
+    fn TessDeleteText(text: *const c_char);
+}
+
+/// An initialized `TessBaseAPI`, ended and deleted when dropped.
+struct Api(*mut TessBaseAPI);
+
+impl Drop for Api {
+    fn drop(&mut self) {
+        unsafe {
+            TessBaseAPIEnd(self.0);
+            TessBaseAPIDelete(self.0);
+        }
+    }
+}
+
+// language tags and the Tesseract languages for them. Most other three letter codes are
+// the same in both.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("en", "eng"),
+    ("ja", "jpn"),
+    ("zh-Hans", "chi_sim"),
+    ("zh-Hant", "chi_tra"),
+    ("ko", "kor"),
+    ("de", "deu"),
+    ("fr", "fra"),
+    ("es", "spa"),
+    ("it", "ita"),
+    ("pt", "por"),
+    ("nl", "nld"),
+    ("ru", "rus"),
+    ("uk", "ukr"),
+    ("pl", "pol"),
+    ("cs", "ces"),
+    ("sv", "swe"),
+    ("da", "dan"),
+    ("nb", "nor"),
+    ("fi", "fin"),
+    ("el", "ell"),
+    ("tr", "tur"),
+    ("ar", "ara"),
+    ("he", "heb"),
+];
+
+/// The Tesseract language for the language tag `tag`, e.g. `jpn` for `ja-JP`. English
+/// for an empty tag, as Tesseract has no user profile languages.
+fn tesseract_language(tag: &str) -> String {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or_default().to_ascii_lowercase();
+    let traditional = subtags
+        .any(|subtag| ["hant", "tw", "hk", "mo"].contains(&subtag.to_ascii_lowercase().as_str()));
+    let tag = match primary.as_str() {
+        "" => "en",
+        "no" => "nb",
+        "zh" if traditional => "zh-Hant",
+        "zh" => "zh-Hans",
+        other => other,
+    };
+    LANGUAGES
+        .iter()
+        .find(|&&(known, _)| known == tag)
+        .map_or(tag, |&(_, language)| language)
+        .to_owned()
+}
+
+/// The language tag for the Tesseract language `language`, e.g. `ja` for `jpn`.
+fn language_tag(language: &str) -> String {
+    LANGUAGES
+        .iter()
+        .find(|&&(_, known)| known == language)
+        .map_or(language, |&(tag, _)| tag)
+        .to_owned()
+}
+
+/// Tesseract, with the language data in `data`, empty for `TESSDATA_PREFIX`.
+pub struct Tesseract {
+    data: String,
+}
+
+impl Tesseract {
+    pub fn new(data: &str) -> Self {
+        Self {
+            data: data.to_owned(),
+        }
+    }
+
+    /// Calls `f` with the API for `language`, initialized once per thread.
+    fn with_api<T>(&self, language: &str, f: impl FnOnce(&Api) -> Result<T>) -> Result<T> {
+        thread_local! {
+            // by tessdata folder and language.
+            static APIS: RefCell<HashMap<(String, String), Api>> = RefCell::default();
+        }
+
+        let key = (self.data.clone(), language.to_owned());
+        APIS.with_borrow_mut(|apis| {
+            if !apis.contains_key(&key) {
+                let data = CString::new(self.data.as_str()).context("invalid tessdata path.")?;
+                let lang = CString::new(language).context("invalid language.")?;
+                let api = Api(unsafe { TessBaseAPICreate() });
+                let data = if self.data.is_empty() {
+                    ptr::null()
+                } else {
+                    data.as_ptr()
+                };
+                let failed = unsafe { TessBaseAPIInit3(api.0, data, lang.as_ptr()) };
+                ensure!(
+                    failed == 0,
+                    "failed to start Tesseract for {language}, is its language data installed?"
+                );
+                apis.insert(key.clone(), api);
+            }
+            f(&apis[&key])
+        })
+    }
+}
+
+impl Recognizer for Tesseract {
+    fn recognize(
+        &self,
+        width: i32,
+        height: i32,
+        pixels: &mut dyn FnMut(&mut [u8]),
+        lang: &str,
+    ) -> Result<OcrOutcome> {
+        let language = tesseract_language(lang);
+        // Tesseract reads 4 bytes per pixel as RGBA.
+        let len = (width.unsigned_abs() as usize)
+            .checked_mul(height.unsigned_abs() as usize * 4)
+            .context("image is too large.")?;
+        let mut rgba = vec![0; len];
+        pixels(&mut rgba);
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        let lines = self.with_api(&language, |api| {
+            unsafe {
+                TessBaseAPISetImage(api.0, rgba.as_ptr(), width, height, 4, width * 4);
+                ensure!(
+                    TessBaseAPIRecognize(api.0, ptr::null_mut()) == 0,
+                    "Tesseract failed to recognize the image."
+                );
+            }
+            Ok(unsafe { read_lines(api) })
+        })?;
+        Ok(OcrOutcome {
+            lang: if lang.is_empty() { "en" } else { lang }.to_owned(),
+            lines,
+            angle: None,
+        })
+    }
+
+    fn languages(&self) -> Result<Vec<String>> {
+        let data = if self.data.is_empty() {
+            env::var("TESSDATA_PREFIX")
+                .context("no tessdata folder set to list the Tesseract languages of.")?
+        } else {
+            self.data.clone()
+        };
+        let mut tags: Vec<String> = fs::read_dir(&data)
+            .with_context(|| format!("failed to list the Tesseract languages in {data}."))?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let language = path.file_stem()?.to_str()?;
+                // `osd` finds how the page is turned, it recognizes no language.
+                let data = path.extension()? == "traineddata" && language != "osd";
+                data.then(|| language_tag(language))
+            })
+            .collect();
+        tags.sort();
+        Ok(tags)
+    }
+
+    fn max_image_dimension(&self) -> Result<u32> {
+        Ok(MAX_DIMENSION)
+    }
+}
+
+/// The lines of words `api` recognized last.
+unsafe fn read_lines(api: &Api) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    let it = TessBaseAPIGetIterator(api.0);
+    if it.is_null() {
+        return lines;
+    }
+    let page = TessResultIteratorGetPageIteratorConst(it);
+    loop {
+        let text = TessResultIteratorGetUTF8Text(it, RIL_WORD);
+        let (mut left, mut top, mut right, mut bottom) = (0, 0, 0, 0);
+        let boxed = TessPageIteratorBoundingBox(
+            page,
+            RIL_WORD,
+            &mut left,
+            &mut top,
+            &mut right,
+            &mut bottom,
+        );
+        if !text.is_null() {
+            let word = CStr::from_ptr(text).to_string_lossy().trim().to_owned();
+            TessDeleteText(text);
+            if boxed != 0 && !word.is_empty() {
+                if lines.is_empty() || TessPageIteratorIsAtBeginningOf(page, RIL_TEXTLINE) != 0 {
+                    lines.push(Line {
+                        text: String::new(),
+                        words: Vec::new(),
+                    });
+                }
+                let line = lines.last_mut().unwrap();
+                if !line.text.is_empty() {
+                    line.text.push(' ');
+                }
+                line.text.push_str(&word);
+                line.words.push(Word {
+                    text: word,
+                    x: left as f32,
+                    y: top as f32,
+                    width: (right - left) as f32,
+                    height: (bottom - top) as f32,
+                });
+            }
+        }
+        if TessResultIteratorNext(it, RIL_WORD) == 0 {
+            break;
+        }
+    }
+    TessResultIteratorDelete(it);
+    lines
+}
+
+#[test]
+fn tesseract_language_test() {
+    assert_eq!(tesseract_language(""), "eng");
+    assert_eq!(tesseract_language("en-US"), "eng");
+    assert_eq!(tesseract_language("ja"), "jpn");
+    assert_eq!(tesseract_language("zh-Hans-CN"), "chi_sim");
+    assert_eq!(tesseract_language("zh-Hant-TW"), "chi_tra");
+    assert_eq!(tesseract_language("zh-TW"), "chi_tra");
+    assert_eq!(tesseract_language("DE-de"), "deu");
+    assert_eq!(tesseract_language("vie"), "vie");
+    assert_eq!(tesseract_language("no"), "nor");
+
+    for (tag, language) in [("ja", "jpn"), ("zh-Hant", "chi_tra"), ("nb", "nor")] {
+        assert_eq!(language_tag(language), tag);
+        assert_eq!(tesseract_language(&language_tag(language)), language);
+    }
+    assert_eq!(language_tag("vie"), "vie");
+}
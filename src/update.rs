@@ -0,0 +1,135 @@
+/// Classifies clipboard updates by the clipboard sequence number and owner. Apps often
+/// notify several times for a single copy, and our own writes notify too; only an update
+/// with a sequence number not seen before, from another app, is worth a scan.
+#[derive(Debug, Default)]
+pub struct UpdateFilter {
+    // the sequence number of the last update that has been handled.
+    last: u32,
+    // the sequence number left by our last write, while it's still current.
+    own: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Update {
+    /// The clipboard has changed since the last update.
+    New,
+    /// Another notification of the last update.
+    Repeated,
+    /// The result of our own write.
+    Own,
+}
+
+impl UpdateFilter {
+    pub const fn new() -> Self {
+        Self { last: 0, own: None }
+    }
+
+    /// Called on every notification with the current sequence number, and whether our
+    /// window owns the clipboard.
+    pub fn update(&mut self, sequence: u32, owned: bool) -> Update {
+        if owned || self.own == Some(sequence) {
+            self.last = sequence;
+            return Update::Own;
+        }
+        if self.last == sequence {
+            return Update::Repeated;
+        }
+        self.last = sequence;
+        self.own = None;
+        Update::New
+    }
+
+    /// Records the sequence number after we have written the clipboard, in case it was
+    /// written without an owner.
+    pub fn written(&mut self, sequence: u32) {
+        self.own = Some(sequence);
+    }
+
+    /// Marks `sequence` as handled without a scan, such as what's on the clipboard at
+    /// startup.
+    pub fn skip(&mut self, sequence: u32) {
+        self.last = sequence;
+    }
+}
+
+/// Holds back clipboard updates that arrive while one is being handled. Only the fact
+/// that one came is kept: the clipboard is read afresh once the current one is done, so
+/// a burst of them is handled once, with the newest contents.
+#[derive(Debug, Default)]
+pub struct Deferral {
+    busy: bool,
+    deferred: bool,
+}
+
+impl Deferral {
+    pub const fn new() -> Self {
+        Self {
+            busy: false,
+            deferred: false,
+        }
+    }
+
+    /// Called before handling an update. Returns false if another one is still being
+    /// handled, deferring this one.
+    pub fn begin(&mut self) -> bool {
+        if self.busy {
+            self.deferred = true;
+            return false;
+        }
+        self.busy = true;
+        true
+    }
+
+    /// Called once the update has been handled. Returns true if another one came in the
+    /// meantime and the clipboard should be checked again.
+    pub fn end(&mut self) -> bool {
+        self.busy = false;
+        std::mem::take(&mut self.deferred)
+    }
+}
+
+#[test]
+fn update_test() {
+    let mut filter = UpdateFilter::new();
+    filter.skip(1);
+    assert_eq!(filter.update(1, false), Update::Repeated);
+
+    // a single copy notified several times is handled once.
+    assert_eq!(filter.update(2, false), Update::New);
+    assert_eq!(filter.update(2, false), Update::Repeated);
+    assert_eq!(filter.update(2, false), Update::Repeated);
+    assert_eq!(filter.update(4, false), Update::New);
+}
+
+#[test]
+fn update_own_test() {
+    let mut filter = UpdateFilter::new();
+    assert_eq!(filter.update(2, false), Update::New);
+
+    filter.written(3);
+    assert_eq!(filter.update(3, false), Update::Own);
+    assert_eq!(filter.update(3, false), Update::Own);
+
+    // whatever comes after our write is new again.
+    assert_eq!(filter.update(4, false), Update::New);
+
+    // owned by our window, whatever the sequence.
+    assert_eq!(filter.update(5, true), Update::Own);
+    assert_eq!(filter.update(5, true), Update::Own);
+    assert_eq!(filter.update(6, false), Update::New);
+}
+
+#[test]
+fn deferral_test() {
+    let mut deferral = Deferral::new();
+    assert!(deferral.begin());
+    assert!(!deferral.end());
+
+    // updates arriving during a scan come back as a single re-check.
+    assert!(deferral.begin());
+    assert!(!deferral.begin());
+    assert!(!deferral.begin());
+    assert!(deferral.end());
+    assert!(deferral.begin());
+    assert!(!deferral.end());
+}
@@ -0,0 +1,230 @@
+use super::{status, Hwnd};
+use anyhow::{Context, Result};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use windows::{
+    core::{h, w, HSTRING, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, RECT, WPARAM},
+        System::LibraryLoader::{GetModuleHandleW, LoadLibraryW},
+        UI::{
+            Controls::{
+                RichEdit::{
+                    EM_GETEVENTMASK, EM_GETSELTEXT, EM_GETTEXTLENGTHEX, EM_SETEVENTMASK,
+                    ENM_MOUSEEVENTS, GETTEXTLENGTHEX, GTL_DEFAULT, MSFTEDIT_CLASS,
+                },
+                EM_GETSEL, EM_REPLACESEL, EM_SETLIMITTEXT, EM_SETSEL, WC_EDITW,
+            },
+            WindowsAndMessaging::{
+                CreateWindowExW, GetClientRect, GetWindowTextLengthW, GetWindowTextW, MessageBoxW,
+                SendMessageW, ES_AUTOHSCROLL, ES_AUTOVSCROLL, ES_MULTILINE, ES_WANTRETURN,
+                MB_ICONWARNING, SB_BOTTOM, WINDOW_EX_STYLE, WINDOW_STYLE, WM_VSCROLL, WS_BORDER,
+                WS_CHILD, WS_HSCROLL, WS_TABSTOP, WS_VISIBLE, WS_VSCROLL,
+            },
+        },
+    },
+};
+
+const FALLBACK_WARNING: PCWSTR = w!(
+    "The RichEdit control (Msftedit.dll) could not be created.\nA plain text box is used instead."
+);
+
+static TEXT_VIEW: OnceLock<TextView> = OnceLock::new();
+// where the text appended last starts and ends, for `replace_last`.
+static LAST_APPENDED: Mutex<Option<(usize, usize)>> = Mutex::new(None);
+
+/// The control showing the recognized text. A plain EDIT control stands in when
+/// the RichEdit library is unavailable; it has no mouse event notifications, so the
+/// custom context menu is replaced by the control's built-in one.
+pub enum TextView {
+    RichEdit(Hwnd),
+    Edit(Hwnd),
+}
+
+impl TextView {
+    pub fn handle(&self) -> HWND {
+        match self {
+            Self::RichEdit(hwnd) | Self::Edit(hwnd) => hwnd.handle(),
+        }
+    }
+
+    /// Returns the selected text. Line breaks are CRs in the RichEdit and CRLFs in the
+    /// EDIT control.
+    pub fn selection(&self) -> String {
+        let hwnd = self.handle();
+        let (mut start, mut end) = (0u32, 0u32);
+        unsafe {
+            SendMessageW(
+                hwnd,
+                EM_GETSEL,
+                WPARAM(&mut start as *mut _ as _),
+                LPARAM(&mut end as *mut _ as _),
+            )
+        };
+        let (start, end) = (start as usize, end as usize);
+        match self {
+            Self::RichEdit(_) => {
+                let mut buf = vec![0u16; end.saturating_sub(start) + 1];
+                let len = unsafe {
+                    SendMessageW(hwnd, EM_GETSELTEXT, None, LPARAM(buf.as_mut_ptr() as _)).0
+                        as usize
+                };
+                String::from_utf16_lossy(&buf[..len.min(buf.len() - 1)])
+            }
+            Self::Edit(_) => {
+                let mut buf = vec![0u16; unsafe { GetWindowTextLengthW(hwnd) } as usize + 1];
+                let len = unsafe { GetWindowTextW(hwnd, &mut buf) } as usize;
+                String::from_utf16_lossy(buf[..len].get(start..end).unwrap_or_default())
+            }
+        }
+    }
+
+    /// Appends `text` at the end and scrolls to it.
+    pub fn append(&self, text: &str) {
+        let len = self.len();
+        self.insert(len, len, text);
+    }
+
+    /// Replaces the text appended last with `text`, or appends it if there is none.
+    pub fn replace_last(&self, text: &str) {
+        let len = self.len();
+        // clamped, the text may have been edited since.
+        let (start, end) = last_appended().unwrap_or((len, len));
+        self.insert(start.min(len), end.min(len), text);
+    }
+
+    /// Replaces the characters from `start` to `end` with `text`, as the text appended
+    /// last, and scrolls to the end.
+    fn insert(&self, start: usize, end: usize, text: &str) {
+        let hwnd = self.handle();
+        unsafe { SendMessageW(hwnd, EM_SETSEL, WPARAM(start), LPARAM(end as isize)) };
+
+        // insert the text at the new caret position
+        let text = HSTRING::from(text);
+        unsafe {
+            SendMessageW(
+                hwnd,
+                EM_REPLACESEL,
+                WPARAM(1),
+                LPARAM(text.as_ptr() as isize),
+            )
+        };
+        // counted the way the control counts, a RichEdit keeps CRLF as a single CR.
+        *last_appended() = Some((start, self.len()));
+
+        // scroll to the end
+        unsafe { SendMessageW(hwnd, WM_VSCROLL, WPARAM(SB_BOTTOM.0 as _), None) };
+    }
+
+    // the length of the text, in the positions EM_SETSEL takes.
+    fn len(&self) -> usize {
+        let hwnd = self.handle();
+        match self {
+            Self::RichEdit(_) => {
+                let len = GETTEXTLENGTHEX {
+                    flags: GTL_DEFAULT,
+                    codepage: 1200,
+                };
+                unsafe {
+                    SendMessageW(
+                        hwnd,
+                        EM_GETTEXTLENGTHEX,
+                        WPARAM(&len as *const _ as _),
+                        None,
+                    )
+                    .0 as usize
+                }
+            }
+            Self::Edit(_) => unsafe { GetWindowTextLengthW(hwnd) as usize },
+        }
+    }
+}
+
+fn last_appended() -> MutexGuard<'static, Option<(usize, usize)>> {
+    LAST_APPENDED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn get() -> Result<&'static TextView> {
+    TEXT_VIEW.get().context("no text view.")
+}
+
+/// Creates the text view below the combobox, falling back to a plain EDIT control
+/// with a one-time warning if the RichEdit can't be created.
+pub fn create(parent: HWND) -> Result<()> {
+    let mut rc = RECT::default();
+    unsafe { GetClientRect(parent, &mut rc)? };
+    rc.top = 30;
+    rc.bottom -= status::height();
+
+    let view = match create_richedit(parent, &rc) {
+        Ok(hwnd) => TextView::RichEdit(Hwnd::new(hwnd)),
+        Err(e) => {
+            log!("failed to create the richedit, falling back to edit: {e}");
+            let hwnd = create_edit(parent, &rc)?;
+            unsafe { MessageBoxW(parent, FALLBACK_WARNING, None, MB_ICONWARNING) };
+            TextView::Edit(Hwnd::new(hwnd))
+        }
+    };
+    TEXT_VIEW.get_or_init(|| view);
+    Ok(())
+}
+
+fn style() -> WINDOW_STYLE {
+    WINDOW_STYLE((ES_MULTILINE | ES_WANTRETURN | ES_AUTOHSCROLL | ES_AUTOVSCROLL) as _)
+        | WS_VISIBLE
+        | WS_CHILD
+        | WS_BORDER
+        | WS_TABSTOP
+        | WS_VSCROLL
+        | WS_HSCROLL
+}
+
+fn create_richedit(parent: HWND, rc: &RECT) -> Result<HWND> {
+    unsafe { LoadLibraryW(h!("Msftedit.dll"))? };
+
+    let hwnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            MSFTEDIT_CLASS,
+            None,
+            style(),
+            rc.left,
+            rc.top,
+            rc.right - rc.left,
+            rc.bottom - rc.top,
+            parent,
+            None,
+            GetModuleHandleW(None)?,
+            None,
+        )?
+    };
+
+    let result = unsafe { SendMessageW(hwnd, EM_GETEVENTMASK, None, None) };
+    let event = result.0 | ENM_MOUSEEVENTS as isize;
+    unsafe { SendMessageW(hwnd, EM_SETEVENTMASK, None, LPARAM(event)) };
+
+    Ok(hwnd)
+}
+
+fn create_edit(parent: HWND, rc: &RECT) -> Result<HWND> {
+    let hwnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            WC_EDITW,
+            None,
+            style(),
+            rc.left,
+            rc.top,
+            rc.right - rc.left,
+            rc.bottom - rc.top,
+            parent,
+            None,
+            GetModuleHandleW(None)?,
+            None,
+        )?
+    };
+
+    // lift the default 32K character limit.
+    unsafe { SendMessageW(hwnd, EM_SETLIMITTEXT, None, None) };
+
+    Ok(hwnd)
+}
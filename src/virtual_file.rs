@@ -0,0 +1,207 @@
+//! Virtual files, as offered by Outlook for copied attachments: a FileGroupDescriptorW
+//! naming the files and a FileContents format per file, with no bitmap format at all.
+//! FileContents of any file but the first is only reachable through OLE.
+
+use super::{clipboard, reference};
+use anyhow::{anyhow, ensure, Context, Result};
+use std::path::Path;
+use std::ptr;
+use windows::Win32::{
+    System::{
+        Com::{
+            IDataObject, IStream, DVASPECT_CONTENT, FORMATETC, STGMEDIUM, STREAM_SEEK_SET,
+            TYMED_HGLOBAL, TYMED_ISTREAM,
+        },
+        DataExchange::{IsClipboardFormatAvailable, RegisterClipboardFormatW},
+        Ole::{OleGetClipboard, OleInitialize, OleUninitialize, ReleaseStgMedium, CF_UNICODETEXT},
+    },
+    UI::Shell::{CFSTR_FILECONTENTS, CFSTR_FILEDESCRIPTORW, FD_FILESIZE, FILEDESCRIPTORW},
+};
+
+/// Upper bound of a virtual file read into memory.
+const MAX_FILE_BYTES: usize = 64 << 20;
+
+struct Ole;
+impl Drop for Ole {
+    fn drop(&mut self) {
+        unsafe { OleUninitialize() };
+    }
+}
+
+struct Medium(STGMEDIUM);
+impl Drop for Medium {
+    fn drop(&mut self) {
+        unsafe { ReleaseStgMedium(&mut self.0) };
+    }
+}
+
+fn descriptor_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(CFSTR_FILEDESCRIPTORW) }
+}
+
+pub fn contents_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(CFSTR_FILECONTENTS) }
+}
+
+/// True if the clipboard holds virtual files. Copied messages come with their text,
+/// copied attachments don't.
+pub fn is_available() -> bool {
+    unsafe {
+        IsClipboardFormatAvailable(descriptor_format()).is_ok()
+            && IsClipboardFormatAvailable(CF_UNICODETEXT.0 as u32).is_err()
+    }
+}
+
+/// Reads the first virtual file with an image extension, None if none of them has one.
+/// The others are ignored.
+pub fn read() -> Result<Option<Vec<u8>>> {
+    unsafe { OleInitialize(None).context("failed to initialize OLE.")? };
+    let _ole = Ole;
+    let data = unsafe { OleGetClipboard()? };
+
+    let descriptor = get_data(&data, descriptor_format(), -1, TYMED_HGLOBAL.0)?;
+    let descriptor = clipboard::copy_global(unsafe { descriptor.0.u.hGlobal })?;
+    let Some((index, size)) = first_image(&descriptor)? else {
+        return Ok(None);
+    };
+    if let Some(size) = size {
+        ensure!(
+            size <= MAX_FILE_BYTES as u64,
+            "the copied file is larger than {} MB.",
+            MAX_FILE_BYTES >> 20
+        );
+    }
+    let size = size.map(|s| s as usize);
+
+    let contents = get_data(
+        &data,
+        contents_format(),
+        index as i32,
+        TYMED_HGLOBAL.0 | TYMED_ISTREAM.0,
+    )?;
+    let bytes = match contents.0.tymed as i32 {
+        t if t == TYMED_HGLOBAL.0 => {
+            // the block may be rounded up past the end of the file.
+            let mut bytes = clipboard::copy_global(unsafe { contents.0.u.hGlobal })?;
+            bytes.truncate(size.unwrap_or(bytes.len()));
+            bytes
+        }
+        t if t == TYMED_ISTREAM.0 => {
+            let stream = unsafe { contents.0.u.pstm.as_ref() }.context(c!())?;
+            read_stream(stream, size)?
+        }
+        t => {
+            return Err(anyhow!(
+                "unexpected storage medium {t} for the copied file."
+            ))
+        }
+    };
+    Ok(Some(bytes))
+}
+
+fn get_data(data: &IDataObject, format: u32, index: i32, tymed: i32) -> Result<Medium> {
+    let format = FORMATETC {
+        cfFormat: format as u16,
+        ptd: ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex: index,
+        tymed: tymed as u32,
+    };
+    let medium = unsafe { data.GetData(&format)? };
+    Ok(Medium(medium))
+}
+
+fn read_stream(stream: &IStream, size: Option<usize>) -> Result<Vec<u8>> {
+    // some producers hand the stream over positioned at its end.
+    unsafe { stream.Seek(0, STREAM_SEEK_SET, None).ok() };
+
+    let mut bytes = Vec::with_capacity(size.unwrap_or(0));
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let mut read = 0;
+        unsafe { stream.Read(chunk.as_mut_ptr() as _, chunk.len() as u32, Some(&mut read)) }
+            .ok()?;
+        if read == 0 {
+            break;
+        }
+        ensure!(
+            bytes.len() + read as usize <= MAX_FILE_BYTES,
+            "the copied file is larger than {} MB.",
+            MAX_FILE_BYTES >> 20
+        );
+        bytes.extend_from_slice(&chunk[..read as usize]);
+    }
+    Ok(bytes)
+}
+
+/// Finds the first file with an image extension in a FILEGROUPDESCRIPTORW, returning its
+/// index and its size if the descriptor has one.
+fn first_image(descriptor: &[u8]) -> Result<Option<(usize, Option<u64>)>> {
+    let count = descriptor
+        .get(..4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .context("truncated file group descriptor.")?;
+    let entry = size_of::<FILEDESCRIPTORW>();
+    for i in 0..count as usize {
+        let offset = 4 + i * entry;
+        let bytes = descriptor
+            .get(offset..offset + entry)
+            .context("truncated file group descriptor.")?;
+        let file = unsafe { ptr::read_unaligned(bytes.as_ptr() as *const FILEDESCRIPTORW) };
+
+        let name = file.cFileName;
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        let name = String::from_utf16_lossy(&name[..len]);
+        if reference::has_image_extension(Path::new(&name)) {
+            let size = (file.dwFlags & FD_FILESIZE.0 as u32 != 0)
+                .then_some((file.nFileSizeHigh as u64) << 32 | file.nFileSizeLow as u64);
+            return Ok(Some((i, size)));
+        }
+    }
+    Ok(None)
+}
+
+#[test]
+fn first_image_test() {
+    let file = |name: &str, size: Option<u64>| {
+        let mut file = FILEDESCRIPTORW::default();
+        let mut file_name = [0u16; 260];
+        for (dst, src) in file_name.iter_mut().zip(name.encode_utf16()) {
+            *dst = src;
+        }
+        file.cFileName = file_name;
+        if let Some(size) = size {
+            file.dwFlags = FD_FILESIZE.0 as u32;
+            file.nFileSizeHigh = (size >> 32) as u32;
+            file.nFileSizeLow = size as u32;
+        }
+        unsafe { std::slice::from_raw_parts(&file as *const _ as *const u8, size_of_val(&file)) }
+            .to_vec()
+    };
+    let descriptor = |files: &[Vec<u8>]| {
+        let mut blob = (files.len() as u32).to_le_bytes().to_vec();
+        files.iter().for_each(|f| blob.extend(f));
+        blob
+    };
+
+    let blob = descriptor(&[
+        file("minutes.docx", Some(10)),
+        file("Screenshot 2024-05-01.PNG", Some(0x1_0000_0002)),
+        file("photo.jpg", None),
+    ]);
+    assert_eq!(first_image(&blob).unwrap(), Some((1, Some(0x1_0000_0002))));
+    let blob = descriptor(&[file("photo.jpg", None), file("shot.png", Some(3))]);
+    assert_eq!(first_image(&blob).unwrap(), Some((0, None)));
+
+    assert_eq!(
+        first_image(&descriptor(&[file("notes.txt", None)])).unwrap(),
+        None
+    );
+    assert_eq!(first_image(&descriptor(&[])).unwrap(), None);
+
+    // fewer entries than counted.
+    let mut blob = descriptor(&[file("a.txt", None)]);
+    blob[0] = 2;
+    assert!(first_image(&blob).is_err());
+    assert!(first_image(&[1, 0]).is_err());
+}
@@ -0,0 +1,65 @@
+use super::image::Image;
+use anyhow::{ensure, Result};
+use std::ptr;
+use std::slice;
+use windows::{
+    core::Interface,
+    Graphics::Imaging::{
+        BitmapAlphaMode, BitmapBufferAccessMode, BitmapDecoder, BitmapPixelFormat, SoftwareBitmap,
+    },
+    Storage::Streams::{DataWriter, InMemoryRandomAccessStream},
+    Win32::System::WinRT::IMemoryBufferByteAccess,
+};
+
+/// Decodes an encoded image (BMP, PNG, ...) with the WIC codecs behind `BitmapDecoder`,
+/// keeping straight alpha.
+pub fn decode(bytes: &[u8]) -> Result<Image> {
+    let stream = InMemoryRandomAccessStream::new()?;
+    let writer = DataWriter::CreateDataWriter(&stream)?;
+    writer.WriteBytes(bytes)?;
+    writer.StoreAsync()?.get()?;
+    writer.FlushAsync()?.get()?;
+    writer.DetachStream()?;
+    stream.Seek(0)?;
+
+    let bmp = BitmapDecoder::CreateAsync(&stream)?
+        .get()?
+        .GetSoftwareBitmapConvertedAsync(BitmapPixelFormat::Bgra8, BitmapAlphaMode::Straight)?
+        .get()?;
+    to_image(&bmp)
+}
+
+fn to_image(bmp: &SoftwareBitmap) -> Result<Image> {
+    let width = bmp.PixelWidth()?;
+    let height = bmp.PixelHeight()?;
+
+    let bmp_buf = bmp.LockBuffer(BitmapBufferAccessMode::Read)?;
+    let plane = bmp_buf.GetPlaneDescription(0)?;
+    let array: IMemoryBufferByteAccess = bmp_buf.CreateReference()?.cast()?;
+
+    let mut data = ptr::null_mut();
+    let mut capacity = 0;
+    unsafe { array.GetBuffer(&mut data, &mut capacity)? };
+
+    let row = width as usize * 4;
+    let start = plane.StartIndex as usize;
+    let stride = plane.Stride as usize;
+    ensure!(
+        stride >= row
+            && start + stride * (height as usize).saturating_sub(1) + row <= capacity as usize,
+        "unexpected bitmap layout."
+    );
+
+    let data = unsafe { slice::from_raw_parts(data, capacity as usize) };
+    let bgra = data[start..]
+        .chunks(stride)
+        .take(height as usize)
+        .flat_map(|s| &s[..row])
+        .copied()
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        bgra,
+    })
+}
@@ -0,0 +1,48 @@
+//! The thread recognitions run on, keeping the UI responsive. Jobs run one at a time in
+//! the order they were queued, so their results come back in that order too.
+
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static JOBS: OnceLock<Sender<Job>> = OnceLock::new();
+
+/// Queues `job` to run after the ones already queued, starting the thread if needed.
+pub fn queue(job: impl FnOnce() + Send + 'static) {
+    let jobs = JOBS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || receiver.into_iter().for_each(|job| job()));
+        sender
+    });
+    // the thread lives as long as the process, the receiver with it.
+    jobs.send(Box::new(job)).ok();
+}
+
+#[test]
+fn queue_test() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    // captures in quick succession, some slower than others.
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let (done, finished) = mpsc::channel();
+    for i in 0..50 {
+        let results = results.clone();
+        let done = done.clone();
+        queue(move || {
+            if i % 7 == 0 {
+                thread::sleep(Duration::from_millis(2));
+            }
+            results.lock().unwrap().push(i);
+            done.send(()).unwrap();
+        });
+    }
+    for _ in 0..50 {
+        finished.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+    // every one, once, in order.
+    assert_eq!(*results.lock().unwrap(), (0..50).collect::<Vec<_>>());
+    assert!(finished.try_recv().is_err());
+}